@@ -0,0 +1,322 @@
+use crate::errors::CacheError;
+use crate::request_types::CreateCacheRequest;
+use crate::response_types::DumpEntry;
+use actix_web::error::PayloadError;
+use actix_web::web::Bytes;
+use base64::prelude::*;
+use futures_util::stream::{LocalBoxStream, StreamExt};
+use serde::{Deserialize, Serialize};
+
+// Tar archives holding a full export: `manifest.json` first, then the NDJSON entries of
+// each cache. A cache's entries may be split over several members at the same path, in
+// order, so neither side ever holds more than one member. Members are framed by hand so
+// the archive can be written and read as a stream rather than built in memory.
+
+pub const MANIFEST_PATH: &str = "manifest.json";
+
+const BLOCK_SIZE: usize = 512;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Manifest {
+    pub caches: Vec<ManifestCache>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ManifestCache {
+    #[serde(flatten)]
+    pub config: CreateCacheRequest,
+    // Path of the cache's entries within the archive.
+    pub file: String,
+}
+
+pub fn cache_path(index: usize) -> String {
+    format!("caches/{}.ndjson", index)
+}
+
+// One archive member: a ustar header, the contents, and padding to a whole block.
+pub fn file(path: &str, contents: &[u8]) -> Result<Bytes, CacheError> {
+    let mut header = tar::Header::new_ustar();
+    header.set_path(path).map_err(|_| CacheError::Internal)?;
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(0);
+    header.set_cksum();
+
+    let padding = (BLOCK_SIZE - contents.len() % BLOCK_SIZE) % BLOCK_SIZE;
+    let mut out = Vec::with_capacity(BLOCK_SIZE + contents.len() + padding);
+    out.extend_from_slice(header.as_bytes());
+    out.extend_from_slice(contents);
+    out.resize(out.len() + padding, 0);
+    Ok(Bytes::from(out))
+}
+
+// The two empty blocks that close an archive.
+pub fn end() -> Bytes {
+    Bytes::from(vec![0; 2 * BLOCK_SIZE])
+}
+
+// The header of an archive member whose contents are next in the stream.
+pub struct Member {
+    pub path: String,
+    pub size: u64,
+}
+
+// Reads an archive produced by an export as it arrives, one member at a time. After
+// `next_member`, the member's contents must be consumed with `contents`, `lines` or
+// `skip` before asking for the next one.
+pub struct Reader {
+    stream: LocalBoxStream<'static, Result<Bytes, PayloadError>>,
+    // Bytes received but not yet consumed.
+    pending: Bytes,
+    received: u64,
+    // Most bytes the stream may deliver before the archive is refused as too large.
+    limit: u64,
+}
+
+impl Reader {
+    pub fn new(stream: LocalBoxStream<'static, Result<Bytes, PayloadError>>, limit: u64) -> Self {
+        Reader {
+            stream,
+            pending: Bytes::new(),
+            received: 0,
+            limit,
+        }
+    }
+
+    // The manifest, which must be the first member.
+    pub async fn manifest(&mut self) -> Result<Manifest, CacheError> {
+        match self.next_member().await? {
+            Some(member) if member.path == MANIFEST_PATH => {
+                let contents = self.contents(&member).await?;
+                serde_json::from_slice(&contents).map_err(invalid)
+            }
+            _ => Err(invalid(format!("{} must come first", MANIFEST_PATH))),
+        }
+    }
+
+    // The next regular file in the archive, or None at its end. Other member types, such
+    // as directories, are skipped.
+    pub async fn next_member(&mut self) -> Result<Option<Member>, CacheError> {
+        loop {
+            let mut block = Vec::with_capacity(BLOCK_SIZE);
+            while block.len() < BLOCK_SIZE {
+                // Like tar itself, accept an archive that stops without its end blocks.
+                if block.is_empty() && !self.fill().await? {
+                    return Ok(None);
+                }
+                block.extend_from_slice(&self.take((BLOCK_SIZE - block.len()) as u64).await?);
+            }
+            if block.iter().all(|b| *b == 0) {
+                return Ok(None);
+            }
+            let header = tar::Header::from_byte_slice(&block);
+            if header.cksum().map_err(invalid)? != checksum(&block) {
+                return Err(invalid("bad header checksum"));
+            }
+            let size = header.entry_size().map_err(invalid)?;
+            if !header.entry_type().is_file() {
+                self.discard(padded(size)).await?;
+                continue;
+            }
+            let path = header
+                .path()
+                .map_err(invalid)?
+                .to_string_lossy()
+                .into_owned();
+            return Ok(Some(Member { path, size }));
+        }
+    }
+
+    // The member's whole contents.
+    pub async fn contents(&mut self, member: &Member) -> Result<Vec<u8>, CacheError> {
+        let mut contents = Vec::new();
+        while (contents.len() as u64) < member.size {
+            let chunk = self.take(member.size - contents.len() as u64).await?;
+            contents.extend_from_slice(&chunk);
+        }
+        self.discard(padded(member.size) - member.size).await?;
+        Ok(contents)
+    }
+
+    // Call `f` with each non-empty line of the member as it arrives, holding no more than
+    // one line at a time.
+    pub async fn lines(
+        &mut self,
+        member: &Member,
+        mut f: impl FnMut(&[u8]) -> Result<(), CacheError>,
+    ) -> Result<(), CacheError> {
+        let mut remaining = member.size;
+        let mut line = Vec::new();
+        while remaining > 0 {
+            let chunk = self.take(remaining).await?;
+            remaining -= chunk.len() as u64;
+            let mut rest = &chunk[..];
+            while let Some(end) = rest.iter().position(|b| *b == b'\n') {
+                line.extend_from_slice(&rest[..end]);
+                if !line.is_empty() {
+                    f(&line)?;
+                    line.clear();
+                }
+                rest = &rest[end + 1..];
+            }
+            line.extend_from_slice(rest);
+        }
+        if !line.is_empty() {
+            f(&line)?;
+        }
+        self.discard(padded(member.size) - member.size).await
+    }
+
+    pub async fn skip(&mut self, member: &Member) -> Result<(), CacheError> {
+        self.discard(padded(member.size)).await
+    }
+
+    async fn discard(&mut self, mut count: u64) -> Result<(), CacheError> {
+        while count > 0 {
+            count -= self.take(count).await?.len() as u64;
+        }
+        Ok(())
+    }
+
+    // Up to `max` of the bytes received so far, waiting for more if there are none.
+    async fn take(&mut self, max: u64) -> Result<Bytes, CacheError> {
+        if !self.fill().await? {
+            return Err(invalid("unexpected end of archive"));
+        }
+        let count = (self.pending.len() as u64).min(max) as usize;
+        Ok(self.pending.split_to(count))
+    }
+
+    // Wait until there are bytes to consume, returning false at the end of the stream.
+    async fn fill(&mut self) -> Result<bool, CacheError> {
+        while self.pending.is_empty() {
+            let Some(chunk) = self.stream.next().await else {
+                return Ok(false);
+            };
+            let chunk = chunk.map_err(|e| CacheError::BadRequest(e.to_string()))?;
+            self.received += chunk.len() as u64;
+            if self.received > self.limit {
+                return Err(CacheError::PayloadTooLarge);
+            }
+            self.pending = chunk;
+        }
+        Ok(true)
+    }
+}
+
+// Decode one NDJSON line of a cache's entries.
+pub fn parse_line(line: &[u8]) -> Result<(String, Vec<u8>), CacheError> {
+    let entry: DumpEntry = serde_json::from_slice(line).map_err(invalid)?;
+    let value = BASE64_STANDARD.decode(entry.value).map_err(invalid)?;
+    Ok((entry.key, value))
+}
+
+// A member's size rounded up to whole blocks.
+fn padded(size: u64) -> u64 {
+    size.div_ceil(BLOCK_SIZE as u64) * BLOCK_SIZE as u64
+}
+
+// The header checksum: the sum of its bytes, with the checksum field read as spaces.
+fn checksum(block: &[u8]) -> u32 {
+    block
+        .iter()
+        .enumerate()
+        .map(|(i, b)| match i {
+            148..156 => u32::from(b' '),
+            _ => u32::from(*b),
+        })
+        .sum()
+}
+
+pub fn invalid(reason: impl std::fmt::Display) -> CacheError {
+    CacheError::Unprocessable(format!("invalid archive: {}", reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    // A reader fed `archive` a few bytes at a time, to exercise reads that straddle chunks.
+    fn reader(archive: Vec<u8>, limit: u64) -> Reader {
+        let chunks: Vec<Result<Bytes, PayloadError>> = archive
+            .chunks(7)
+            .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+            .collect();
+        Reader::new(futures_util::stream::iter(chunks).boxed_local(), limit)
+    }
+
+    #[actix_web::test]
+    async fn test_manifest_must_come_first() {
+        let mut archive = file("caches/0.ndjson", b"").unwrap().to_vec();
+        archive.extend_from_slice(&file(MANIFEST_PATH, br#"{"caches": []}"#).unwrap());
+        archive.extend_from_slice(&end());
+        assert!(matches!(
+            reader(archive, u64::MAX).manifest().await,
+            Err(CacheError::Unprocessable(_))
+        ));
+    }
+
+    #[actix_web::test]
+    async fn test_reader_lines() {
+        let mut archive = file(MANIFEST_PATH, br#"{"caches": []}"#).unwrap().to_vec();
+        archive.extend_from_slice(&file("a", b"one\n\ntwo\nthree").unwrap());
+        archive.extend_from_slice(&file("b", b"skipped\n").unwrap());
+        archive.extend_from_slice(&file("a", b"four\n").unwrap());
+        archive.extend_from_slice(&end());
+
+        let mut reader = reader(archive.clone(), u64::MAX);
+        assert!(reader.manifest().await.unwrap().caches.is_empty());
+        let mut lines = Vec::new();
+        while let Some(member) = reader.next_member().await.unwrap() {
+            match member.path.as_str() {
+                "a" => reader
+                    .lines(&member, |line| {
+                        lines.push(String::from_utf8(line.to_vec()).unwrap());
+                        Ok(())
+                    })
+                    .await
+                    .unwrap(),
+                _ => reader.skip(&member).await.unwrap(),
+            }
+        }
+        assert_eq!(lines, vec!["one", "two", "three", "four"]);
+
+        // Cut off part way through the last member.
+        let mut reader = self::reader(archive.clone(), (archive.len() - 3 * BLOCK_SIZE) as u64);
+        reader.manifest().await.unwrap();
+        let mut result = Ok(());
+        while result.is_ok() {
+            result = match reader.next_member().await {
+                Ok(Some(member)) => reader.skip(&member).await,
+                Ok(None) => break,
+                Err(e) => Err(e),
+            };
+        }
+        assert!(matches!(result, Err(CacheError::PayloadTooLarge)));
+
+        // A corrupted header is caught by its checksum.
+        let mut corrupt = archive;
+        corrupt[BLOCK_SIZE * 2 + 1] ^= 1;
+        let mut reader = self::reader(corrupt, u64::MAX);
+        reader.manifest().await.unwrap();
+        assert!(matches!(
+            reader.next_member().await,
+            Err(CacheError::Unprocessable(_))
+        ));
+    }
+
+    #[test]
+    fn test_file_padding() {
+        let member = file("a", b"hello").unwrap();
+        assert_eq!(member.len(), 2 * BLOCK_SIZE);
+
+        let mut archive = member.to_vec();
+        archive.extend_from_slice(&end());
+        let mut archive = tar::Archive::new(archive.as_slice());
+        let mut entry = archive.entries().unwrap().next().unwrap().unwrap();
+        let mut contents = String::new();
+        entry.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "hello");
+    }
+}
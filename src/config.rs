@@ -0,0 +1,298 @@
+use crate::state::{SharedClock, SystemClock};
+use std::env;
+use std::str::FromStr;
+use std::sync::Arc;
+
+// Server configuration, resolved from `CACHERS_*` environment variables.
+#[derive(Debug, Clone)]
+pub struct Config {
+    // TCP address, or `unix:<path>` to listen on a Unix domain socket.
+    pub bind: String,
+    // Separate TCP address for /admin/*, /debug/*, /metrics and /healthz; they're served
+    // on `bind` alongside the data routes if unset.
+    pub admin_bind: Option<String>,
+    // Utilization (size / capacity) at which a cache is reported as near full.
+    pub near_full_threshold: f64,
+    // Largest request body accepted, measured after any Content-Encoding is decoded.
+    pub max_body_bytes: usize,
+    // Largest archive accepted by /admin/import, measured the same way. Archives are read
+    // as they arrive, so this isn't held in memory at once.
+    pub max_import_bytes: u64,
+    // How often caches with a soft_capacity are trimmed back down to it.
+    pub trim_interval_ms: u64,
+    // Bytes of keys and values the server may hold across all caches; unlimited if unset.
+    pub memory_budget_bytes: Option<u64>,
+    // Keep a copy of every value outside the caches so it can be peeked at; doubles memory use.
+    pub shadow_values: bool,
+    // Value for the `Server` response header; empty to remove it, unset to leave it alone.
+    pub server_header: Option<String>,
+    // Bearer token required by the /admin endpoints; they are disabled if unset.
+    pub admin_token: Option<String>,
+    // JSON file describing caches and entries to create before the server starts.
+    pub seed_file: Option<String>,
+    // JSON file listing caches that must exist; applied at startup and by /admin/reload-manifest.
+    pub manifest_file: Option<String>,
+    // Cache type used when a create request doesn't name one.
+    pub default_cache_type: Option<String>,
+    // Peer base URLs (comma-separated in the environment) that /route assigns keys to.
+    pub peers: Vec<String>,
+    // Requests on a cache taking at least this many milliseconds are logged; off if unset.
+    pub slow_ms: Option<u64>,
+    // Directory for values over a cache's spill_threshold_bytes.
+    pub spill_dir: String,
+    // Reject unrecognised parameters on endpoints that take a query string, rather than
+    // ignoring them.
+    pub strict_query: bool,
+    // Base64-encoded 256-bit AES key for caches created with `encrypted`.
+    pub encryption_key: Option<String>,
+    // Seconds an idle keep-alive connection is held open; 0 closes after each response.
+    pub keep_alive_secs: u64,
+    // Milliseconds a client has to send a request's headers; 0 waits indefinitely.
+    pub client_timeout_ms: u64,
+    // Connections each worker holds open before it stops accepting new ones.
+    pub max_connections: usize,
+    // Prometheus Pushgateway base URL that metrics are pushed to; off if unset.
+    pub pushgateway: Option<String>,
+    // How often metrics are pushed to the Pushgateway.
+    pub push_interval_ms: u64,
+    // Bytes of NDJSON gathered into each chunk of a dump response.
+    pub dump_chunk_bytes: usize,
+    // Hosts (comma-separated in the environment) a cache's stats_webhook may target. When
+    // empty, any host is allowed except loopback and link-local ones.
+    pub webhook_allowlist: Vec<String>,
+    // Source of the current time; only ever replaced in tests.
+    pub clock: SharedClock,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bind: "127.0.0.1:8080".to_string(),
+            admin_bind: None,
+            near_full_threshold: 0.9,
+            max_body_bytes: 256 * 1024,
+            max_import_bytes: 1024 * 1024 * 1024,
+            trim_interval_ms: 100,
+            memory_budget_bytes: None,
+            shadow_values: false,
+            server_header: None,
+            admin_token: None,
+            seed_file: None,
+            manifest_file: None,
+            default_cache_type: None,
+            peers: Vec::new(),
+            slow_ms: None,
+            spill_dir: std::env::temp_dir()
+                .join("cachers-spill")
+                .to_string_lossy()
+                .into_owned(),
+            strict_query: false,
+            encryption_key: None,
+            keep_alive_secs: 5,
+            client_timeout_ms: 5000,
+            // actix-web's own default.
+            max_connections: 25_000,
+            pushgateway: None,
+            push_interval_ms: 15_000,
+            dump_chunk_bytes: 64 * 1024,
+            webhook_allowlist: Vec::new(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        let defaults = Config::default();
+        Config {
+            bind: env_or("CACHERS_BIND", defaults.bind),
+            admin_bind: env::var("CACHERS_ADMIN_BIND").ok(),
+            near_full_threshold: env_or(
+                "CACHERS_NEAR_FULL_THRESHOLD",
+                defaults.near_full_threshold,
+            ),
+            max_body_bytes: env_or("CACHERS_MAX_BODY_BYTES", defaults.max_body_bytes),
+            max_import_bytes: env_or("CACHERS_MAX_IMPORT_BYTES", defaults.max_import_bytes),
+            trim_interval_ms: env_or("CACHERS_TRIM_INTERVAL_MS", defaults.trim_interval_ms),
+            memory_budget_bytes: env::var("CACHERS_MEMORY_BUDGET_BYTES")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            shadow_values: env_or("CACHERS_SHADOW_VALUES", defaults.shadow_values),
+            server_header: env::var("CACHERS_SERVER_HEADER").ok(),
+            admin_token: env::var("CACHERS_ADMIN_TOKEN").ok(),
+            seed_file: env::var("CACHERS_SEED_FILE").ok(),
+            manifest_file: env::var("CACHERS_MANIFEST").ok(),
+            default_cache_type: env::var("CACHERS_DEFAULT_CACHE_TYPE").ok(),
+            peers: list("CACHERS_PEERS"),
+            slow_ms: env::var("CACHERS_SLOW_MS")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            spill_dir: env_or("CACHERS_SPILL_DIR", defaults.spill_dir),
+            strict_query: matches!(
+                env::var("CACHERS_STRICT_QUERY").as_deref(),
+                Ok("1" | "true")
+            ),
+            encryption_key: env::var("CACHERS_ENCRYPTION_KEY").ok(),
+            keep_alive_secs: env_or("CACHERS_KEEP_ALIVE", defaults.keep_alive_secs),
+            client_timeout_ms: env_or("CACHERS_CLIENT_TIMEOUT", defaults.client_timeout_ms),
+            max_connections: positive_or(
+                "CACHERS_MAX_CONN",
+                env::var("CACHERS_MAX_CONN").ok().as_deref(),
+                defaults.max_connections,
+            ),
+            pushgateway: env::var("CACHERS_PUSHGATEWAY").ok(),
+            push_interval_ms: env_or("CACHERS_PUSH_INTERVAL_MS", defaults.push_interval_ms),
+            dump_chunk_bytes: env_or("CACHERS_DUMP_CHUNK_BYTES", defaults.dump_chunk_bytes),
+            webhook_allowlist: list("CACHERS_WEBHOOK_ALLOWLIST"),
+            clock: defaults.clock,
+        }
+    }
+
+    // One line of `key=value` pairs describing the effective settings, for the startup log.
+    // Secrets are never included, only whether admin auth and encryption are available.
+    pub fn summary(&self) -> String {
+        let or_none = |v: &Option<String>| v.clone().unwrap_or_else(|| "none".to_string());
+        // HttpServer starts one worker per available CPU unless told otherwise.
+        let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+        format!(
+            "bind={} admin_bind={} workers={} max_connections={} keep_alive_secs={} \
+             client_timeout_ms={} \
+             max_body_bytes={} max_import_bytes={} memory_budget_bytes={} dump_chunk_bytes={} \
+             near_full_threshold={} trim_interval_ms={} slow_ms={} shadow_values={} \
+             admin_auth={} server_header={} seed_file={} manifest_file={} \
+             default_cache_type={} peers={} spill_dir={} strict_query={} \
+             encryption={} pushgateway={} push_interval_ms={} webhook_allowlist={}",
+            self.bind,
+            or_none(&self.admin_bind),
+            workers,
+            self.max_connections,
+            self.keep_alive_secs,
+            self.client_timeout_ms,
+            self.max_body_bytes,
+            self.max_import_bytes,
+            or_none(&self.memory_budget_bytes.map(|v| v.to_string())),
+            self.dump_chunk_bytes,
+            self.near_full_threshold,
+            self.trim_interval_ms,
+            or_none(&self.slow_ms.map(|v| v.to_string())),
+            self.shadow_values,
+            if self.admin_token.is_some() {
+                "on"
+            } else {
+                "off"
+            },
+            or_none(&self.server_header),
+            or_none(&self.seed_file),
+            or_none(&self.manifest_file),
+            or_none(&self.default_cache_type),
+            if self.peers.is_empty() {
+                "none".to_string()
+            } else {
+                self.peers.join(",")
+            },
+            self.spill_dir,
+            self.strict_query,
+            if self.encryption_key.is_some() {
+                "on"
+            } else {
+                "off"
+            },
+            or_none(&self.pushgateway),
+            self.push_interval_ms,
+            if self.webhook_allowlist.is_empty() {
+                "none".to_string()
+            } else {
+                self.webhook_allowlist.join(",")
+            },
+        )
+    }
+}
+
+// Parse an environment variable, falling back to `default` when unset or invalid.
+fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+// Split a comma-separated environment variable, dropping empty items.
+fn list(name: &str) -> Vec<String> {
+    env::var(name)
+        .map(|v| {
+            v.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Parse a count that must be at least 1. Unlike `env_or`, a value that's set but invalid
+// is logged, since a limit that silently didn't apply is hard to notice.
+fn positive_or(name: &str, value: Option<&str>, default: usize) -> usize {
+    let Some(value) = value else {
+        return default;
+    };
+    match value.trim().parse() {
+        Ok(count) if count > 0 => count,
+        _ => {
+            log::warn!(
+                "ignoring {}={:?}: expected a positive integer; using {}",
+                name,
+                value,
+                default
+            );
+            default
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_positive_or() {
+        assert_eq!(positive_or("N", None, 7), 7);
+        assert_eq!(positive_or("N", Some("1024"), 7), 1024);
+        assert_eq!(positive_or("N", Some(" 12 "), 7), 12);
+        for invalid in ["0", "-5", "lots", ""] {
+            assert_eq!(positive_or("N", Some(invalid), 7), 7, "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_summary() {
+        let config = Config {
+            admin_token: Some("s3cret-token".to_string()),
+            encryption_key: Some("s3cret-key".to_string()),
+            memory_budget_bytes: Some(1024),
+            peers: vec!["http://a:8080".to_string(), "http://b:8080".to_string()],
+            ..Config::default()
+        };
+        let summary = config.summary();
+        assert!(!summary.contains("s3cret"));
+        assert!(!summary.contains('\n'));
+        for field in [
+            "bind=127.0.0.1:8080 ",
+            "admin_bind=none ",
+            "workers=",
+            "max_body_bytes=262144 ",
+            "memory_budget_bytes=1024 ",
+            "admin_auth=on ",
+            "encryption=on",
+            "peers=http://a:8080,http://b:8080 ",
+        ] {
+            assert!(
+                summary.contains(field),
+                "{} missing from {}",
+                field,
+                summary
+            );
+        }
+
+        assert!(Config::default().summary().contains("admin_auth=off "));
+    }
+}
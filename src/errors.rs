@@ -1,28 +1,107 @@
-use actix_web::{HttpResponse, ResponseError};
-use derive_more::Display;
-
-#[derive(Debug, Display)]
-pub enum CacheError {
-    #[display("Cache not found")]
-    CacheNotFound,
-    #[display("Cache already exists")]
-    CacheAlreadyExists,
-    #[display("Unknown cache type")]
-    UnknownCacheType,
-    #[display("Key not found")]
-    KeyNotFound,
-    #[display("Internal error")]
-    Internal,
-}
-
-impl ResponseError for CacheError {
-    fn error_response(&self) -> HttpResponse {
-        match self {
-            CacheError::CacheNotFound => HttpResponse::NotFound().body("Cache not found"),
-            CacheError::CacheAlreadyExists => HttpResponse::Conflict().body("Cache already exists"),
-            CacheError::UnknownCacheType => HttpResponse::BadRequest().body("Unknown cache type"),
-            CacheError::KeyNotFound => HttpResponse::NotFound().body("Key not found"),
-            CacheError::Internal => HttpResponse::InternalServerError().body("Internal error"),
-        }
-    }
-}
+use crate::response_types::ErrorResponse;
+use actix_web::http::{header, StatusCode};
+use actix_web::{HttpResponse, ResponseError};
+use derive_more::Display;
+
+#[derive(Debug, Display)]
+pub enum CacheError {
+    #[display("Cache not found")]
+    CacheNotFound,
+    #[display("Cache already exists")]
+    CacheAlreadyExists,
+    #[display("Unknown cache type")]
+    UnknownCacheType,
+    #[display("Unprocessable request: {_0}")]
+    Unprocessable(String),
+    #[display("Bad request: {_0}")]
+    BadRequest(String),
+    #[display("Key not found")]
+    KeyNotFound,
+    #[display("Invalid key")]
+    InvalidKey,
+    #[display("Cache is read-only")]
+    ReadOnly,
+    #[display("Precondition failed")]
+    PreconditionFailed,
+    #[display("Operation not allowed on this cache")]
+    OperationNotAllowed,
+    #[display("Unauthorized")]
+    Unauthorized,
+    #[display("Values are not shadowed; set CACHERS_SHADOW_VALUES to enable peek")]
+    NotShadowed,
+    #[display("Insufficient storage")]
+    InsufficientStorage,
+    #[display("Request body too large")]
+    PayloadTooLarge,
+    #[display("Server is in maintenance mode")]
+    Maintenance,
+    #[display("Peer request failed: {_0}")]
+    BadGateway(String),
+    #[display("Internal error")]
+    Internal,
+}
+
+impl std::error::Error for CacheError {}
+
+// Seconds clients are told to wait before retrying a write during maintenance.
+const MAINTENANCE_RETRY_AFTER_SECS: u64 = 30;
+
+impl CacheError {
+    // Stable, machine-readable identifier for the error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            CacheError::CacheNotFound => "cache_not_found",
+            CacheError::CacheAlreadyExists => "cache_already_exists",
+            CacheError::UnknownCacheType => "unknown_cache_type",
+            CacheError::Unprocessable(_) => "unprocessable",
+            CacheError::BadRequest(_) => "bad_request",
+            CacheError::KeyNotFound => "key_not_found",
+            CacheError::InvalidKey => "invalid_key",
+            CacheError::ReadOnly => "read_only",
+            CacheError::PreconditionFailed => "precondition_failed",
+            CacheError::OperationNotAllowed => "operation_not_allowed",
+            CacheError::Unauthorized => "unauthorized",
+            CacheError::NotShadowed => "not_shadowed",
+            CacheError::InsufficientStorage => "insufficient_storage",
+            CacheError::PayloadTooLarge => "payload_too_large",
+            CacheError::Maintenance => "maintenance",
+            CacheError::BadGateway(_) => "bad_gateway",
+            CacheError::Internal => "internal",
+        }
+    }
+}
+
+impl ResponseError for CacheError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            CacheError::CacheNotFound => StatusCode::NOT_FOUND,
+            CacheError::CacheAlreadyExists => StatusCode::CONFLICT,
+            CacheError::UnknownCacheType => StatusCode::BAD_REQUEST,
+            CacheError::Unprocessable(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            CacheError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            CacheError::KeyNotFound => StatusCode::NOT_FOUND,
+            CacheError::InvalidKey => StatusCode::BAD_REQUEST,
+            CacheError::ReadOnly => StatusCode::FORBIDDEN,
+            CacheError::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+            CacheError::OperationNotAllowed => StatusCode::FORBIDDEN,
+            CacheError::Unauthorized => StatusCode::UNAUTHORIZED,
+            CacheError::NotShadowed => StatusCode::NOT_IMPLEMENTED,
+            CacheError::InsufficientStorage => StatusCode::INSUFFICIENT_STORAGE,
+            CacheError::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            CacheError::Maintenance => StatusCode::SERVICE_UNAVAILABLE,
+            CacheError::BadGateway(_) => StatusCode::BAD_GATEWAY,
+            CacheError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut resp = HttpResponse::build(self.status_code());
+        if let CacheError::Maintenance = self {
+            resp.insert_header((header::RETRY_AFTER, MAINTENANCE_RETRY_AFTER_SECS));
+        }
+        resp.json(ErrorResponse {
+            code: self.code().to_string(),
+            error: self.to_string(),
+        })
+    }
+}
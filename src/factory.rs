@@ -0,0 +1,221 @@
+use crate::errors::CacheError;
+use crate::request_types::CreateCacheRequest;
+use crate::state::SharedCache;
+use crate::webhook;
+use cachers::{FIFOCache, LRUCache, MRUCache, TTLCache};
+use std::sync::Arc;
+use std::time::Duration;
+
+type CacheBuilder = fn(&CreateCacheRequest) -> Result<SharedCache, CacheError>;
+
+// Every supported cache type and how to construct an empty one. Creates, policy switches
+// and GET /cache/types all go by this list.
+const CACHE_TYPES: &[(&str, CacheBuilder)] = &[
+    ("lru", |req| Ok(Arc::new(LRUCache::new(req.max_capacity())))),
+    ("fifo", |req| {
+        Ok(Arc::new(FIFOCache::new(req.max_capacity())))
+    }),
+    ("mru", |req| Ok(Arc::new(MRUCache::new(req.max_capacity())))),
+    ("ttl", |req| {
+        let settings = req.ttl_settings().ok_or(CacheError::UnknownCacheType)?;
+        // Each key's jittered expiry is picked by the server's key index, which reads check
+        // first. The cache itself only has to hold values until the latest of those.
+        Ok(Arc::new(TTLCache::new(
+            settings.ttl + settings.jitter,
+            settings.check_interval,
+            Duration::ZERO,
+            req.max_capacity(),
+        )))
+    }),
+];
+
+// Names of the supported cache types, in the order they're listed above.
+pub fn cache_types() -> Vec<&'static str> {
+    CACHE_TYPES.iter().map(|(name, _)| *name).collect()
+}
+
+// Construct an empty cache of the requested type.
+pub fn build_cache(req: &CreateCacheRequest) -> Result<SharedCache, CacheError> {
+    let build = match req.cache_type() {
+        "" => {
+            return Err(CacheError::BadRequest(
+                "cache_type is required when no default is configured".to_string(),
+            ))
+        }
+        cache_type => CACHE_TYPES
+            .iter()
+            .find(|(name, _)| *name == cache_type)
+            .map(|(_, build)| build)
+            .ok_or(CacheError::UnknownCacheType)?,
+    };
+    validate_create(req, req.cache_type() == "ttl")?;
+    check_capacity(req.max_capacity(), usize::MAX as u64)?;
+    build(req)
+}
+
+// Caches hold at most `usize::MAX` entries, which on 32-bit targets is less than a
+// capacity can ask for. Takes the limit as an argument so it can be tested anywhere.
+fn check_capacity(capacity: u64, limit: u64) -> Result<(), CacheError> {
+    match capacity > limit {
+        true => Err(CacheError::BadRequest(format!(
+            "capacity {} exceeds the largest supported on this platform ({})",
+            capacity, limit
+        ))),
+        false => Ok(()),
+    }
+}
+
+// Reject well-formed create requests whose options don't make sense together.
+fn validate_create(req: &CreateCacheRequest, is_ttl: bool) -> Result<(), CacheError> {
+    let unprocessable = |msg: &str| Err(CacheError::Unprocessable(msg.to_string()));
+    match (req.soft_capacity, req.hard_capacity) {
+        (None, None) if req.capacity == 0 => {
+            return unprocessable("capacity must be greater than 0");
+        }
+        (None, None) => {}
+        (Some(_), Some(_)) if req.capacity != 0 => {
+            return unprocessable(
+                "capacity can't be combined with soft_capacity and hard_capacity",
+            );
+        }
+        (Some(0), Some(_)) => return unprocessable("soft_capacity must be greater than 0"),
+        (Some(soft), Some(hard)) if soft > hard => {
+            return unprocessable("soft_capacity must not exceed hard_capacity");
+        }
+        (Some(_), Some(_)) => {}
+        _ => return unprocessable("soft_capacity and hard_capacity must be given together"),
+    }
+    if !is_ttl
+        && (req.ttl.is_some()
+            || req.check_interval.is_some()
+            || req.jitter.is_some()
+            || req.sliding)
+    {
+        return unprocessable("ttl, check_interval, jitter and sliding only apply to ttl caches");
+    }
+    let at_least_1ms = |value: Option<f64>| match value {
+        Some(value) => req
+            .ttl_unit
+            .to_duration(value)
+            .is_some_and(|duration| !duration.is_zero()),
+        None => true,
+    };
+    if !at_least_1ms(req.ttl) {
+        return unprocessable("ttl must be at least 1ms");
+    }
+    if !at_least_1ms(req.check_interval) {
+        return unprocessable("check_interval must be at least 1ms");
+    }
+    if req
+        .jitter
+        .is_some_and(|jitter| req.ttl_unit.to_duration(jitter).is_none())
+    {
+        return unprocessable("jitter must not be negative");
+    }
+    if req.allowed_ops.as_ref().is_some_and(Vec::is_empty) {
+        return unprocessable("allowed_ops must not be empty");
+    }
+    if req.idle_key_ttl == Some(0) {
+        return unprocessable("idle_key_ttl must be greater than 0");
+    }
+    if req.write_buffer_ms == Some(0) {
+        return unprocessable("write_buffer_ms must be greater than 0");
+    }
+    // A queued set can't be turned away once the cache fills up.
+    if req.write_buffer_ms.is_some() && req.hard_capacity.is_some() {
+        return unprocessable("write_buffer_ms can't be combined with hard_capacity");
+    }
+    // The URL itself is checked against the server's webhook allowlist in CacheEntry::new.
+    match (&req.stats_webhook, req.stats_webhook_interval_ms) {
+        (None, Some(_)) => {
+            return unprocessable("stats_webhook_interval_ms requires stats_webhook")
+        }
+        (_, Some(ms)) if ms < webhook::MIN_INTERVAL_MS => {
+            return Err(CacheError::Unprocessable(format!(
+                "stats_webhook_interval_ms must be at least {}",
+                webhook::MIN_INTERVAL_MS
+            )));
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(cache_type: &str) -> CreateCacheRequest {
+        CreateCacheRequest {
+            name: "test".to_string(),
+            cache_type: Some(cache_type.to_string()),
+            capacity: 10,
+            ttl: (cache_type == "ttl").then_some(60.0),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_build_each_type() {
+        for cache_type in cache_types() {
+            let cache = build_cache(&request(cache_type)).unwrap();
+            cache.set("key".to_string(), b"value".to_vec());
+            assert_eq!(
+                cache.get(&"key".to_string()).as_deref(),
+                Some(&b"value".to_vec())
+            );
+            assert_eq!(cache.stats().capacity, 10, "{}", cache_type);
+        }
+    }
+
+    #[test]
+    fn test_build_rejects_unknown_and_missing_type() {
+        assert!(matches!(
+            build_cache(&request("lfu")),
+            Err(CacheError::UnknownCacheType)
+        ));
+        assert!(matches!(
+            build_cache(&request("")),
+            Err(CacheError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_build_validates() {
+        let req = CreateCacheRequest {
+            capacity: 0,
+            ..request("lru")
+        };
+        assert!(matches!(
+            build_cache(&req),
+            Err(CacheError::Unprocessable(_))
+        ));
+        let req = CreateCacheRequest {
+            ttl: Some(60.0),
+            ..request("fifo")
+        };
+        assert!(matches!(
+            build_cache(&req),
+            Err(CacheError::Unprocessable(_))
+        ));
+    }
+
+    #[test]
+    fn test_check_capacity() {
+        let limit = u32::MAX as u64;
+        assert!(check_capacity(limit, limit).is_ok());
+        assert!(matches!(
+            check_capacity(limit + 1, limit),
+            Err(CacheError::BadRequest(msg)) if msg.contains("4294967296")
+        ));
+        // Where usize is 32 bits, building a cache applies the same limit.
+        #[cfg(target_pointer_width = "32")]
+        assert!(matches!(
+            build_cache(&CreateCacheRequest {
+                capacity: limit + 1,
+                ..request("lru")
+            }),
+            Err(CacheError::BadRequest(_))
+        ));
+    }
+}
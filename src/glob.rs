@@ -0,0 +1,60 @@
+// Match `text` against a glob `pattern`, where `*` matches any run of
+// characters (including none) and `?` matches exactly one character.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` seen and the text index it is currently matched up to.
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            // Let the last `*` swallow one more character and retry.
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_literal() {
+        assert!(glob_match("users", "users"));
+        assert!(!glob_match("users", "user"));
+        assert!(!glob_match("user", "users"));
+    }
+
+    #[test]
+    fn test_star() {
+        assert!(glob_match("user-*", "user-1"));
+        assert!(glob_match("user-*", "user-"));
+        assert!(glob_match("*-cache", "session-cache"));
+        assert!(glob_match("a*b*c", "aXXbYYc"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("user-*", "session-1"));
+        assert!(!glob_match("a*b*c", "aXXbYY"));
+    }
+
+    #[test]
+    fn test_question_mark() {
+        assert!(glob_match("user-?", "user-1"));
+        assert!(!glob_match("user-?", "user-"));
+        assert!(!glob_match("user-?", "user-10"));
+        assert!(glob_match("?*", "x"));
+        assert!(!glob_match("?*", ""));
+    }
+}
@@ -1,83 +1,521 @@
+use crate::archive;
 use crate::errors::CacheError;
-use crate::request_types::{CreateCacheRequest, DeleteCacheRequest};
-use crate::state::AppState;
-use actix_web::{web, HttpResponse, Responder};
-use cachers::cache::CacheStats;
-use cachers::{Cache, FIFOCache, LRUCache, MRUCache, TTLCache};
+use crate::factory::{build_cache, cache_types};
+use crate::glob::glob_match;
+use crate::manifest;
+use crate::metrics::{format_metrics, CONTENT_TYPE as METRICS_CONTENT_TYPE};
+use crate::request_types::{
+    self, CacheOp, CacheStatsQuery, ChangesQuery, ConfigUpdateRequest, CreateCacheRequest,
+    DeleteCacheRequest, DeleteManyRequest, DrainRequest, GetQuery, HistogramQuery, KeysQuery,
+    MGetQuery, MGetRequest, MSetEntry, MSetRequest, PolicyRequest, ReadOnlyRequest, ReinitRequest,
+    ScanQuery, SetQuery, StatsDeltaQuery, StatsQuery,
+};
+use crate::response_types::{
+    CacheListEntry, CacheStatusEntry, ChangeEntry, ChangesResponse, CompactResponse,
+    ConfigUpdateResponse, CountResponse, CreateCacheResponse, DrainResponse, DumpEntry,
+    HistogramBucket, HistogramResponse, InternalResponse, KeyTtlResponse, ManifestResponse,
+    PingResponse, PolicyResponse, RouteResponse, ScanResponse, StatsDelta, StatsDeltaResponse,
+    StatsResponse, StatusResponse, VerifyResponse,
+};
+use crate::state::{unix_millis, AppState, CacheEntry, FlaggedValue, SetCondition, SetOptions};
+use actix_web::dev::Decompress;
+use actix_web::http::header::{
+    self, AcceptCharset, ContentRange, ContentRangeSpec, Header, HttpDate, IfModifiedSince,
+    LastModified, Quality, Range,
+};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use base64::prelude::*;
+use futures_util::{future, stream, StreamExt};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Under CACHERS_STRICT_QUERY, reject query parameters outside `allowed` so a misspelled
+// one isn't silently ignored.
+fn check_query(state: &AppState, req: &HttpRequest, allowed: &[&str]) -> Result<(), CacheError> {
+    if !state.config.strict_query {
+        return Ok(());
+    }
+    let params = web::Query::<Vec<(String, String)>>::from_query(req.query_string())
+        .map_err(|e| CacheError::BadRequest(e.to_string()))?;
+    let mut unknown: Vec<&str> = params
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .filter(|name| !allowed.contains(name))
+        .collect();
+    unknown.sort_unstable();
+    unknown.dedup();
+    match unknown.is_empty() {
+        true => Ok(()),
+        false => Err(CacheError::BadRequest(format!(
+            "unknown query parameters: {}",
+            unknown.join(", ")
+        ))),
+    }
+}
+
+// Reject keys the server won't store, such as the empty key left by a trailing slash.
+fn validate_key(key: &str) -> Result<(), CacheError> {
+    match key.is_empty() {
+        true => Err(CacheError::InvalidKey),
+        false => Ok(()),
+    }
+}
+
+// Require `Authorization: Bearer <admin token>`. Without a configured token every admin
+// request is refused.
+fn check_admin(state: &AppState, req: &HttpRequest) -> Result<(), CacheError> {
+    let token = state
+        .config
+        .admin_token
+        .as_deref()
+        .ok_or(CacheError::Unauthorized)?;
+    let provided = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match provided == Some(token) {
+        true => Ok(()),
+        false => Err(CacheError::Unauthorized),
+    }
+}
+
+// Parse a `?content_type=` override, accepting only a concrete `type/subtype`.
+fn parse_content_type(value: &str) -> Result<mime::Mime, CacheError> {
+    let invalid = || CacheError::BadRequest(format!("invalid content_type {:?}", value));
+    let mime: mime::Mime = value.parse().map_err(|_| invalid())?;
+    match mime.type_() == mime::STAR || mime.subtype() == mime::STAR {
+        true => Err(invalid()),
+        false => Ok(mime),
+    }
+}
+
+// Whether the client's Accept-Charset admits UTF-8 text, explicitly or via `*`.
+fn accepts_utf8(req: &HttpRequest) -> bool {
+    let Ok(AcceptCharset(charsets)) = AcceptCharset::parse(req) else {
+        return false;
+    };
+    charsets.iter().any(|charset| {
+        let name = charset.item.to_string();
+        charset.quality > Quality::ZERO && (name == "*" || name.eq_ignore_ascii_case("utf-8"))
+    })
+}
 
 // POST /cache/create – Create a new named cache.
 pub async fn create_cache(
     state: web::Data<AppState>,
+    http_req: HttpRequest,
     req: web::Json<CreateCacheRequest>,
 ) -> Result<impl Responder, CacheError> {
-    match state.cache_exists(&req.name) {
-        Ok(_) => return Err(CacheError::CacheAlreadyExists),
-        Err(_) => (),
-    }
-    let cache: Arc<dyn Cache<String, Vec<u8>>> = match req.cache_type.as_str() {
-        "lru" => Arc::new(LRUCache::new(req.capacity)),
-        "fifo" => Arc::new(FIFOCache::new(req.capacity)),
-        "mru" => Arc::new(MRUCache::new(req.capacity)),
-        "ttl" => {
-            let ttl_value = Duration::from_secs(req.ttl.unwrap_or(60));
-            let check_interval_value = Duration::from_secs(req.check_interval.unwrap_or(10));
-            let jitter_value = Duration::from_secs(req.jitter.unwrap_or(0));
-
-            // Assuming your TtlCache has a constructor that accepts these options.
-            Arc::new(TTLCache::new(
-                ttl_value,
-                check_interval_value,
-                jitter_value,
-                req.capacity,
-            ))
-        }
-        _ => return Err(CacheError::UnknownCacheType),
-    };
+    state.check_maintenance()?;
+    // The server calls a stats webhook on its own schedule, so only admins may set one.
+    if req.stats_webhook.is_some() {
+        check_admin(&state, &http_req)?;
+    }
+    let req = state.with_defaults(req.into_inner());
+    if state.cache_exists(&req.name).is_ok() {
+        return Err(CacheError::CacheAlreadyExists);
+    }
+    if let Some(bytes) = req.require_free_bytes {
+        state.check_free_bytes(bytes)?;
+    }
+    let cache = CacheEntry::new(build_cache(&req)?, &req, &state.config)?;
 
+    let created = CreateCacheResponse::new(&req.name, &cache);
     match state.insert_cache(req.name.clone(), cache) {
-        Ok(_) => Ok(HttpResponse::Ok().body("Cache created")),
+        Ok(_) => Ok(HttpResponse::Ok().json(created)),
         Err(e) => Err(e),
     }
 }
 
-// POST /cache/delete – Delete a named cache.
+// GET /cache/list – List every cache by name, with its size in bytes and last write.
+pub async fn list_caches(state: web::Data<AppState>) -> Result<impl Responder, CacheError> {
+    let mut caches = state
+        .list_caches()
+        .into_iter()
+        .map(|(name, cache)| CacheListEntry {
+            name,
+            cache_type: cache.config.cache_type().to_string(),
+            capacity: cache.config.max_capacity(),
+            is_ttl: cache.config.ttl_settings().is_some(),
+            bytes: cache.bytes(),
+            last_modified: unix_millis(cache.last_modified()),
+        })
+        .collect::<Vec<CacheListEntry>>();
+    caches.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(HttpResponse::Ok().json(caches))
+}
+
+// GET /cache/types – List the cache types this server can create.
+pub async fn list_cache_types() -> impl Responder {
+    HttpResponse::Ok().json(cache_types())
+}
+
+// POST /cache/delete – Delete a named cache, responding with its final stats.
 pub async fn delete_cache(
     state: web::Data<AppState>,
     req: web::Json<DeleteCacheRequest>,
 ) -> Result<impl Responder, CacheError> {
+    state.check_maintenance()?;
     match state.remove_cache(&req.name) {
-        Ok(_) => Ok(HttpResponse::Ok().body("Cache deleted")),
+        Ok(cache) => {
+            Ok(HttpResponse::Ok()
+                .json(StatsResponse::new(&cache, state.config.near_full_threshold)))
+        }
         Err(e) => Err(e),
     }
 }
 
+// Opaque per-key client flags, set on PUT and echoed on GET.
+const CACHE_FLAGS: &str = "x-cache-flags";
+const CACHE_PRIORITY: &str = "x-cache-priority";
+const CACHE_META: &str = "x-cache-meta";
+// Largest metadata (decoded JSON) accepted per key.
+const MAX_META_BYTES: usize = 4096;
+
 // GET /cache/{cache_name}/{key} – Retrieve a value.
+// Also serves HEAD, and honours a single `Range: bytes=...` request with 206 Partial Content.
+// `?count=false` keeps the lookup out of the hit/miss stats (unlike peek, the key's
+// eviction order and sliding TTL are still updated). `?meta=true` echoes the key's
+// metadata back in `X-Cache-Meta`.
 pub async fn get_value(
     state: web::Data<AppState>,
     path: web::Path<(String, String)>, // (cache_name, key)
+    query: web::Query<GetQuery>,
+    req: HttpRequest,
+) -> Result<HttpResponse, CacheError> {
+    check_query(&state, &req, GetQuery::PARAMS)?;
+    let (cache_name, key) = path.into_inner();
+    validate_key(&key)?;
+    let forced_type = query
+        .content_type
+        .as_deref()
+        .map(parse_content_type)
+        .transpose()?;
+    let cache = state.get_cache(&cache_name)?;
+    cache.check_op(CacheOp::Get)?;
+    let FlaggedValue {
+        value: val,
+        flags,
+        meta,
+    } = cache
+        .get_with_flags(&key, query.count)?
+        .ok_or(CacheError::KeyNotFound)?;
+    let meta = match (query.meta, meta) {
+        (true, Some(meta)) => Some(encode_meta(&meta)?),
+        _ => None,
+    };
+
+    if let Ok(Range::Bytes(specs)) = Range::parse(&req) {
+        if let [spec] = specs.as_slice() {
+            let len = val.len() as u64;
+            return Ok(match spec.to_satisfiable_range(len) {
+                Some((start, end)) => {
+                    let mut resp = HttpResponse::PartialContent();
+                    resp.content_type(
+                        forced_type
+                            .clone()
+                            .unwrap_or(mime::APPLICATION_OCTET_STREAM),
+                    )
+                    .insert_header((header::ACCEPT_RANGES, "bytes"))
+                    .insert_header((CACHE_FLAGS, flags))
+                    .insert_header(ContentRange(ContentRangeSpec::Bytes {
+                        range: Some((start, end)),
+                        instance_length: Some(len),
+                    }));
+                    if let Some(meta) = &meta {
+                        resp.insert_header((CACHE_META, meta.as_str()));
+                    }
+                    resp.body(val[start as usize..=end as usize].to_vec())
+                }
+                None => HttpResponse::RangeNotSatisfiable()
+                    .insert_header(ContentRange(ContentRangeSpec::Bytes {
+                        range: None,
+                        instance_length: Some(len),
+                    }))
+                    .finish(),
+            });
+        }
+    }
+
+    // Clients asking for a text charset get UTF-8 values back as text.
+    let content_type = match (
+        forced_type,
+        accepts_utf8(&req) && std::str::from_utf8(&val).is_ok(),
+    ) {
+        (Some(forced), _) => forced,
+        (None, true) => mime::TEXT_PLAIN_UTF_8,
+        (None, false) => mime::APPLICATION_OCTET_STREAM,
+    };
+    let mut resp = HttpResponse::Ok();
+    resp.content_type(content_type)
+        .insert_header((header::ACCEPT_RANGES, "bytes"))
+        .insert_header((CACHE_FLAGS, flags))
+        // The content type above depends on Accept-Charset, so shared caches must key on it.
+        .insert_header((header::VARY, "Accept-Charset"));
+    if let Some(meta) = meta {
+        resp.insert_header((CACHE_META, meta));
+    }
+    Ok(resp.body(val.as_ref().clone()))
+}
+
+// GET /cache/{cache_name}/{key}/ttl – When a key in a ttl cache expires. Each write (and,
+// for sliding caches, read) sets its expiry to the cache's ttl shifted by a random amount
+// within ±jitter. Like peek, eviction order and stats are untouched.
+pub async fn get_key_ttl(
+    state: web::Data<AppState>,
+    path: web::Path<(String, String)>, // (cache_name, key)
+) -> Result<impl Responder, CacheError> {
+    let (cache_name, key) = path.into_inner();
+    validate_key(&key)?;
+    let cache = state.get_cache(&cache_name)?;
+    cache.check_op(CacheOp::Get)?;
+    let expires = cache.expires_at(&key)?;
+    let now = state.config.clock.now();
+    Ok(HttpResponse::Ok().json(KeyTtlResponse {
+        expires_at: expires.map(unix_millis),
+        remaining_seconds: expires.map(|expires| {
+            expires
+                .duration_since(now)
+                .unwrap_or_default()
+                .as_secs_f64()
+        }),
+    }))
+}
+
+// GET /cache/{cache_name}/{key}/meta – The JSON metadata stored with a key via
+// `X-Cache-Meta`, or null if it has none. Like peek, eviction order and stats are untouched.
+pub async fn get_meta(
+    state: web::Data<AppState>,
+    path: web::Path<(String, String)>, // (cache_name, key)
 ) -> Result<impl Responder, CacheError> {
     let (cache_name, key) = path.into_inner();
+    validate_key(&key)?;
+    let cache = state.get_cache(&cache_name)?;
+    cache.check_op(CacheOp::Get)?;
+    let meta = cache.meta(&key).ok_or(CacheError::KeyNotFound)?;
+    Ok(HttpResponse::Ok().json(meta))
+}
+
+// GET /cache/{cache_name}/{key}/peek – Read a value without affecting eviction order or stats.
+// Only available when the server shadows values (CACHERS_SHADOW_VALUES).
+pub async fn peek_value(
+    state: web::Data<AppState>,
+    path: web::Path<(String, String)>, // (cache_name, key)
+) -> Result<HttpResponse, CacheError> {
+    let (cache_name, key) = path.into_inner();
+    validate_key(&key)?;
+    let cache = state.get_cache(&cache_name)?;
+    cache.check_op(CacheOp::Get)?;
+    let val = cache.peek(&key)?.ok_or(CacheError::KeyNotFound)?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/octet-stream")
+        .body(val.as_ref().clone()))
+}
+
+// POST /cache/{cache_name}/mget – Retrieve several values, base64-encoded.
+// Misses are omitted, or returned as `null` in request order with `?ordered=true`.
+pub async fn mget(
+    state: web::Data<AppState>,
+    cache_name: web::Path<String>,
+    query: web::Query<MGetQuery>,
+    req: web::Json<MGetRequest>,
+    http_req: HttpRequest,
+) -> Result<impl Responder, CacheError> {
+    check_query(&state, &http_req, MGetQuery::PARAMS)?;
+    let cache = state.get_cache(&cache_name)?;
+    cache.check_op(CacheOp::Get)?;
+    let values = req
+        .keys
+        .iter()
+        .map(|key| {
+            Ok(cache
+                .get(key)?
+                .map(|val| BASE64_STANDARD.encode(val.as_ref())))
+        })
+        .collect::<Result<Vec<Option<String>>, CacheError>>()?;
+    if query.ordered {
+        return Ok(HttpResponse::Ok().json(values));
+    }
+    let found: BTreeMap<&String, String> = req
+        .keys
+        .iter()
+        .zip(values)
+        .filter_map(|(key, val)| val.map(|val| (key, val)))
+        .collect();
+    Ok(HttpResponse::Ok().json(found))
+}
+
+// POST /cache/{cache_name}/mset – Set several base64-encoded values. Every entry is
+// checked before any is stored; a bad entry is reported by index and, if known, key.
+// With `atomic`, a failure while storing (such as a full hard capacity) also leaves every
+// entry unwritten; otherwise the entries before it stay written.
+pub async fn mset(
+    state: web::Data<AppState>,
+    cache_name: web::Path<String>,
+    req: web::Json<MSetRequest>,
+) -> Result<impl Responder, CacheError> {
+    state.check_maintenance()?;
     let cache = state.get_cache(&cache_name)?;
-    match cache.get(&key) {
-        Some(val) => Ok(HttpResponse::Ok()
-            .content_type("application/octet-stream")
-            .body(val.as_ref().clone())),
-        None => Err(CacheError::KeyNotFound),
+    cache.check_op(CacheOp::Set)?;
+    let req = req.into_inner();
+    let entries = req
+        .entries
+        .into_iter()
+        .enumerate()
+        .map(|(i, entry)| parse_mset_entry(i, entry))
+        .collect::<Result<Vec<_>, CacheError>>()?;
+    let count = entries.len();
+    if req.atomic {
+        cache.set_all(entries)?;
+    } else {
+        for (key, value) in entries {
+            cache.set(key, value)?;
+        }
     }
+    Ok(HttpResponse::Ok().json(CountResponse { count }))
+}
+
+fn parse_mset_entry(
+    index: usize,
+    entry: serde_json::Value,
+) -> Result<(String, Vec<u8>), CacheError> {
+    let path = match entry.get("key").and_then(|key| key.as_str()) {
+        Some(key) => format!("entries[{}] (key {:?})", index, key),
+        None => format!("entries[{}]", index),
+    };
+    let bad = |reason: String| CacheError::BadRequest(format!("{}: {}", path, reason));
+    let entry: MSetEntry = serde_json::from_value(entry).map_err(|e| bad(e.to_string()))?;
+    validate_key(&entry.key).map_err(|e| bad(e.to_string()))?;
+    let value = BASE64_STANDARD
+        .decode(&entry.value)
+        .map_err(|e| bad(format!("value is not valid base64: {}", e)))?;
+    Ok((entry.key, value))
 }
 
 // PUT /cache/{cache_name}/{key} – Set a value with raw binary body.
+// Bodies sent with `Content-Encoding: gzip` or `br` are stored decompressed.
+// `If-None-Match: *` only creates (201, or 412 if the key exists) and `If-Match: *` only
+// overwrites (412 if the key is absent). An `X-Cache-Flags` u32 is stored with the value.
+// With `?return_old=true` the response body is the value that was replaced, or 204 if the
+// key was absent.
+// An `X-Cache-Meta` header of base64 JSON (up to 4 KiB decoded) is kept for GET .../meta.
+// An `X-Cache-Priority` integer (default 0) protects the key from capacity eviction until
+// every lower-priority key has gone; ties fall back to the cache's own order.
 pub async fn set_value(
     state: web::Data<AppState>,
     path: web::Path<(String, String)>, // (cache_name, key)
     body: web::Bytes,
+    query: web::Query<SetQuery>,
+    req: HttpRequest,
+) -> Result<impl Responder, CacheError> {
+    check_query(&state, &req, SetQuery::PARAMS)?;
+    state.check_maintenance()?;
+    let (cache_name, key) = path.into_inner();
+    validate_key(&key)?;
+    let flags = numeric_header::<u32>(&req, CACHE_FLAGS, "a u32")?.unwrap_or(0);
+    let priority = numeric_header::<i32>(&req, CACHE_PRIORITY, "an integer")?.unwrap_or(0);
+    let meta = req
+        .headers()
+        .get(CACHE_META)
+        .map(|value| decode_meta(value.as_bytes()))
+        .transpose()?;
+    let cache = state.get_cache(&cache_name)?;
+    cache.check_op(CacheOp::Set)?;
+    let is_star = |name| {
+        req.headers()
+            .get(name)
+            .is_some_and(|value| value.as_bytes().trim_ascii() == b"*")
+    };
+    let condition = match (is_star(header::IF_NONE_MATCH), is_star(header::IF_MATCH)) {
+        (true, _) => SetCondition::IfAbsent,
+        (false, true) => SetCondition::IfPresent,
+        (false, false) => SetCondition::Always,
+    };
+    let options = SetOptions {
+        flags,
+        priority,
+        meta,
+        condition,
+        return_old: query.return_old,
+    };
+    let old = cache.set_if(key, body.to_vec(), options)?;
+    if query.return_old {
+        return Ok(match old {
+            Some(old) => HttpResponse::Ok()
+                .content_type(mime::APPLICATION_OCTET_STREAM)
+                .body(old.as_ref().clone()),
+            None => HttpResponse::NoContent().finish(),
+        });
+    }
+    match condition {
+        SetCondition::IfAbsent => Ok(HttpResponse::Created().body("Value created")),
+        _ => Ok(HttpResponse::Ok().body("Value set")),
+    }
+}
+
+// Decode an `X-Cache-Meta` header: base64 of at most MAX_META_BYTES of JSON.
+fn decode_meta(header: &[u8]) -> Result<Arc<serde_json::Value>, CacheError> {
+    let invalid = |reason: &str| CacheError::BadRequest(format!("{} {}", CACHE_META, reason));
+    let json = BASE64_STANDARD
+        .decode(header.trim_ascii())
+        .map_err(|_| invalid("must be base64"))?;
+    if json.len() > MAX_META_BYTES {
+        return Err(invalid(&format!(
+            "must be at most {} bytes once decoded",
+            MAX_META_BYTES
+        )));
+    }
+    let meta = serde_json::from_slice(&json).map_err(|_| invalid("must be base64 JSON"))?;
+    Ok(Arc::new(meta))
+}
+
+fn encode_meta(meta: &serde_json::Value) -> Result<String, CacheError> {
+    let json = serde_json::to_vec(meta).map_err(|_| CacheError::Internal)?;
+    Ok(BASE64_STANDARD.encode(json))
+}
+
+// Parse an optional numeric request header, rejecting values that aren't `what`.
+fn numeric_header<T: std::str::FromStr>(
+    req: &HttpRequest,
+    name: &str,
+    what: &str,
+) -> Result<Option<T>, CacheError> {
+    req.headers()
+        .get(name)
+        .map(|value| {
+            value
+                .to_str()
+                .ok()
+                .and_then(|value| value.trim().parse().ok())
+                .ok_or_else(|| CacheError::BadRequest(format!("{} must be {}", name, what)))
+        })
+        .transpose()
+}
+
+// POST /cache/{cache_name}/{key}/get-or-set – Return the value, or store the raw body as
+// the value if the key is absent and return that (201). Check and set are atomic.
+pub async fn get_or_set(
+    state: web::Data<AppState>,
+    path: web::Path<(String, String)>, // (cache_name, key)
+    body: web::Bytes,
 ) -> Result<impl Responder, CacheError> {
     let (cache_name, key) = path.into_inner();
+    validate_key(&key)?;
     let cache = state.get_cache(&cache_name)?;
-    cache.set(key, body.to_vec());
-    Ok(HttpResponse::Ok().body("Value set"))
+    cache.check_op(CacheOp::Get)?;
+    cache.check_op(CacheOp::Set)?;
+    // A hit is only a read, so maintenance mode only turns away misses.
+    let (val, created) = cache.get_or_set(key, body.to_vec(), || state.check_maintenance())?;
+    let mut resp = match created {
+        true => HttpResponse::Created(),
+        false => HttpResponse::Ok(),
+    };
+    Ok(resp
+        .content_type(mime::APPLICATION_OCTET_STREAM)
+        .body(val.as_ref().clone()))
 }
 
 // DELETE /cache/{cache_name}/{key} – Remove a key.
@@ -85,367 +523,5186 @@ pub async fn delete_value(
     state: web::Data<AppState>,
     path: web::Path<(String, String)>, // (cache_name, key)
 ) -> Result<impl Responder, CacheError> {
+    state.check_maintenance()?;
     let (cache_name, key) = path.into_inner();
+    validate_key(&key)?;
     let cache = state.get_cache(&cache_name)?;
-    cache.remove(&key);
+    cache.check_op(CacheOp::Delete)?;
+    cache.remove(&key)?;
     Ok(HttpResponse::Ok().body("Key removed"))
 }
 
+// POST /cache/{cache_name}/delete-many – Remove several keys, counting those that were present.
+pub async fn delete_many(
+    state: web::Data<AppState>,
+    cache_name: web::Path<String>,
+    req: web::Json<DeleteManyRequest>,
+) -> Result<impl Responder, CacheError> {
+    state.check_maintenance()?;
+    let cache = state.get_cache(&cache_name)?;
+    cache.check_op(CacheOp::Delete)?;
+    let count = cache.remove_many(&req.keys)?;
+    Ok(HttpResponse::Ok().json(CountResponse { count }))
+}
+
+// Chunks of a dump that may wait on a slow client; reading stops until one is sent.
+const DUMP_BUFFERED_CHUNKS: usize = 4;
+
+// GET /cache/{cache_name}/dump.ndjson – Stream every entry as one JSON object per line.
+// Keys are snapshotted up front and values read as the client keeps up, in chunks of
+// CACHERS_DUMP_CHUNK_BYTES, so entries removed mid-dump are skipped. Reads count as cache
+// hits, but not as client gets. Answers 304 if the cache hasn't changed since
+// `If-Modified-Since`.
+pub async fn dump_ndjson(
+    state: web::Data<AppState>,
+    cache_name: web::Path<String>,
+    req: HttpRequest,
+) -> Result<impl Responder, CacheError> {
+    let cache = state.get_cache(&cache_name)?;
+    cache.check_op(CacheOp::Get)?;
+    let last_modified = http_date(cache.last_modified());
+    if let Ok(IfModifiedSince(since)) = IfModifiedSince::parse(&req) {
+        if last_modified <= since {
+            return Ok(HttpResponse::NotModified()
+                .insert_header(LastModified(last_modified))
+                .finish());
+        }
+    }
+    let keys = cache.keys();
+    let chunk_bytes = state.config.dump_chunk_bytes.max(1);
+    let (tx, rx) = tokio::sync::mpsc::channel(DUMP_BUFFERED_CHUNKS);
+    actix_web::rt::spawn(async move {
+        let mut chunk = Vec::with_capacity(chunk_bytes);
+        for key in keys {
+            let line = cache
+                .read(&key)
+                .map(|val| ndjson_line(key, &val))
+                .transpose();
+            match line {
+                Ok(Some(line)) => chunk.extend_from_slice(&line),
+                Ok(None) => continue,
+                Err(e) => {
+                    let _ = tx.send(Err(e)).await;
+                    return;
+                }
+            }
+            if chunk.len() >= chunk_bytes {
+                let full = std::mem::replace(&mut chunk, Vec::with_capacity(chunk_bytes));
+                // Waits while the channel is full, and fails once the client has gone.
+                if tx.send(Ok(web::Bytes::from(full))).await.is_err() {
+                    return;
+                }
+            }
+        }
+        if !chunk.is_empty() {
+            let _ = tx.send(Ok(web::Bytes::from(chunk))).await;
+        }
+    });
+    let chunks = stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    });
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .insert_header(LastModified(last_modified))
+        .streaming(chunks))
+}
+
+// GET /cache/{cache_name}/changes?since=<unix_ms> – Keys written after `since`, oldest
+// write first, for incremental syncs. `values=true` adds each value. Deleted and evicted
+// keys aren't reported, and keys that expire before they're read are skipped.
+pub async fn changes(
+    state: web::Data<AppState>,
+    cache_name: web::Path<String>,
+    query: web::Query<ChangesQuery>,
+    req: HttpRequest,
+) -> Result<impl Responder, CacheError> {
+    check_query(&state, &req, ChangesQuery::PARAMS)?;
+    let cache = state.get_cache(&cache_name)?;
+    cache.check_op(CacheOp::Get)?;
+    let since = UNIX_EPOCH + Duration::from_millis(query.since);
+    let mut changes = Vec::new();
+    for (key, modified) in cache.modified_after(since) {
+        let modified = unix_millis(modified);
+        // Compare at the millisecond precision clients see.
+        if modified <= query.since {
+            continue;
+        }
+        let value = match query.values {
+            true => match cache.read(&key) {
+                Some(val) => Some(BASE64_STANDARD.encode(val.as_slice())),
+                None => continue,
+            },
+            false => None,
+        };
+        changes.push(ChangeEntry {
+            key,
+            modified,
+            value,
+        });
+    }
+    Ok(HttpResponse::Ok().json(ChangesResponse { changes }))
+}
+
+// GET /cache/{cache_name}/histogram – Count values by stored size. Buckets double from 1
+// byte up to the largest value unless `buckets` gives their upper bounds.
+pub async fn histogram(
+    state: web::Data<AppState>,
+    cache_name: web::Path<String>,
+    query: web::Query<HistogramQuery>,
+    req: HttpRequest,
+) -> Result<impl Responder, CacheError> {
+    check_query(&state, &req, HistogramQuery::PARAMS)?;
+    let cache = state.get_cache(&cache_name)?;
+    cache.check_op(CacheOp::Get)?;
+    let sizes = cache.value_sizes();
+    let bounds = match &query.buckets {
+        Some(buckets) => parse_buckets(buckets)?,
+        None => {
+            let largest = sizes.iter().copied().max().unwrap_or(0).max(1);
+            std::iter::successors(Some(1u64), |bound| bound.checked_mul(2))
+                .take_while(|bound| bound / 2 < largest)
+                .collect()
+        }
+    };
+    let mut buckets: Vec<HistogramBucket> = bounds
+        .iter()
+        .map(|&le| HistogramBucket {
+            le: Some(le),
+            count: 0,
+        })
+        .chain([HistogramBucket { le: None, count: 0 }])
+        .collect();
+    for size in sizes {
+        let i = bounds.partition_point(|&le| le < size);
+        buckets[i].count += 1;
+    }
+    Ok(HttpResponse::Ok().json(HistogramResponse { buckets }))
+}
+
+fn parse_buckets(buckets: &str) -> Result<Vec<u64>, CacheError> {
+    let bad = || {
+        CacheError::BadRequest(format!(
+            "buckets must be increasing byte counts separated by commas: {}",
+            buckets
+        ))
+    };
+    let bounds = buckets
+        .split(',')
+        .map(|bound| bound.trim().parse::<u64>().map_err(|_| bad()))
+        .collect::<Result<Vec<u64>, CacheError>>()?;
+    match bounds.windows(2).all(|pair| pair[0] < pair[1]) {
+        true => Ok(bounds),
+        false => Err(bad()),
+    }
+}
+
+// HTTP dates only have whole-second precision, so truncate before comparing with one.
+fn http_date(time: SystemTime) -> HttpDate {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    HttpDate::from(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+fn ndjson_line(key: String, val: &[u8]) -> Result<web::Bytes, CacheError> {
+    let entry = DumpEntry {
+        key,
+        value: BASE64_STANDARD.encode(val),
+    };
+    let mut line = serde_json::to_vec(&entry).map_err(|_| CacheError::Internal)?;
+    line.push(b'\n');
+    Ok(web::Bytes::from(line))
+}
+
+// Most keys returned by one scan call.
+const MAX_SCAN_COUNT: usize = 1000;
+
+// GET /cache/{cache_name}/scan – Page through keys in sorted order, `?count=` at a time.
+// The cursor encodes the last key returned, so no state is held between calls and the
+// scan tolerates concurrent writes: keys present for the whole scan are returned exactly
+// once, while keys added or removed mid-scan may or may not appear.
+pub async fn scan_keys(
+    state: web::Data<AppState>,
+    cache_name: web::Path<String>,
+    query: web::Query<ScanQuery>,
+    req: HttpRequest,
+) -> Result<impl Responder, CacheError> {
+    check_query(&state, &req, ScanQuery::PARAMS)?;
+    let cache = state.get_cache(&cache_name)?;
+    cache.check_op(CacheOp::Get)?;
+    let after = match &query.cursor {
+        Some(cursor) => {
+            let key = BASE64_URL_SAFE_NO_PAD
+                .decode(cursor)
+                .ok()
+                .and_then(|key| String::from_utf8(key).ok())
+                .ok_or_else(|| CacheError::BadRequest("invalid cursor".to_string()))?;
+            Some(key)
+        }
+        None => None,
+    };
+    let count = query.count.clamp(1, MAX_SCAN_COUNT);
+    let keys = cache.keys_after(after.as_deref(), count);
+    let cursor = match keys.len() == count {
+        true => keys.last().map(|key| BASE64_URL_SAFE_NO_PAD.encode(key)),
+        false => None,
+    };
+    Ok(HttpResponse::Ok().json(ScanResponse { keys, cursor }))
+}
+
+// GET /cache/{cache_name}/keys/count – Count keys, optionally only those matching `?prefix=`.
+pub async fn count_keys(
+    state: web::Data<AppState>,
+    cache_name: web::Path<String>,
+    query: web::Query<KeysQuery>,
+    req: HttpRequest,
+) -> Result<impl Responder, CacheError> {
+    check_query(&state, &req, KeysQuery::PARAMS)?;
+    let cache = state.get_cache(&cache_name)?;
+    cache.check_op(CacheOp::Get)?;
+    let count = cache.count_keys(&query.prefix);
+    Ok(HttpResponse::Ok().json(CountResponse { count }))
+}
+
+// POST /cache/{cache_name}/policy – Switch a cache to another type at the same capacity.
+// Entries and server-side state are migrated into a new cache, which is then swapped in.
+// Writes fail with 403 read_only while the migration runs.
+pub async fn set_policy(
+    state: web::Data<AppState>,
+    cache_name: web::Path<String>,
+    req: web::Json<PolicyRequest>,
+) -> Result<impl Responder, CacheError> {
+    state.check_maintenance()?;
+    let old = state.get_cache(&cache_name)?;
+    let mut config = old.config.clone();
+    config.cache_type = Some(req.cache_type.clone());
+    if req.cache_type != "ttl" {
+        config.ttl = None;
+        config.check_interval = None;
+        config.jitter = None;
+        config.sliding = false;
+    }
+    let new = CacheEntry::new(build_cache(&config)?, &config, &state.config)?;
+    old.migrate_to(&new, false)?;
+    let old = state
+        .replace_cache(&cache_name, new)
+        .inspect_err(|_| old.cancel_migration())?;
+    Ok(HttpResponse::Ok().json(PolicyResponse {
+        old: old.config.cache_type().to_string(),
+        new: req.cache_type.clone(),
+    }))
+}
+
+// POST /cache/{cache_name}/reinit – Replace a cache with an empty one of the given capacity
+// and, optionally, type. Contents, counters and key tracking all start afresh.
+pub async fn reinit(
+    state: web::Data<AppState>,
+    cache_name: web::Path<String>,
+    req: web::Json<ReinitRequest>,
+) -> Result<impl Responder, CacheError> {
+    state.check_maintenance()?;
+    let old = state.get_cache(&cache_name)?;
+    // Wiping the contents is a write, and a fresh cache would also drop the freeze.
+    old.check_writable()?;
+    let mut config = old.config.clone();
+    config.capacity = req.capacity;
+    config.soft_capacity = None;
+    config.hard_capacity = None;
+    if let Some(cache_type) = &req.cache_type {
+        config.cache_type = Some(cache_type.clone());
+        if cache_type != "ttl" {
+            config.ttl = None;
+            config.check_interval = None;
+            config.jitter = None;
+            config.sliding = false;
+        }
+    }
+    let new = CacheEntry::new(build_cache(&config)?, &config, &state.config)?;
+    let reinitialized = CreateCacheResponse::new(&cache_name, &new);
+    state.replace_cache(&cache_name, new)?;
+    Ok(HttpResponse::Ok().json(reinitialized))
+}
+
+// Approximate JSON size of each mset sent by a drain, well under the default body limit.
+const DRAIN_BATCH_BYTES: usize = 64 * 1024;
+
+// POST /cache/{cache_name}/drain – Copy every entry to the same-named cache on a peer,
+// creating it there with this cache's config if needed, then optionally delete the cache.
+// The peer only lets admins set a stats webhook, so the copy goes without one and the
+// response lists the settings dropped.
+// Entries are sent as mset batches; if any batch fails the local cache is kept. Values too
+// large for the peer's body limit are skipped and reported, and also keep the local cache.
+pub async fn drain(
+    state: web::Data<AppState>,
+    cache_name: web::Path<String>,
+    body: web::Json<DrainRequest>,
+    req: HttpRequest,
+) -> Result<impl Responder, CacheError> {
+    check_admin(&state, &req)?;
+    let cache = state.get_cache(&cache_name)?;
+    let client = reqwest::Client::new();
+
+    let mut config = cache.config.clone();
+    let mut dropped = Vec::new();
+    if config.stats_webhook.take().is_some() {
+        dropped.push("stats_webhook".to_string());
+    }
+    if config.stats_webhook_interval_ms.take().is_some() {
+        dropped.push("stats_webhook_interval_ms".to_string());
+    }
+    let url = peer_url(&body.target, &["cache", "create"])?;
+    let status = post_to_peer(&client, &url, &config).await?;
+    if !status.is_success() && status != reqwest::StatusCode::CONFLICT {
+        return Err(CacheError::BadGateway(format!(
+            "{} returned {}",
+            url, status
+        )));
+    }
+
+    let url = peer_url(&body.target, &["cache", &cache_name, "mset"])?;
+    let mut transferred = 0;
+    let mut skipped = Vec::new();
+    let mut batch = Vec::new();
+    let mut batch_bytes = 0;
+    let mut keys = cache.keys().into_iter().peekable();
+    while let Some(key) = keys.next() {
+        if let Some(val) = cache.read(&key) {
+            let value = BASE64_STANDARD.encode(val.as_slice());
+            batch_bytes += key.len() + value.len();
+            batch.push(MSetEntry { key, value });
+        }
+        if !batch.is_empty() && (batch_bytes >= DRAIN_BATCH_BYTES || keys.peek().is_none()) {
+            let sent = batch.len();
+            let refused = send_drain_batch(&client, &url, std::mem::take(&mut batch)).await?;
+            transferred += sent - refused.len();
+            skipped.extend(refused);
+            batch_bytes = 0;
+        }
+    }
+
+    if body.delete && skipped.is_empty() {
+        state.remove_cache(&cache_name)?;
+    }
+    Ok(HttpResponse::Ok().json(DrainResponse {
+        count: transferred,
+        skipped,
+        dropped,
+    }))
+}
+
+// Send one mset batch of a drain, returning the keys the peer refused as too large. A
+// refused batch is retried an entry at a time, so only the oversized values are left out.
+async fn send_drain_batch(
+    client: &reqwest::Client,
+    url: &reqwest::Url,
+    batch: Vec<MSetEntry>,
+) -> Result<Vec<String>, CacheError> {
+    let too_large = reqwest::StatusCode::PAYLOAD_TOO_LARGE;
+    let status = post_to_peer(client, url, &serde_json::json!({"entries": batch})).await?;
+    if status.is_success() {
+        return Ok(Vec::new());
+    }
+    if status != too_large {
+        return Err(CacheError::BadGateway(format!(
+            "{} returned {}",
+            url, status
+        )));
+    }
+    let mut refused = Vec::new();
+    for entry in batch {
+        let status = post_to_peer(client, url, &serde_json::json!({"entries": [&entry]})).await?;
+        match status {
+            status if status.is_success() => {}
+            status if status == too_large => refused.push(entry.key),
+            status => {
+                return Err(CacheError::BadGateway(format!(
+                    "{} returned {}",
+                    url, status
+                )))
+            }
+        }
+    }
+    Ok(refused)
+}
+
+// A URL on a peer, with each path segment percent-encoded, so cache names are sent as-is.
+fn peer_url(target: &str, segments: &[&str]) -> Result<reqwest::Url, CacheError> {
+    let invalid = || CacheError::BadRequest(format!("invalid target {:?}", target));
+    let mut url = reqwest::Url::parse(target).map_err(|_| invalid())?;
+    url.path_segments_mut()
+        .map_err(|_| invalid())?
+        .pop_if_empty()
+        .extend(segments);
+    Ok(url)
+}
+
+async fn post_to_peer(
+    client: &reqwest::Client,
+    url: &reqwest::Url,
+    body: &impl serde::Serialize,
+) -> Result<reqwest::StatusCode, CacheError> {
+    let resp = client
+        .post(url.clone())
+        .json(body)
+        .send()
+        .await
+        .map_err(|e| CacheError::BadGateway(format!("{}: {}", url, e)))?;
+    Ok(resp.status())
+}
+
+// POST /cache/{cache_name}/compact – Drop server-side records (and spill files) of keys
+// the cache has already expired on its own, which are otherwise only noticed on read.
+pub async fn compact(
+    state: web::Data<AppState>,
+    cache_name: web::Path<String>,
+) -> Result<impl Responder, CacheError> {
+    let cache = state.get_cache(&cache_name)?;
+    let (removed, bytes_reclaimed) = cache.compact();
+    Ok(HttpResponse::Ok().json(CompactResponse {
+        removed,
+        bytes_reclaimed,
+    }))
+}
+
+// POST /cache/{cache_name}/verify – Check the server's key index, byte counter and
+// eviction bookkeeping against each other and the cache, reporting any discrepancies.
+pub async fn verify(
+    state: web::Data<AppState>,
+    cache_name: web::Path<String>,
+) -> Result<impl Responder, CacheError> {
+    let cache = state.get_cache(&cache_name)?;
+    let (keys, discrepancies) = cache.verify();
+    Ok(HttpResponse::Ok().json(VerifyResponse {
+        ok: discrepancies.is_empty(),
+        keys,
+        discrepancies,
+    }))
+}
+
+// POST /cache/{cache_name}/readonly – Enable or disable read-only mode for a cache.
+pub async fn set_read_only(
+    state: web::Data<AppState>,
+    cache_name: web::Path<String>,
+    req: web::Json<ReadOnlyRequest>,
+) -> Result<impl Responder, CacheError> {
+    let cache = state.get_cache(&cache_name)?;
+    cache.set_read_only(req.enabled);
+    match req.enabled {
+        true => Ok(HttpResponse::Ok().body("Read-only enabled")),
+        false => Ok(HttpResponse::Ok().body("Read-only disabled")),
+    }
+}
+
+// Longest a stats long-poll may wait.
+const MAX_STATS_WAIT_SECS: u64 = 60;
+
 // GET /cache/{cache_name}/stats – Retrieve cache statistics.
+// With `?wait=N`, holds the response up to N seconds until the stats version moves past
+// `since`, so dashboards can long-poll for changes.
 pub async fn stats(
     state: web::Data<AppState>,
     cache_name: web::Path<String>,
+    query: web::Query<CacheStatsQuery>,
+    req: HttpRequest,
 ) -> Result<impl Responder, CacheError> {
+    check_query(&state, &req, CacheStatsQuery::PARAMS)?;
     let cache = state.get_cache(&cache_name)?;
-    let s: CacheStats = cache.stats();
-    let json = format!(
-        r#"{{"hits":{},"misses":{},"size":{},"capacity":{}}}"#,
-        s.hits, s.misses, s.size, s.capacity
-    );
-    Ok(HttpResponse::Ok()
-        .content_type("application/json")
-        .body(json))
+    if let Some(wait) = query.wait {
+        let since = query.since.unwrap_or_else(|| cache.version());
+        let wait = Duration::from_secs(wait.min(MAX_STATS_WAIT_SECS));
+        cache.wait_for_change(since, wait).await;
+    }
+    let s = StatsResponse::new(&cache, state.config.near_full_threshold);
+    let Some(fields) = &query.fields else {
+        return Ok(HttpResponse::Ok().json(s));
+    };
+
+    let mut all = match serde_json::to_value(s).map_err(|_| CacheError::Internal)? {
+        serde_json::Value::Object(map) => map,
+        _ => return Err(CacheError::Internal),
+    };
+    let mut selected = serde_json::Map::new();
+    for field in fields.split(',').map(str::trim) {
+        let value = all
+            .remove(field)
+            .ok_or_else(|| CacheError::BadRequest(format!("unknown stats field: {}", field)))?;
+        selected.insert(field.to_string(), value);
+    }
+    Ok(HttpResponse::Ok().json(selected))
 }
 
-#[cfg(test)]
-mod tests {
-    use actix_web::{http::header::HeaderValue, test, web, App};
-    use std::collections::HashMap;
-    use std::sync::Mutex;
+// GET /cache/stats – Retrieve statistics for every cache, optionally filtered by a name glob.
+pub async fn all_stats(
+    state: web::Data<AppState>,
+    query: web::Query<StatsQuery>,
+    req: HttpRequest,
+) -> Result<impl Responder, CacheError> {
+    check_query(&state, &req, StatsQuery::PARAMS)?;
+    let all: BTreeMap<String, StatsResponse> = state
+        .list_caches()
+        .into_iter()
+        .filter(|(name, _)| match &query.pattern {
+            Some(pattern) => glob_match(pattern, name),
+            None => true,
+        })
+        .map(|(name, cache)| {
+            let s = StatsResponse::new(&cache, state.config.near_full_threshold);
+            (name, s)
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(all))
+}
 
-    use super::*;
-    use crate::request_types::{CreateCacheRequest, DeleteCacheRequest};
-    use crate::state::AppState;
+// GET /cache/stats/delta – Change in each cache's hits, misses and sets since the snapshot
+// named by `since`, plus a token for the next call. Caches created since then count
+// from zero; counters that were reset since then report zero.
+pub async fn stats_delta(
+    state: web::Data<AppState>,
+    query: web::Query<StatsDeltaQuery>,
+    req: HttpRequest,
+) -> Result<impl Responder, CacheError> {
+    check_query(&state, &req, StatsDeltaQuery::PARAMS)?;
+    let baseline = match query.since {
+        Some(since) => state.snapshot(since).ok_or_else(|| {
+            CacheError::BadRequest(format!("unknown or expired stats token: {}", since))
+        })?,
+        None => HashMap::new(),
+    };
+    let (token, current) = state.take_snapshot();
+    let caches = current
+        .into_iter()
+        .map(|(name, now)| {
+            let before = baseline.get(&name).copied().unwrap_or_default();
+            let delta = StatsDelta {
+                hits: now.hits.saturating_sub(before.hits),
+                misses: now.misses.saturating_sub(before.misses),
+                sets: now.sets.saturating_sub(before.sets),
+            };
+            (name, delta)
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(StatsDeltaResponse { token, caches }))
+}
 
-    #[macro_export]
-    macro_rules! create_app {
-        () => {
-            test::init_service(
-                App::new()
-                    .app_data(web::Data::new(AppState {
-                        caches: Mutex::new(HashMap::new()),
-                    }))
-                    .route("/cache/create", web::post().to(create_cache))
-                    .route("/cache/delete", web::post().to(delete_cache))
-                    .route("/cache/{cache_name}/stats", web::get().to(stats))
-                    .route("/cache/{cache_name}/{key}", web::get().to(get_value))
-                    .route("/cache/{cache_name}/{key}", web::put().to(set_value))
-                    .route("/cache/{cache_name}/{key}", web::delete().to(delete_value)),
-            )
-            .await
-        };
+// POST /metrics/reset – Zero every cache's counters for a clean benchmark run. Gauges
+// such as size and the cached entries themselves are untouched. Requires the admin token.
+pub async fn reset_metrics(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<impl Responder, CacheError> {
+    check_admin(&state, &req)?;
+    for (_, cache) in state.list_caches() {
+        cache.reset_counters();
     }
+    Ok(HttpResponse::Ok().body("Metrics reset"))
+}
 
-    #[actix_web::test]
-    async fn test_create_cache() {
-        let mut app = create_app!();
+// GET /status – Uptime, totals across caches and brief stats for each cache in one
+// payload, for dashboards that would otherwise call /ping, /cache/list and /cache/stats.
+pub async fn status(state: web::Data<AppState>) -> Result<impl Responder, CacheError> {
+    let mut caches = state
+        .list_caches()
+        .into_iter()
+        .map(|(name, cache)| {
+            let stats = cache.stats();
+            CacheStatusEntry {
+                name,
+                cache_type: cache.config.cache_type().to_string(),
+                size: stats.size,
+                capacity: stats.capacity,
+                bytes: cache.bytes(),
+                hits: stats.hits,
+                misses: stats.misses,
+            }
+        })
+        .collect::<Vec<CacheStatusEntry>>();
+    caches.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(HttpResponse::Ok().json(StatusResponse {
+        uptime_seconds: state.uptime().as_secs_f64(),
+        cache_count: caches.len(),
+        bytes: caches.iter().map(|c| c.bytes).sum(),
+        hits: caches.iter().map(|c| c.hits).sum(),
+        misses: caches.iter().map(|c| c.misses).sum(),
+        caches,
+    }))
+}
 
-        let req = test::TestRequest::post()
-            .uri("/cache/create")
-            .set_json(&CreateCacheRequest {
-                name: "test".to_string(),
-                cache_type: "lru".to_string(),
-                capacity: 10,
-                ttl: None,
-                check_interval: None,
-                jitter: None,
-            })
+// GET /ping – Liveness check reporting the server's clock.
+pub async fn ping(state: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(PingResponse {
+        pong: true,
+        server_time: unix_millis(state.config.clock.now()),
+    })
+}
+
+// GET /route/{key} – The peer in CACHERS_PEERS that owns `key` under consistent hashing.
+pub async fn route_key(
+    state: web::Data<AppState>,
+    key: web::Path<String>,
+) -> Result<impl Responder, CacheError> {
+    validate_key(&key)?;
+    let peer = state
+        .ring
+        .peer_for(&key)
+        .ok_or_else(|| CacheError::Unprocessable("CACHERS_PEERS is not set".to_string()))?;
+    Ok(HttpResponse::Ok().json(RouteResponse {
+        peer: peer.to_string(),
+    }))
+}
+
+// GET /schema – JSON Schema for the request bodies, for client generators.
+pub async fn schema() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("application/schema+json")
+        .body(request_types::schema().to_string())
+}
+
+// GET /metrics – Per-cache metrics in the Prometheus text format.
+pub async fn metrics(state: web::Data<AppState>) -> Result<impl Responder, CacheError> {
+    let body = format_metrics(&state.list_caches());
+    Ok(HttpResponse::Ok()
+        .content_type(METRICS_CONTENT_TYPE)
+        .body(body))
+}
+
+// POST /admin/shutdown – Gracefully stop the server once in-flight requests finish.
+pub async fn shutdown(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<impl Responder, CacheError> {
+    check_admin(&state, &req)?;
+    state.shutdown();
+    Ok(HttpResponse::Accepted().body("Shutting down"))
+}
+
+// GET /admin/export – Stream every cache as a tar archive: a manifest of cache configs,
+// then each cache's entries as NDJSON. Entries are read as the client keeps up and sent in
+// members of about CACHERS_DUMP_CHUNK_BYTES, so only one member is held at a time.
+pub async fn export(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<impl Responder, CacheError> {
+    check_admin(&state, &req)?;
+    let mut caches = state.list_caches();
+    caches.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let manifest = archive::Manifest {
+        caches: caches
+            .iter()
+            .enumerate()
+            .map(|(i, (_, cache))| archive::ManifestCache {
+                config: cache.config.clone(),
+                file: archive::cache_path(i),
+            })
+            .collect(),
+    };
+    let manifest = serde_json::to_vec(&manifest).map_err(|_| CacheError::Internal)?;
+    let manifest = archive::file(archive::MANIFEST_PATH, &manifest);
+
+    let chunk_bytes = state.config.dump_chunk_bytes.max(1);
+    let files = stream::iter(caches.into_iter().enumerate()).flat_map(move |(i, (_, cache))| {
+        stream::iter(archive_cache_members(i, cache, chunk_bytes))
+    });
+    let body = stream::once(future::ready(manifest))
+        .chain(files)
+        .chain(stream::once(future::ready(Ok(archive::end()))));
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-tar")
+        .streaming(body))
+}
+
+// The archive members holding a cache's entries, at least one even if it's empty. Keys
+// are snapshotted when the first member is requested; entries removed since are skipped.
+fn archive_cache_members(
+    index: usize,
+    cache: Arc<CacheEntry>,
+    chunk_bytes: usize,
+) -> impl Iterator<Item = Result<web::Bytes, CacheError>> {
+    let path = archive::cache_path(index);
+    let mut keys = cache.keys().into_iter().peekable();
+    let mut first = true;
+    std::iter::from_fn(move || {
+        if !std::mem::take(&mut first) && keys.peek().is_none() {
+            return None;
+        }
+        let mut contents = Vec::new();
+        while contents.len() < chunk_bytes {
+            let Some(key) = keys.next() else {
+                break;
+            };
+            if let Some(val) = cache.read(&key) {
+                match ndjson_line(key, &val) {
+                    Ok(line) => contents.extend_from_slice(&line),
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+        }
+        Some(archive::file(&path, &contents))
+    })
+}
+
+// POST /admin/import – Recreate the caches in an archive from /admin/export. The archive
+// is read as it arrives, up to CACHERS_MAX_IMPORT_BYTES, rather than under the body limit
+// of the data routes. Nothing is created unless every cache is valid and none of their
+// names are taken.
+pub async fn import(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    payload: web::Payload,
+) -> Result<impl Responder, CacheError> {
+    check_admin(&state, &req)?;
+    let payload = Decompress::from_headers(payload, req.headers());
+    let mut archive = archive::Reader::new(payload.boxed_local(), state.config.max_import_bytes);
+    let manifest = archive.manifest().await?;
+
+    // Each cache is built empty up front and filled as its members arrive.
+    let mut built: Vec<(CacheEntry, String, bool)> = Vec::new();
+    for cache in manifest.caches {
+        let name = &cache.config.name;
+        if state.cache_exists(name).is_ok() || built.iter().any(|(c, ..)| c.config.name == *name) {
+            return Err(CacheError::CacheAlreadyExists);
+        }
+        let entry = CacheEntry::new(build_cache(&cache.config)?, &cache.config, &state.config)?;
+        built.push((entry, cache.file, false));
+    }
+    while let Some(member) = archive.next_member().await? {
+        match built.iter_mut().find(|(_, file, _)| *file == member.path) {
+            Some((entry, _, seen)) => {
+                *seen = true;
+                archive
+                    .lines(&member, |line| {
+                        let (key, value) = archive::parse_line(line)?;
+                        entry.set(key, value)
+                    })
+                    .await?;
+            }
+            None => archive.skip(&member).await?,
+        }
+    }
+    if let Some((_, file, _)) = built.iter().find(|(_, _, seen)| !seen) {
+        return Err(archive::invalid(format!("missing {}", file)));
+    }
+
+    let count = built.len();
+    for (entry, ..) in built {
+        state.insert_cache(entry.config.name.clone(), entry)?;
+    }
+    Ok(HttpResponse::Ok().json(CountResponse { count }))
+}
+
+// GET /debug/caches/{cache_name}/internal – The cache's keys in the order it would evict
+// them. TTL caches are rejected: expiry, not this order, decides when their keys go.
+pub async fn debug_internal(
+    state: web::Data<AppState>,
+    cache_name: web::Path<String>,
+    req: HttpRequest,
+) -> Result<impl Responder, CacheError> {
+    check_admin(&state, &req)?;
+    let cache = state.get_cache(&cache_name)?;
+    if cache.config.ttl_settings().is_some() {
+        return Err(CacheError::BadRequest(
+            "ttl caches have no fixed eviction order".to_string(),
+        ));
+    }
+    Ok(HttpResponse::Ok().json(InternalResponse {
+        cache_type: cache.config.cache_type().to_string(),
+        eviction_order: cache.eviction_order(),
+    }))
+}
+
+// POST /admin/reload-manifest – Re-read CACHERS_MANIFEST and create any listed caches
+// that are missing. Existing caches, listed or not, are left untouched.
+pub async fn reload_manifest(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<impl Responder, CacheError> {
+    check_admin(&state, &req)?;
+    let path = state
+        .config
+        .manifest_file
+        .as_deref()
+        .ok_or_else(|| CacheError::Unprocessable("CACHERS_MANIFEST is not set".to_string()))?;
+    let manifest = manifest::read_manifest(Path::new(path))?;
+    let created = manifest::apply_manifest(&state, manifest)?;
+    Ok(HttpResponse::Ok().json(ManifestResponse { created }))
+}
+
+// POST /admin/maintenance – Enable or disable maintenance mode, in which writes from
+// clients are refused with 503 and a Retry-After header. Reads and /admin keep working.
+pub async fn maintenance(
+    state: web::Data<AppState>,
+    body: web::Json<ReadOnlyRequest>,
+    req: HttpRequest,
+) -> Result<impl Responder, CacheError> {
+    check_admin(&state, &req)?;
+    state.set_maintenance(body.enabled);
+    match body.enabled {
+        true => Ok(HttpResponse::Ok().body("Maintenance enabled")),
+        false => Ok(HttpResponse::Ok().body("Maintenance disabled")),
+    }
+}
+
+// POST /admin/config-update – Change the TTL settings of every ttl cache matching
+// `pattern`, responding with the names of those updated. Like a policy switch, each is
+// rebuilt and its entries migrated; they start a fresh TTL if ttl or jitter changes and
+// keep their expiry otherwise. Every cache is migrated before any is swapped in, so a
+// failure leaves them all as they were.
+pub async fn config_update(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<ConfigUpdateRequest>,
+) -> Result<impl Responder, CacheError> {
+    check_admin(&state, &req)?;
+    if body.ttl.is_none() && body.check_interval.is_none() && body.jitter.is_none() {
+        return Err(CacheError::BadRequest(
+            "one of ttl, check_interval or jitter is required".to_string(),
+        ));
+    }
+    let mut rebuilt = Vec::new();
+    for (name, old) in state.list_caches() {
+        if old.config.ttl_settings().is_none() || !glob_match(&body.pattern, &name) {
+            continue;
+        }
+        let mut config = old.config.clone();
+        config.ttl = body.ttl.or(config.ttl);
+        config.check_interval = body.check_interval.or(config.check_interval);
+        config.jitter = body.jitter.or(config.jitter);
+        let keep_expiry = config.ttl == old.config.ttl && config.jitter == old.config.jitter;
+        let new = CacheEntry::new(build_cache(&config)?, &config, &state.config)?;
+        rebuilt.push((name, old, new, keep_expiry));
+    }
+
+    let mut migrated = Vec::with_capacity(rebuilt.len());
+    let mut entries = Vec::with_capacity(rebuilt.len());
+    let cancel = |migrated: &[Arc<CacheEntry>]| {
+        migrated.iter().for_each(|old| old.cancel_migration());
+    };
+    for (name, old, new, keep_expiry) in rebuilt {
+        if let Err(e) = old.migrate_to(&new, keep_expiry) {
+            cancel(&migrated);
+            return Err(e);
+        }
+        migrated.push(old);
+        entries.push((name, new));
+    }
+    let mut updated: Vec<String> = entries.iter().map(|(name, _)| name.clone()).collect();
+    if let Err(e) = state.replace_caches(entries) {
+        cancel(&migrated);
+        return Err(e);
+    }
+    updated.sort();
+    Ok(HttpResponse::Ok().json(ConfigUpdateResponse { updated }))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::header::HeaderValue, test, web, App};
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::request_types::{
+        CreateCacheRequest, DeleteCacheRequest, MGetRequest, ReadOnlyRequest, TtlUnit,
+    };
+    use crate::response_types::ErrorResponse;
+    use crate::state::{AppState, Clock, ManualClock};
+
+    #[macro_export]
+    macro_rules! create_app {
+        () => {
+            create_app!(Config::default())
+        };
+        ($config:expr) => {
+            test::init_service(
+                App::new()
+                    .app_data(web::Data::new(AppState::new($config)))
+                    .configure($crate::routes::data)
+                    .configure($crate::routes::admin),
+            )
+            .await
+        };
+    }
+
+    #[actix_web::test]
+    async fn test_create_cache() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ttl: None,
+                check_interval: None,
+                jitter: None,
+                ..Default::default()
+            })
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        let resp: CreateCacheResponse = test::read_body_json(resp).await;
+        assert_eq!(resp.name, "test");
+        assert_eq!(resp.cache_type, "lru");
+        assert_eq!(resp.capacity, 10);
+        assert!(resp.created_at > 0);
+
+        // With soft and hard capacities, the hard capacity is the effective one.
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "burst".to_string(),
+                cache_type: Some("fifo".to_string()),
+                soft_capacity: Some(5),
+                hard_capacity: Some(20),
+                ..Default::default()
+            })
+            .to_request();
+        let resp: CreateCacheResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.capacity, 20);
+    }
+
+    #[actix_web::test]
+    async fn test_cache_age() {
+        let clock = ManualClock::new();
+        let app = create_app!(Config {
+            clock: clock.clone(),
+            ..Config::default()
+        });
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ..Default::default()
+            })
+            .to_request();
+        let created: CreateCacheResponse = test::call_and_read_body_json(&app, req).await;
+
+        clock.advance(Duration::from_millis(1500));
+        let req = test::TestRequest::get()
+            .uri("/cache/test/stats")
+            .to_request();
+        let stats: StatsResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(stats.created_at, created.created_at);
+        assert_eq!(stats.age_seconds, created.age_seconds + 1.5);
+    }
+
+    #[actix_web::test]
+    async fn test_create_cache_already_exists() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ttl: None,
+                check_interval: None,
+                jitter: None,
+                ..Default::default()
+            })
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ttl: None,
+                check_interval: None,
+                jitter: None,
+                ..Default::default()
+            })
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 409);
+    }
+
+    #[actix_web::test]
+    async fn test_delete_cache() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ttl: None,
+                check_interval: None,
+                jitter: None,
+                ..Default::default()
+            })
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        for key in ["a", "b"] {
+            let req = test::TestRequest::put()
+                .uri(&format!("/cache/test/{}", key))
+                .set_payload("value")
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+        let req = test::TestRequest::get().uri("/cache/test/a").to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::post()
+            .uri("/cache/delete")
+            .set_json(&DeleteCacheRequest {
+                name: "test".to_string(),
+            })
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        let stats: StatsResponse = test::read_body_json(resp).await;
+        assert_eq!((stats.size, stats.capacity), (2, 10));
+        assert_eq!((stats.hits, stats.gets, stats.sets), (1, 1, 2));
+    }
+
+    #[actix_web::test]
+    async fn test_list_cache_types() {
+        let app = create_app!();
+
+        let req = test::TestRequest::get().uri("/cache/types").to_request();
+        let types: Vec<String> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(types, vec!["lru", "fifo", "mru", "ttl"]);
+
+        // Every listed type can be created, and nothing else.
+        for cache_type in types.iter().map(String::as_str).chain(["lfu"]) {
+            let req = test::TestRequest::post()
+                .uri("/cache/create")
+                .set_json(&CreateCacheRequest {
+                    name: cache_type.to_string(),
+                    cache_type: Some(cache_type.to_string()),
+                    capacity: 10,
+                    ttl: (cache_type == "ttl").then_some(60.0),
+                    ..Default::default()
+                })
+                .to_request();
+            let status = test::call_service(&app, req).await.status();
+            assert_eq!(status.is_success(), types.iter().any(|t| t == cache_type));
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_list_caches() {
+        let app = create_app!();
+
+        for (name, cache_type) in [("sessions", "ttl"), ("users", "lru")] {
+            let req = test::TestRequest::post()
+                .uri("/cache/create")
+                .set_json(&CreateCacheRequest {
+                    name: name.to_string(),
+                    cache_type: Some(cache_type.to_string()),
+                    capacity: 10,
+                    ..Default::default()
+                })
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+
+        let req = test::TestRequest::get().uri("/cache/list").to_request();
+        let resp: Vec<CacheListEntry> = test::call_and_read_body_json(&app, req).await;
+        let flags: Vec<(&str, &str, bool)> = resp
+            .iter()
+            .map(|c| (c.name.as_str(), c.cache_type.as_str(), c.is_ttl))
+            .collect();
+        assert_eq!(
+            flags,
+            vec![("sessions", "ttl", true), ("users", "lru", false)]
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_list_caches_bytes_and_last_modified() {
+        let app = create_app!();
+
+        for name in ["empty", "written"] {
+            let req = test::TestRequest::post()
+                .uri("/cache/create")
+                .set_json(&CreateCacheRequest {
+                    name: name.to_string(),
+                    cache_type: Some("lru".to_string()),
+                    capacity: 10,
+                    ..Default::default()
+                })
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+        let req = test::TestRequest::get().uri("/cache/list").to_request();
+        let before: Vec<CacheListEntry> = test::call_and_read_body_json(&app, req).await;
+
+        actix_web::rt::time::sleep(Duration::from_millis(5)).await;
+        for (key, value) in [("a", "12345"), ("bc", "678")] {
+            let req = test::TestRequest::put()
+                .uri(&format!("/cache/written/{}", key))
+                .set_payload(value)
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+
+        let req = test::TestRequest::get().uri("/cache/list").to_request();
+        let after: Vec<CacheListEntry> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(after[0].name, "empty");
+        assert_eq!(after[0].bytes, 0);
+        assert_eq!(after[0].last_modified, before[0].last_modified);
+        assert_eq!(after[1].name, "written");
+        assert_eq!(after[1].bytes, 11);
+        assert!(after[1].last_modified > before[1].last_modified);
+    }
+
+    #[actix_web::test]
+    async fn test_delete_cache_not_found() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/delete")
+            .set_json(&DeleteCacheRequest {
+                name: "test".to_string(),
+            })
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 404);
+    }
+
+    #[actix_web::test]
+    async fn test_get_value() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ttl: None,
+                check_interval: None,
+                jitter: None,
+                ..Default::default()
+            })
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .set_payload("value")
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let req = test::TestRequest::get().uri("/cache/test/key").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(
+            resp.headers().get("content-type"),
+            Some(&HeaderValue::from_static("application/octet-stream"))
+        );
+        assert_eq!(
+            resp.headers().get("accept-ranges"),
+            Some(&HeaderValue::from_static("bytes"))
+        );
+        let body = test::read_body(resp).await;
+        assert_eq!(body.as_ref(), b"value");
+    }
+
+    #[actix_web::test]
+    async fn test_get_value_accept_charset() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::put()
+            .uri("/cache/test/text")
+            .set_payload("héllo")
+            .to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::put()
+            .uri("/cache/test/binary")
+            .set_payload(vec![0xff, 0xfe])
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/text")
+            .insert_header(("accept-charset", "utf-8, iso-8859-1;q=0.5"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.headers().get("content-type"),
+            Some(&HeaderValue::from_static("text/plain; charset=utf-8"))
+        );
+        assert_eq!(
+            resp.headers().get("vary"),
+            Some(&HeaderValue::from_static("Accept-Charset"))
+        );
+        assert_eq!(test::read_body(resp).await, "héllo");
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/text")
+            .insert_header(("accept-charset", "utf-8;q=0"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.headers().get("content-type"),
+            Some(&HeaderValue::from_static("application/octet-stream"))
+        );
+        assert_eq!(
+            resp.headers().get("vary"),
+            Some(&HeaderValue::from_static("Accept-Charset"))
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/binary")
+            .insert_header(("accept-charset", "utf-8"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.headers().get("content-type"),
+            Some(&HeaderValue::from_static("application/octet-stream"))
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_value_flags() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .insert_header(("x-cache-flags", "4294967295"))
+            .set_payload("value")
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+        let req = test::TestRequest::get().uri("/cache/test/key").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.headers().get("x-cache-flags"),
+            Some(&HeaderValue::from_static("4294967295"))
+        );
+
+        // Overwriting without flags clears them.
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .set_payload("value")
+            .to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::get().uri("/cache/test/key").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.headers().get("x-cache-flags"),
+            Some(&HeaderValue::from_static("0"))
+        );
+
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .insert_header(("x-cache-flags", "-1"))
+            .set_payload("value")
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+    }
+
+    #[actix_web::test]
+    async fn test_value_meta() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let meta = serde_json::json!({"owner": "alice", "tags": ["a", "b"]});
+        let encoded = BASE64_STANDARD.encode(meta.to_string());
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .insert_header(("x-cache-meta", encoded.as_str()))
+            .set_payload("value")
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/key/meta")
+            .to_request();
+        let stored: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(stored, meta);
+        let req = test::TestRequest::get()
+            .uri("/cache/test/key?meta=true")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let echoed = resp.headers().get("x-cache-meta").unwrap().as_bytes();
+        let echoed: serde_json::Value =
+            serde_json::from_slice(&BASE64_STANDARD.decode(echoed).unwrap()).unwrap();
+        assert_eq!(echoed, meta);
+        let req = test::TestRequest::get().uri("/cache/test/key").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.headers().get("x-cache-meta").is_none());
+
+        // Each write replaces the metadata, and missing keys have none to show.
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .set_payload("value")
+            .to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::get()
+            .uri("/cache/test/key/meta")
+            .to_request();
+        assert_eq!(test::call_and_read_body(&app, req).await, "null");
+        let req = test::TestRequest::get()
+            .uri("/cache/test/missing/meta")
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+        let too_big = serde_json::json!({ "pad": "x".repeat(4096) });
+        for header in [
+            BASE64_STANDARD.encode(too_big.to_string()),
+            BASE64_STANDARD.encode("not json"),
+            "not base64!".to_string(),
+        ] {
+            let req = test::TestRequest::put()
+                .uri("/cache/test/key")
+                .insert_header(("x-cache-meta", header.as_str()))
+                .set_payload("value")
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 400);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_value_priority() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 3,
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::put()
+            .uri("/cache/test/keep")
+            .insert_header(("x-cache-priority", "10"))
+            .set_payload("value")
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+        for i in 0..5 {
+            let req = test::TestRequest::put()
+                .uri(&format!("/cache/test/key{}", i))
+                .set_payload("value")
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 200);
+        }
+
+        // The oldest key survives; normal keys were evicted around it.
+        for (key, status) in [("keep", 200), ("key0", 404), ("key2", 404), ("key4", 200)] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/cache/test/{}", key))
+                .to_request();
+            assert_eq!(
+                test::call_service(&app, req).await.status(),
+                status,
+                "{}",
+                key
+            );
+        }
+
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .insert_header(("x-cache-priority", "high"))
+            .set_payload("value")
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+    }
+
+    #[actix_web::test]
+    async fn test_get_or_set() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+
+        // Absent: the default is stored and returned.
+        let req = test::TestRequest::post()
+            .uri("/cache/test/key/get-or-set")
+            .set_payload("default")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 201);
+        assert_eq!(test::read_body(resp).await, "default");
+
+        // Present: the existing value wins over the new default.
+        let req = test::TestRequest::post()
+            .uri("/cache/test/key/get-or-set")
+            .set_payload("other")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(test::read_body(resp).await, "default");
+
+        let req = test::TestRequest::get().uri("/cache/test/key").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(test::read_body(resp).await, "default");
+    }
+
+    #[actix_web::test]
+    async fn test_spill_large_values() {
+        let spill_dir =
+            std::env::temp_dir().join(format!("cachers-spill-test-{}", std::process::id()));
+        let app = create_app!(Config {
+            spill_dir: spill_dir.to_str().unwrap().to_string(),
+            ..Default::default()
+        });
+        let spilled = || match std::fs::read_dir(&spill_dir) {
+            Ok(entries) => entries.count(),
+            Err(_) => 0,
+        };
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("fifo".to_string()),
+                capacity: 2,
+                spill_threshold_bytes: Some(8),
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let big = vec![7u8; 64];
+        let req = test::TestRequest::put()
+            .uri("/cache/test/big")
+            .set_payload(big.clone())
+            .to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::put()
+            .uri("/cache/test/small")
+            .set_payload("tiny")
+            .to_request();
+        test::call_service(&app, req).await;
+        assert_eq!(spilled(), 1);
+
+        let req = test::TestRequest::get().uri("/cache/test/big").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(test::read_body(resp).await.as_ref(), big.as_slice());
+
+        // A third key evicts "big" (fifo), which removes its spill file.
+        let req = test::TestRequest::put()
+            .uri("/cache/test/other")
+            .set_payload("tiny")
+            .to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::get().uri("/cache/test/big").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 404);
+        assert_eq!(spilled(), 0);
+
+        let _ = std::fs::remove_dir(&spill_dir);
+    }
+
+    #[actix_web::test]
+    async fn test_get_value_content_type_override() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::put()
+            .uri("/cache/test/logo")
+            .set_payload(b"\x89PNG\r\n\x1a\n".to_vec())
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/logo?content_type=image/png")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(
+            resp.headers().get("content-type"),
+            Some(&HeaderValue::from_static("image/png"))
+        );
+
+        for bad in ["png", "image/*", "image%2Fpng%3B%3B"] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/cache/test/logo?content_type={}", bad))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 400, "{}", bad);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_get_value_range() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ttl: None,
+                check_interval: None,
+                jitter: None,
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .set_payload("0123456789")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/key")
+            .insert_header(("range", "bytes=2-5"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 206);
+        assert_eq!(
+            resp.headers().get("content-range"),
+            Some(&HeaderValue::from_static("bytes 2-5/10"))
+        );
+        let body = test::read_body(resp).await;
+        assert_eq!(body.as_ref(), b"2345");
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/key")
+            .insert_header(("range", "bytes=20-30"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 416);
+
+        let req = test::TestRequest::default()
+            .method(actix_web::http::Method::HEAD)
+            .uri("/cache/test/key")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(
+            resp.headers().get("accept-ranges"),
+            Some(&HeaderValue::from_static("bytes"))
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_get_value_not_found() {
+        let app = create_app!();
+
+        let req = test::TestRequest::get().uri("/cache/test/key").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 404);
+    }
+
+    #[actix_web::test]
+    async fn test_not_found_codes() {
+        let app = create_app!();
+
+        let req = test::TestRequest::get().uri("/cache/test/key").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 404);
+        let err: ErrorResponse = test::read_body_json(resp).await;
+        assert_eq!(err.code, "cache_not_found");
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ttl: None,
+                check_interval: None,
+                jitter: None,
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let req = test::TestRequest::get().uri("/cache/test/key").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 404);
+        let err: ErrorResponse = test::read_body_json(resp).await;
+        assert_eq!(err.code, "key_not_found");
+    }
+
+    #[actix_web::test]
+    async fn test_mset() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::post()
+            .uri("/cache/test/mset")
+            .set_json(serde_json::json!({"entries": [
+                {"key": "a", "value": BASE64_STANDARD.encode("1")},
+                {"key": "b", "value": BASE64_STANDARD.encode("2")},
+            ]}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        let resp: CountResponse = test::read_body_json(resp).await;
+        assert_eq!(resp.count, 2);
+
+        let req = test::TestRequest::get().uri("/cache/test/b").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(test::read_body(resp).await, "2");
+    }
+
+    #[actix_web::test]
+    async fn test_mset_invalid_entry() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let cases = [
+            (
+                serde_json::json!({"key": "c", "value": "not base64!"}),
+                "entries[1] (key \"c\"): value is not valid base64",
+            ),
+            (
+                serde_json::json!({"key": "c", "value": 3}),
+                "entries[1] (key \"c\"): invalid type",
+            ),
+            (
+                serde_json::json!({"value": "Mw=="}),
+                "entries[1]: missing field `key`",
+            ),
+        ];
+        for (bad, expected) in cases {
+            let req = test::TestRequest::post()
+                .uri("/cache/test/mset")
+                .set_json(serde_json::json!({"entries": [
+                    {"key": "a", "value": BASE64_STANDARD.encode("1")},
+                    bad,
+                ]}))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 400);
+            let err: ErrorResponse = test::read_body_json(resp).await;
+            assert_eq!(err.code, "bad_request");
+            assert!(err.error.contains(expected), "{}", err.error);
+        }
+
+        // Nothing is stored when any entry is bad.
+        let req = test::TestRequest::get().uri("/cache/test/a").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 404);
+    }
+
+    #[actix_web::test]
+    async fn test_get_value_uncounted() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .set_payload("value")
+            .to_request();
+        test::call_service(&app, req).await;
+        let hits_and_misses = || async {
+            let req = test::TestRequest::get()
+                .uri("/cache/test/stats")
+                .to_request();
+            let stats: StatsResponse = test::call_and_read_body_json(&app, req).await;
+            (stats.hits, stats.misses)
+        };
+
+        for (uri, status) in [
+            ("/cache/test/missing?count=false", 404),
+            ("/cache/test/key?count=false", 200),
+        ] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), status);
+        }
+        assert_eq!(hits_and_misses().await, (0, 0));
+
+        for (uri, status) in [("/cache/test/missing", 404), ("/cache/test/key", 200)] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), status);
+        }
+        assert_eq!(hits_and_misses().await, (1, 1));
+    }
+
+    #[actix_web::test]
+    async fn test_histogram() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+        for (i, len) in [0, 1, 3, 4, 5, 100].into_iter().enumerate() {
+            let req = test::TestRequest::put()
+                .uri(&format!("/cache/test/k{}", i))
+                .set_payload(vec![b'x'; len])
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+        let counts = |uri: &'static str| {
+            let app = &app;
+            async move {
+                let req = test::TestRequest::get().uri(uri).to_request();
+                let resp: HistogramResponse = test::call_and_read_body_json(app, req).await;
+                resp.buckets
+                    .into_iter()
+                    .map(|b| (b.le, b.count))
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        assert_eq!(
+            counts("/cache/test/histogram").await,
+            vec![
+                (Some(1), 2),
+                (Some(2), 0),
+                (Some(4), 2),
+                (Some(8), 1),
+                (Some(16), 0),
+                (Some(32), 0),
+                (Some(64), 0),
+                (Some(128), 1),
+                (None, 0),
+            ]
+        );
+        assert_eq!(
+            counts("/cache/test/histogram?buckets=4,64").await,
+            vec![(Some(4), 4), (Some(64), 1), (None, 1)]
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/histogram?buckets=64,4")
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+    }
+
+    #[actix_web::test]
+    async fn test_changes() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+        let put = |key: &'static str, value: &'static str| {
+            test::TestRequest::put()
+                .uri(&format!("/cache/test/{}", key))
+                .set_payload(value)
+                .to_request()
+        };
+
+        for (key, value) in [("a", "1"), ("b", "2")] {
+            test::call_service(&app, put(key, value)).await;
+        }
+        actix_web::rt::time::sleep(Duration::from_millis(5)).await;
+        let since = unix_millis(SystemTime::now());
+        actix_web::rt::time::sleep(Duration::from_millis(5)).await;
+        for (key, value) in [("c", "3"), ("a", "4")] {
+            test::call_service(&app, put(key, value)).await;
+        }
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/cache/test/changes?since={}", since))
+            .to_request();
+        let resp: ChangesResponse = test::call_and_read_body_json(&app, req).await;
+        let keys: Vec<&str> = resp.changes.iter().map(|c| c.key.as_str()).collect();
+        assert_eq!(keys, vec!["c", "a"]);
+        assert!(resp.changes.iter().all(|c| c.modified > since));
+        assert!(resp.changes.iter().all(|c| c.value.is_none()));
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/cache/test/changes?since={}&values=true", since))
+            .to_request();
+        let resp: ChangesResponse = test::call_and_read_body_json(&app, req).await;
+        let values: Vec<Option<String>> = resp.changes.into_iter().map(|c| c.value).collect();
+        assert_eq!(
+            values,
+            vec![
+                Some(BASE64_STANDARD.encode("3")),
+                Some(BASE64_STANDARD.encode("4"))
+            ]
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/changes")
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+    }
+
+    #[actix_web::test]
+    async fn test_mset_atomic() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                soft_capacity: Some(2),
+                hard_capacity: Some(2),
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+        let entries = |keys: &[&str]| -> Vec<serde_json::Value> {
+            keys.iter()
+                .map(|key| serde_json::json!({"key": key, "value": BASE64_STANDARD.encode(key)}))
+                .collect()
+        };
+        let size = || async {
+            let req = test::TestRequest::get()
+                .uri("/cache/test/stats")
+                .to_request();
+            let stats: StatsResponse = test::call_and_read_body_json(&app, req).await;
+            stats.size
+        };
+
+        // A bad entry rejects the whole batch.
+        let mut bad = entries(&["a"]);
+        bad.push(serde_json::json!({"key": "b", "value": "not base64!"}));
+        let req = test::TestRequest::post()
+            .uri("/cache/test/mset")
+            .set_json(serde_json::json!({"entries": bad, "atomic": true}))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+        assert_eq!(size().await, 0);
+
+        // So does a batch the hard capacity can't hold, where a plain mset stores a prefix.
+        let req = test::TestRequest::post()
+            .uri("/cache/test/mset")
+            .set_json(serde_json::json!({"entries": entries(&["a", "b", "c"]), "atomic": true}))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 507);
+        assert_eq!(size().await, 0);
+        let req = test::TestRequest::post()
+            .uri("/cache/test/mset")
+            .set_json(serde_json::json!({"entries": entries(&["a", "b", "c"])}))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 507);
+        assert_eq!(size().await, 2);
+
+        // Overwrites don't need room, so this fits.
+        let req = test::TestRequest::post()
+            .uri("/cache/test/mset")
+            .set_json(serde_json::json!({"entries": entries(&["a", "b", "a"]), "atomic": true}))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+        assert_eq!(size().await, 2);
+    }
+
+    #[actix_web::test]
+    async fn test_set_value_if_none_match() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .insert_header(("if-none-match", "*"))
+            .set_payload("first")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 201);
+
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .insert_header(("if-none-match", "*"))
+            .set_payload("second")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 412);
+        let err: ErrorResponse = test::read_body_json(resp).await;
+        assert_eq!(err.code, "precondition_failed");
+
+        let req = test::TestRequest::get().uri("/cache/test/key").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(test::read_body(resp).await, "first");
+    }
+
+    #[actix_web::test]
+    async fn test_set_value_if_match() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .insert_header(("if-match", "*"))
+            .set_payload("value")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 412);
+
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .set_payload("value")
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .insert_header(("if-match", "*"))
+            .set_payload("updated")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let req = test::TestRequest::get().uri("/cache/test/key").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(test::read_body(resp).await, "updated");
+    }
+
+    #[actix_web::test]
+    async fn test_delete_many() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+        for key in ["a", "b", "c"] {
+            let req = test::TestRequest::put()
+                .uri(&format!("/cache/test/{}", key))
+                .set_payload("value")
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+
+        let req = test::TestRequest::post()
+            .uri("/cache/test/delete-many")
+            .set_json(serde_json::json!({"keys": ["a", "missing", "c", "a"]}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        let resp: CountResponse = test::read_body_json(resp).await;
+        assert_eq!(resp.count, 2);
+
+        for (key, status) in [("a", 404), ("b", 200), ("c", 404)] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/cache/test/{}", key))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), status, "{}", key);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_mget() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ttl: None,
+                check_interval: None,
+                jitter: None,
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        for key in ["a", "c"] {
+            let req = test::TestRequest::put()
+                .uri(&format!("/cache/test/{}", key))
+                .set_payload(key.to_uppercase())
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+        }
+
+        let keys = MGetRequest {
+            keys: vec!["a".to_string(), "b".to_string(), "c".to_string()],
+        };
+
+        let req = test::TestRequest::post()
+            .uri("/cache/test/mget")
+            .set_json(&keys)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        let found: BTreeMap<String, String> = test::read_body_json(resp).await;
+        assert_eq!(found.len(), 2);
+        assert_eq!(found["a"], BASE64_STANDARD.encode("A"));
+        assert_eq!(found["c"], BASE64_STANDARD.encode("C"));
+
+        let req = test::TestRequest::post()
+            .uri("/cache/test/mget?ordered=true")
+            .set_json(&keys)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        let ordered: Vec<Option<String>> = test::read_body_json(resp).await;
+        assert_eq!(
+            ordered,
+            vec![
+                Some(BASE64_STANDARD.encode("A")),
+                None,
+                Some(BASE64_STANDARD.encode("C")),
+            ]
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_empty_key_rejected() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ttl: None,
+                check_interval: None,
+                jitter: None,
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let req = test::TestRequest::put()
+            .uri("/cache/test/")
+            .set_payload("value")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+        let err: ErrorResponse = test::read_body_json(resp).await;
+        assert_eq!(err.code, "invalid_key");
+
+        let req = test::TestRequest::get().uri("/cache/test/").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+    }
+
+    #[actix_web::test]
+    async fn test_set_value() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ttl: None,
+                check_interval: None,
+                jitter: None,
+                ..Default::default()
+            })
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .set_payload("value")
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+    }
+
+    #[actix_web::test]
+    async fn test_set_value_return_old() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key?return_old=true")
+            .set_payload("first")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 204);
+        assert!(test::read_body(resp).await.is_empty());
+
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key?return_old=true")
+            .set_payload("second")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(test::read_body(resp).await, "first");
+
+        let req = test::TestRequest::get().uri("/cache/test/key").to_request();
+        assert_eq!(test::call_and_read_body(&app, req).await, "second");
+
+        // Reading the old value doesn't count as a hit or a miss.
+        let req = test::TestRequest::get()
+            .uri("/cache/test/stats")
+            .to_request();
+        let stats: StatsResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!((stats.hits, stats.misses), (1, 0));
+    }
+
+    #[actix_web::test]
+    async fn test_set_value_gzip() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ttl: None,
+                check_interval: None,
+                jitter: None,
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"compressed value").unwrap();
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .insert_header(("content-encoding", "gzip"))
+            .set_payload(encoder.finish().unwrap())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let req = test::TestRequest::get().uri("/cache/test/key").to_request();
+        let resp = test::call_service(&app, req).await;
+        let body = test::read_body(resp).await;
+        assert_eq!(body.as_ref(), b"compressed value");
+
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .insert_header(("content-encoding", "gzip"))
+            .set_payload("not gzip at all")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+    }
+
+    #[actix_web::test]
+    async fn test_set_value_gzip_size_cap() {
+        let state = web::Data::new(AppState::new(Config::default()));
+        let config = CreateCacheRequest {
+            name: "test".to_string(),
+            cache_type: Some("lru".to_string()),
+            capacity: 10,
+            ttl: None,
+            check_interval: None,
+            jitter: None,
+            ..Default::default()
+        };
+        let cache =
+            CacheEntry::new(build_cache(&config).unwrap(), &config, &Config::default()).unwrap();
+        state.insert_cache("test".to_string(), cache).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(state)
+                .app_data(web::PayloadConfig::new(1024))
+                .route("/cache/{cache_name}/{key:[^/]*}", web::put().to(set_value)),
+        )
+        .await;
+
+        // A tiny upload that inflates far beyond the configured limit.
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&vec![0u8; 1024 * 1024]).unwrap();
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .insert_header(("content-encoding", "gzip"))
+            .set_payload(encoder.finish().unwrap())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 413);
+    }
+
+    #[actix_web::test]
+    async fn test_delete_value() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ttl: None,
+                check_interval: None,
+                jitter: None,
+                ..Default::default()
+            })
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .set_payload("value")
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let req = test::TestRequest::delete()
+            .uri("/cache/test/key")
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+    }
+
+    #[actix_web::test]
+    async fn test_verify() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 3,
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+        // Overwrites, evictions (around a prioritised key) and deletes.
+        let req = test::TestRequest::put()
+            .uri("/cache/test/keep")
+            .insert_header(("x-cache-priority", "1"))
+            .set_payload("value")
+            .to_request();
+        test::call_service(&app, req).await;
+        for (key, value) in [
+            ("a", "1"),
+            ("b", "22"),
+            ("a", "333"),
+            ("c", "4"),
+            ("d", "5"),
+        ] {
+            let req = test::TestRequest::put()
+                .uri(&format!("/cache/test/{}", key))
+                .set_payload(value)
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+        let req = test::TestRequest::delete()
+            .uri("/cache/test/d")
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::post()
+            .uri("/cache/test/verify")
+            .to_request();
+        let resp: VerifyResponse = test::call_and_read_body_json(&app, req).await;
+        assert!(resp.ok, "{:?}", resp.discrepancies);
+        assert_eq!(resp.keys, 2);
+        assert!(resp.discrepancies.is_empty());
+
+        // Verifying doesn't count as hits.
+        let req = test::TestRequest::get()
+            .uri("/cache/test/stats")
+            .to_request();
+        let stats: StatsResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(stats.hits, 0);
+    }
+
+    #[actix_web::test]
+    async fn test_dump_ndjson_backpressure() {
+        use actix_web::body::MessageBody;
+
+        let app = create_app!(Config {
+            dump_chunk_bytes: 1,
+            ..Config::default()
+        });
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("fifo".to_string()),
+                capacity: 1000,
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+        let entries: Vec<MSetEntry> = (0..1000)
+            .map(|i| MSetEntry {
+                key: format!("key{:04}", i),
+                value: BASE64_STANDARD.encode("value"),
+            })
+            .collect();
+        let req = test::TestRequest::post()
+            .uri("/cache/test/mset")
+            .set_json(serde_json::json!({ "entries": entries }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+        let hits = || async {
+            let req = test::TestRequest::get()
+                .uri("/cache/test/stats")
+                .to_request();
+            let stats: StatsResponse = test::call_and_read_body_json(&app, req).await;
+            stats.hits
+        };
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/dump.ndjson")
+            .to_request();
+        let mut body = std::pin::pin!(test::call_service(&app, req).await.into_body());
+        let first = future::poll_fn(|cx| body.as_mut().poll_next(cx)).await;
+        assert!(first.unwrap().unwrap().starts_with(b"{\"key\":\"key0000\""));
+        // A stalled client holds the dump to a few chunks' worth of reads.
+        actix_web::rt::time::sleep(Duration::from_millis(50)).await;
+        let read_ahead = hits().await;
+        assert!(
+            read_ahead <= DUMP_BUFFERED_CHUNKS as u64 + 2,
+            "{}",
+            read_ahead
+        );
+
+        let rest = actix_web::body::to_bytes(body).await.unwrap();
+        assert_eq!(std::str::from_utf8(&rest).unwrap().lines().count(), 999);
+        assert_eq!(hits().await, 1000);
+    }
+
+    #[actix_web::test]
+    async fn test_dump_ndjson() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        for (key, value) in [("a", "1"), ("b", "2"), ("c", "3")] {
+            let req = test::TestRequest::put()
+                .uri(&format!("/cache/test/{}", key))
+                .set_payload(value)
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+        }
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/dump.ndjson")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(
+            resp.headers().get("content-type"),
+            Some(&HeaderValue::from_static("application/x-ndjson"))
+        );
+        let body = test::read_body(resp).await;
+        let entries: Vec<DumpEntry> = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let entries: Vec<(String, Vec<u8>)> = entries
+            .into_iter()
+            .map(|e| (e.key, BASE64_STANDARD.decode(e.value).unwrap()))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                ("a".to_string(), b"1".to_vec()),
+                ("b".to_string(), b"2".to_vec()),
+                ("c".to_string(), b"3".to_vec()),
+            ]
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_soft_and_hard_capacity() {
+        // The test runs the trimmer's sweep itself, so the real one never gets a turn.
+        let state = web::Data::new(AppState::new(Config {
+            trim_interval_ms: 3_600_000,
+            ..Config::default()
+        }));
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(crate::routes::data),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                soft_capacity: Some(2),
+                hard_capacity: Some(3),
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        // A burst grows the cache past the soft limit...
+        for key in ["a", "b", "c"] {
+            let req = test::TestRequest::put()
+                .uri(&format!("/cache/test/{}", key))
+                .set_payload(key.to_string())
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+        }
+
+        // ...but new keys are refused at the hard limit. Existing keys can still be set.
+        let req = test::TestRequest::put()
+            .uri("/cache/test/d")
+            .set_payload("d")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 507);
+        let req = test::TestRequest::put()
+            .uri("/cache/test/c")
+            .set_payload("c")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        // The trimmer then evicts back down to the soft limit. Run its sweep directly
+        // rather than wait for it.
+        assert_eq!(state.get_cache("test").unwrap().trim_to(2), 1);
+        let req = test::TestRequest::get()
+            .uri("/cache/test/keys/count")
+            .to_request();
+        let resp: CountResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.count, 2);
+        let req = test::TestRequest::get().uri("/cache/test/a").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 404);
+
+        let req = test::TestRequest::put()
+            .uri("/cache/test/d")
+            .set_payload("d")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+    }
+
+    #[actix_web::test]
+    async fn test_set_policy() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 3,
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+        for key in ["a", "b", "c"] {
+            let req = test::TestRequest::put()
+                .uri(&format!("/cache/test/{}", key))
+                .set_payload(key.to_string())
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+        // "a" becomes the most recently used.
+        let req = test::TestRequest::get().uri("/cache/test/a").to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::post()
+            .uri("/cache/test/policy")
+            .set_json(serde_json::json!({"type": "fifo"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        let resp: PolicyResponse = test::read_body_json(resp).await;
+        assert_eq!((resp.old.as_str(), resp.new.as_str()), ("lru", "fifo"));
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/stats")
+            .to_request();
+        let resp: StatsResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!((resp.size, resp.capacity, resp.sets), (3, 3, 3));
+
+        // Entries were migrated in recency order, so "b" is now first out.
+        let req = test::TestRequest::put()
+            .uri("/cache/test/d")
+            .set_payload("d")
+            .to_request();
+        test::call_service(&app, req).await;
+        for (key, status) in [("a", 200), ("b", 404), ("c", 200), ("d", 200)] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/cache/test/{}", key))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), status, "{}", key);
+        }
+
+        let req = test::TestRequest::post()
+            .uri("/cache/test/policy")
+            .set_json(serde_json::json!({"type": "lfu"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+    }
+
+    #[actix_web::test]
+    async fn test_reinit() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 3,
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+        for key in ["a", "b", "c"] {
+            let req = test::TestRequest::put()
+                .uri(&format!("/cache/test/{}", key))
+                .set_payload(key.to_string())
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+
+        let req = test::TestRequest::post()
+            .uri("/cache/test/reinit")
+            .set_json(serde_json::json!({"capacity": 5, "type": "fifo"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        let resp: CreateCacheResponse = test::read_body_json(resp).await;
+        assert_eq!(
+            (resp.name.as_str(), resp.cache_type.as_str(), resp.capacity),
+            ("test", "fifo", 5)
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/stats")
+            .to_request();
+        let resp: StatsResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!((resp.size, resp.capacity, resp.sets), (0, 5, 0));
+        let req = test::TestRequest::get().uri("/cache/test/a").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 404);
+
+        for key in ["a", "b", "c", "d", "e", "f"] {
+            let req = test::TestRequest::put()
+                .uri(&format!("/cache/test/{}", key))
+                .set_payload(key.to_string())
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+        let req = test::TestRequest::get()
+            .uri("/cache/test/stats")
+            .to_request();
+        let resp: StatsResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.size, 5);
+
+        // A read-only cache can't be wiped, and stays read-only.
+        let req = test::TestRequest::post()
+            .uri("/cache/test/readonly")
+            .set_json(serde_json::json!({"enabled": true}))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+        let req = test::TestRequest::post()
+            .uri("/cache/test/reinit")
+            .set_json(serde_json::json!({"capacity": 2}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
+        let err: ErrorResponse = test::read_body_json(resp).await;
+        assert_eq!(err.code, "read_only");
+        let req = test::TestRequest::get()
+            .uri("/cache/test/stats")
+            .to_request();
+        let resp: StatsResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!((resp.size, resp.capacity), (5, 5));
+        let req = test::TestRequest::put()
+            .uri("/cache/test/a")
+            .set_payload("changed")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let err: ErrorResponse = test::read_body_json(resp).await;
+        assert_eq!(err.code, "read_only");
+
+        let req = test::TestRequest::post()
+            .uri("/cache/missing/reinit")
+            .set_json(serde_json::json!({"capacity": 5}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 404);
+    }
+
+    #[actix_web::test]
+    async fn test_allowed_ops() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "get_only".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                allowed_ops: Some(vec![CacheOp::Get]),
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let req = test::TestRequest::put()
+            .uri("/cache/get_only/key")
+            .set_payload("value")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
+        let err: ErrorResponse = test::read_body_json(resp).await;
+        assert_eq!(err.code, "operation_not_allowed");
+
+        let req = test::TestRequest::delete()
+            .uri("/cache/get_only/key")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
+
+        let req = test::TestRequest::get()
+            .uri("/cache/get_only/key")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 404);
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "write_only".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                allowed_ops: Some(vec![CacheOp::Set, CacheOp::Delete]),
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::put()
+            .uri("/cache/write_only/key")
+            .set_payload("value")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        // Listing, counting and sizing keys are reads too.
+        for uri in [
+            "/cache/write_only/key",
+            "/cache/write_only/scan",
+            "/cache/write_only/keys/count",
+            "/cache/write_only/histogram",
+        ] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 403, "{}", uri);
+        }
+        for uri in [
+            "/cache/get_only/scan",
+            "/cache/get_only/keys/count",
+            "/cache/get_only/histogram",
+        ] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200, "{}", uri);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_last_modified() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/stats")
+            .to_request();
+        let created: StatsResponse = test::call_and_read_body_json(&app, req).await;
+
+        actix_web::rt::time::sleep(Duration::from_millis(5)).await;
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .set_payload("value")
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/stats")
+            .to_request();
+        let written: StatsResponse = test::call_and_read_body_json(&app, req).await;
+        assert!(written.last_modified > created.last_modified);
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/dump.ndjson")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let last_modified = resp.headers().get("last-modified").unwrap().clone();
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/dump.ndjson")
+            .insert_header(("if-modified-since", last_modified))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 304);
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/dump.ndjson")
+            .insert_header(("if-modified-since", "Mon, 01 Jan 2001 00:00:00 GMT"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+    }
+
+    #[actix_web::test]
+    async fn test_dump_sees_buffered_sets() {
+        let clock = ManualClock::new();
+        let app = create_app!(Config {
+            clock: clock.clone(),
+            ..Config::default()
+        });
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                write_buffer_ms: Some(60_000),
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+        let put = |key: &str| {
+            test::TestRequest::put()
+                .uri(&format!("/cache/test/{}", key))
+                .set_payload("value")
+                .to_request()
+        };
+        test::call_service(&app, put("a")).await;
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/dump.ndjson")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let last_modified = resp.headers().get("last-modified").unwrap().clone();
+
+        // A set that's still queued has changed the cache as far as readers are concerned.
+        clock.advance(Duration::from_secs(2));
+        test::call_service(&app, put("b")).await;
+        let req = test::TestRequest::get()
+            .uri("/cache/test/dump.ndjson")
+            .insert_header(("if-modified-since", last_modified))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        let body = test::read_body(resp).await;
+        assert_eq!(
+            body.split(|b| *b == b'\n')
+                .filter(|l| !l.is_empty())
+                .count(),
+            2
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_strict_query() {
+        for strict_query in [false, true] {
+            let app = create_app!(Config {
+                strict_query,
+                ..Config::default()
+            });
+            let req = test::TestRequest::post()
+                .uri("/cache/create")
+                .set_json(&CreateCacheRequest {
+                    name: "test".to_string(),
+                    cache_type: Some("lru".to_string()),
+                    capacity: 10,
+                    ..Default::default()
+                })
+                .to_request();
+            test::call_service(&app, req).await;
+
+            let req = test::TestRequest::get()
+                .uri("/cache/test/scan?count=5&cusor=abc&limit=1&cusor=def")
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            match strict_query {
+                false => assert_eq!(resp.status(), 200),
+                true => {
+                    assert_eq!(resp.status(), 400);
+                    let body: ErrorResponse = test::read_body_json(resp).await;
+                    assert!(
+                        body.error
+                            .ends_with("unknown query parameters: cusor, limit"),
+                        "{}",
+                        body.error
+                    );
+                }
+            }
+
+            // Known parameters are always accepted.
+            let req = test::TestRequest::get()
+                .uri("/cache/test/scan?count=5")
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 200);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_scan_keys() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let mut expected = Vec::new();
+        for i in 0..7 {
+            let key = format!("key{}", i);
+            let req = test::TestRequest::put()
+                .uri(&format!("/cache/test/{}", key))
+                .set_payload("value")
+                .to_request();
+            test::call_service(&app, req).await;
+            expected.push(key);
+        }
+
+        let mut keys = Vec::new();
+        let mut cursor: Option<String> = None;
+        let mut calls = 0;
+        loop {
+            let uri = match &cursor {
+                Some(cursor) => format!("/cache/test/scan?count=3&cursor={}", cursor),
+                None => "/cache/test/scan?count=3".to_string(),
+            };
+            let req = test::TestRequest::get().uri(&uri).to_request();
+            let resp: ScanResponse = test::call_and_read_body_json(&app, req).await;
+            assert!(resp.keys.len() <= 3);
+            keys.extend(resp.keys);
+            calls += 1;
+            // Deleting a key mid-scan doesn't disturb the rest of it.
+            if calls == 1 {
+                let req = test::TestRequest::delete()
+                    .uri("/cache/test/key1")
+                    .to_request();
+                test::call_service(&app, req).await;
+            }
+            cursor = resp.cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        assert_eq!(keys, expected);
+        assert_eq!(calls, 3);
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/scan?cursor=!!!")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+    }
+
+    #[actix_web::test]
+    async fn test_compact() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("ttl".to_string()),
+                capacity: 10,
+                ttl: Some(50.0),
+                check_interval: Some(10.0),
+                ttl_unit: TtlUnit::Milliseconds,
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+        for key in ["a", "b", "c"] {
+            let req = test::TestRequest::put()
+                .uri(&format!("/cache/test/{}", key))
+                .set_payload("value")
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+        actix_web::rt::time::sleep(Duration::from_millis(100)).await;
+        let req = test::TestRequest::put()
+            .uri("/cache/test/live")
+            .set_payload("value")
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::post()
+            .uri("/cache/test/compact")
+            .to_request();
+        let resp: CompactResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.removed, 3);
+        assert_eq!(resp.bytes_reclaimed, 3 * 6);
+
+        // Only the live key is still tracked, and the probes didn't count as lookups.
+        let req = test::TestRequest::get()
+            .uri("/cache/test/keys/count")
+            .to_request();
+        let resp: CountResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.count, 1);
+        let req = test::TestRequest::get()
+            .uri("/cache/test/stats")
+            .to_request();
+        let resp: StatsResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!((resp.hits, resp.misses), (0, 0));
+    }
+
+    #[actix_web::test]
+    async fn test_count_keys() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ttl: None,
+                check_interval: None,
+                jitter: None,
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        for key in ["user:1", "user:2", "session:1"] {
+            let req = test::TestRequest::put()
+                .uri(&format!("/cache/test/{}", key))
+                .set_payload("value")
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+        }
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/keys/count")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        let count: CountResponse = test::read_body_json(resp).await;
+        assert_eq!(count.count, 3);
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/keys/count?prefix=user:")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let count: CountResponse = test::read_body_json(resp).await;
+        assert_eq!(count.count, 2);
+    }
+
+    #[actix_web::test]
+    async fn test_read_only() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ttl: None,
+                check_interval: None,
+                jitter: None,
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .set_payload("value")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let req = test::TestRequest::post()
+            .uri("/cache/test/readonly")
+            .set_json(&ReadOnlyRequest { enabled: true })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .set_payload("other")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
+        let err: ErrorResponse = test::read_body_json(resp).await;
+        assert_eq!(err.code, "read_only");
+
+        let req = test::TestRequest::delete()
+            .uri("/cache/test/key")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
+
+        let req = test::TestRequest::get().uri("/cache/test/key").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        let body = test::read_body(resp).await;
+        assert_eq!(body.as_ref(), b"value");
+
+        let req = test::TestRequest::post()
+            .uri("/cache/test/readonly")
+            .set_json(&ReadOnlyRequest { enabled: false })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .set_payload("other")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+    }
+
+    #[actix_web::test]
+    async fn test_stats() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ttl: None,
+                check_interval: None,
+                jitter: None,
+                ..Default::default()
+            })
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/stats")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(
+            resp.headers().get("content-type"),
+            Some(&HeaderValue::from_static("application/json"))
+        );
+        let mut body: serde_json::Value = test::read_body_json(resp).await;
+        // The creation time varies from run to run.
+        let body_map = body.as_object_mut().unwrap();
+        let last_modified = body_map.remove("last_modified").unwrap();
+        assert!(last_modified.as_u64().unwrap() > 0);
+        assert_eq!(body_map.remove("created_at").unwrap(), last_modified);
+        assert!(body_map.remove("age_seconds").unwrap().as_f64().unwrap() >= 0.0);
+        assert_eq!(
+            body,
+            serde_json::json!({"hits":0,"misses":0,"size":0,"capacity":10,"utilization":0.0,"near_full":false,"gets":0,"sets":0,"deletes":0,"ttl":null,"check_interval":null,"jitter":null,"version":0,"hit_rate_1m":0.0,"miss_rate_1m":0.0})
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_stats_fields() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::get()
+            .uri("/cache/test/missing")
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/stats?fields=hits,misses")
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body, serde_json::json!({"hits": 0, "misses": 1}));
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/stats?fields=hits,bogus")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+        let body: ErrorResponse = test::read_body_json(resp).await;
+        assert_eq!(body.code, "bad_request");
+    }
+
+    #[actix_web::test]
+    async fn test_stats_long_poll() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::get()
+            .uri("/cache/test/stats")
+            .to_request();
+        let before: StatsResponse = test::call_and_read_body_json(&app, req).await;
+
+        let started = std::time::Instant::now();
+        let poll = test::TestRequest::get()
+            .uri(&format!(
+                "/cache/test/stats?wait=5&since={}",
+                before.version
+            ))
+            .to_request();
+        let set = async {
+            actix_web::rt::time::sleep(Duration::from_millis(50)).await;
+            let req = test::TestRequest::put()
+                .uri("/cache/test/key")
+                .set_payload("value")
+                .to_request();
+            test::call_service(&app, req).await;
+        };
+        let (after, _): (StatsResponse, _) =
+            future::join(test::call_and_read_body_json(&app, poll), set).await;
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(after.version > before.version);
+        assert_eq!(after.size, 1);
+
+        // A stale `since` returns immediately.
+        let started = std::time::Instant::now();
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/cache/test/stats?wait=5&since={}",
+                before.version
+            ))
+            .to_request();
+        test::call_service(&app, req).await;
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[actix_web::test]
+    async fn test_stats_delta() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .set_payload("value")
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::get()
+            .uri("/cache/stats/delta")
+            .to_request();
+        let first: StatsDeltaResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(first.caches["test"].sets, 1);
+
+        for key in ["key", "key", "missing"] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/cache/test/{}", key))
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+        let req = test::TestRequest::put()
+            .uri("/cache/test/other")
+            .set_payload("value")
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/cache/stats/delta?since={}", first.token))
+            .to_request();
+        let second: StatsDeltaResponse = test::call_and_read_body_json(&app, req).await;
+        assert_ne!(second.token, first.token);
+        let delta = &second.caches["test"];
+        assert_eq!((delta.hits, delta.misses, delta.sets), (2, 1, 1));
+
+        let req = test::TestRequest::get()
+            .uri("/cache/stats/delta?since=9999")
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+    }
+
+    #[actix_web::test]
+    async fn test_stats_ttl_settings() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "ttl".to_string(),
+                cache_type: Some("ttl".to_string()),
+                capacity: 10,
+                ttl: Some(1500.0),
+                check_interval: Some(250.0),
+                jitter: Some(100.0),
+                ttl_unit: TtlUnit::Milliseconds,
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "lru".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::get()
+            .uri("/cache/ttl/stats")
+            .to_request();
+        let resp: StatsResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.ttl, Some(1.5));
+        assert_eq!(resp.check_interval, Some(0.25));
+        assert_eq!(resp.jitter, Some(0.1));
+
+        let req = test::TestRequest::get()
+            .uri("/cache/lru/stats")
+            .to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert!(resp["ttl"].is_null());
+        assert!(resp["check_interval"].is_null());
+        assert!(resp["jitter"].is_null());
+    }
+
+    #[actix_web::test]
+    async fn test_stats_near_full() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ttl: None,
+                check_interval: None,
+                jitter: None,
+                ..Default::default()
+            })
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        for i in 0..9 {
+            let req = test::TestRequest::put()
+                .uri(&format!("/cache/test/key{}", i))
+                .set_payload("value")
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+        }
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/stats")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        let stats: StatsResponse = test::read_body_json(resp).await;
+        assert_eq!(stats.size, 9);
+        assert_eq!(stats.utilization, 0.9);
+        assert!(stats.near_full);
+    }
+
+    #[actix_web::test]
+    async fn test_stats_op_counters() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ttl: None,
+                check_interval: None,
+                jitter: None,
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        for key in ["a", "b", "c"] {
+            let req = test::TestRequest::put()
+                .uri(&format!("/cache/test/{}", key))
+                .set_payload("value")
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+        for key in ["a", "a", "missing", "b"] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/cache/test/{}", key))
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+        let req = test::TestRequest::delete()
+            .uri("/cache/test/c")
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/stats")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let stats: StatsResponse = test::read_body_json(resp).await;
+        assert_eq!(stats.sets, 3);
+        assert_eq!(stats.gets, 4);
+        assert_eq!(stats.deletes, 1);
+        assert_eq!(stats.hits, 3);
+        assert_eq!(stats.misses, 1);
+    }
+
+    #[actix_web::test]
+    async fn test_stats_not_found() {
+        let app = create_app!();
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/stats")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 404);
+    }
+
+    #[actix_web::test]
+    async fn test_no_caches() {
+        let app = create_app!();
+
+        for (uri, expected) in [("/cache/list", "[]"), ("/cache/stats", "{}")] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200, "{}", uri);
+            assert_eq!(test::read_body(resp).await, expected, "{}", uri);
+        }
+
+        let req = test::TestRequest::get()
+            .uri("/cache/stats/delta")
+            .to_request();
+        let resp: StatsDeltaResponse = test::call_and_read_body_json(&app, req).await;
+        assert!(resp.caches.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_status() {
+        let app = create_app!();
+
+        for (name, cache_type) in [("users", "lru"), ("sessions", "fifo")] {
+            let req = test::TestRequest::post()
+                .uri("/cache/create")
+                .set_json(&CreateCacheRequest {
+                    name: name.to_string(),
+                    cache_type: Some(cache_type.to_string()),
+                    capacity: 10,
+                    ..Default::default()
+                })
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+        for uri in ["/cache/users/a", "/cache/users/b", "/cache/sessions/c"] {
+            let req = test::TestRequest::put()
+                .uri(uri)
+                .set_payload("value")
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+        for uri in ["/cache/users/a", "/cache/users/x", "/cache/sessions/c"] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            test::call_service(&app, req).await;
+        }
+
+        let req = test::TestRequest::get().uri("/status").to_request();
+        let status: StatusResponse = test::call_and_read_body_json(&app, req).await;
+        assert!(status.uptime_seconds >= 0.0);
+        assert_eq!(status.cache_count, 2);
+        assert_eq!(status.caches[0].name, "sessions");
+        assert_eq!(status.caches[1].name, "users");
+        assert_eq!((status.hits, status.misses), (2, 1));
+
+        let req = test::TestRequest::get().uri("/cache/list").to_request();
+        let list: Vec<CacheListEntry> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(status.bytes, list.iter().map(|c| c.bytes).sum::<u64>());
+        for entry in &status.caches {
+            let req = test::TestRequest::get()
+                .uri(&format!("/cache/{}/stats", entry.name))
+                .to_request();
+            let stats: StatsResponse = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(
+                (entry.size, entry.capacity, entry.hits, entry.misses),
+                (stats.size, stats.capacity, stats.hits, stats.misses)
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_all_stats_pattern() {
+        let app = create_app!();
+
+        for name in ["user-1", "user-2", "session-1", "users"] {
+            let req = test::TestRequest::post()
+                .uri("/cache/create")
+                .set_json(&CreateCacheRequest {
+                    name: name.to_string(),
+                    cache_type: Some("lru".to_string()),
+                    capacity: 10,
+                    ttl: None,
+                    check_interval: None,
+                    jitter: None,
+                    ..Default::default()
+                })
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+        }
+
+        let req = test::TestRequest::get()
+            .uri("/cache/stats?pattern=user-*")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        let all: BTreeMap<String, StatsResponse> = test::read_body_json(resp).await;
+        let names: Vec<&str> = all.keys().map(|k| k.as_str()).collect();
+        assert_eq!(names, vec!["user-1", "user-2"]);
+
+        let req = test::TestRequest::get().uri("/cache/stats").to_request();
+        let resp = test::call_service(&app, req).await;
+        let all: BTreeMap<String, StatsResponse> = test::read_body_json(resp).await;
+        assert_eq!(all.len(), 4);
+    }
+
+    #[actix_web::test]
+    async fn test_idle_key_eviction() {
+        let clock = ManualClock::new();
+        let state = web::Data::new(AppState::new(Config {
+            clock: clock.clone(),
+            ..Config::default()
+        }));
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(crate::routes::data),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                idle_key_ttl: Some(1),
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        for key in ["active", "idle"] {
+            let req = test::TestRequest::put()
+                .uri(&format!("/cache/test/{}", key))
+                .set_payload("value")
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+        }
+
+        // Keep reading one key while the other sits untouched past the idle TTL, then run
+        // the idle sweeper's sweep directly rather than wait for it.
+        for _ in 0..6 {
+            clock.advance(Duration::from_millis(300));
+            let req = test::TestRequest::get()
+                .uri("/cache/test/active")
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+        }
+        state
+            .get_cache("test")
+            .unwrap()
+            .evict_idle(Duration::from_secs(1));
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/idle")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 404);
+    }
+
+    #[actix_web::test]
+    async fn test_unknown_cache_type() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("unknown".to_string()),
+                capacity: 10,
+                ttl: None,
+                check_interval: None,
+                jitter: None,
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+    }
+
+    #[actix_web::test]
+    async fn test_create_cache_default_type() {
+        let app = create_app!(Config {
+            default_cache_type: Some("fifo".to_string()),
+            ..Default::default()
+        });
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(serde_json::json!({"name": "defaulted", "capacity": 10}))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(serde_json::json!({"name": "explicit", "cache_type": "ttl", "capacity": 10}))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+        let req = test::TestRequest::get().uri("/cache/list").to_request();
+        let caches: Vec<CacheListEntry> = test::call_and_read_body_json(&app, req).await;
+        let types: Vec<_> = caches
+            .iter()
+            .map(|c| (c.name.as_str(), c.cache_type.as_str()))
+            .collect();
+        assert_eq!(types, vec![("defaulted", "fifo"), ("explicit", "ttl")]);
+
+        // Without a default the type is required.
+        let app = create_app!();
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(serde_json::json!({"name": "untyped", "capacity": 10}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+        let body: ErrorResponse = test::read_body_json(resp).await;
+        assert_eq!(body.code, "bad_request");
+    }
+
+    #[actix_web::test]
+    async fn test_create_cache_unprocessable() {
+        let app = create_app!();
+
+        let cases = [
+            CreateCacheRequest {
+                name: "lru_with_ttl".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ttl: Some(60.0),
+                ..Default::default()
+            },
+            CreateCacheRequest {
+                name: "fifo_with_jitter".to_string(),
+                cache_type: Some("fifo".to_string()),
+                capacity: 10,
+                jitter: Some(5.0),
+                ..Default::default()
+            },
+            CreateCacheRequest {
+                name: "sliding_lru".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                sliding: true,
+                ..Default::default()
+            },
+            CreateCacheRequest {
+                name: "zero_capacity".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 0,
+                ..Default::default()
+            },
+            CreateCacheRequest {
+                name: "zero_ttl".to_string(),
+                cache_type: Some("ttl".to_string()),
+                capacity: 10,
+                ttl: Some(0.0),
+                ..Default::default()
+            },
+            CreateCacheRequest {
+                name: "sub_millisecond_ttl".to_string(),
+                cache_type: Some("ttl".to_string()),
+                capacity: 10,
+                ttl: Some(0.0004),
+                ..Default::default()
+            },
+            CreateCacheRequest {
+                name: "negative_jitter".to_string(),
+                cache_type: Some("ttl".to_string()),
+                capacity: 10,
+                jitter: Some(-1.0),
+                ..Default::default()
+            },
+            CreateCacheRequest {
+                name: "zero_check_interval".to_string(),
+                cache_type: Some("ttl".to_string()),
+                capacity: 10,
+                check_interval: Some(0.0),
+                ..Default::default()
+            },
+            CreateCacheRequest {
+                name: "soft_without_hard".to_string(),
+                cache_type: Some("lru".to_string()),
+                soft_capacity: Some(5),
+                ..Default::default()
+            },
+            CreateCacheRequest {
+                name: "soft_above_hard".to_string(),
+                cache_type: Some("lru".to_string()),
+                soft_capacity: Some(5),
+                hard_capacity: Some(4),
+                ..Default::default()
+            },
+            CreateCacheRequest {
+                name: "capacity_with_soft_and_hard".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                soft_capacity: Some(5),
+                hard_capacity: Some(10),
+                ..Default::default()
+            },
+            CreateCacheRequest {
+                name: "no_allowed_ops".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                allowed_ops: Some(vec![]),
+                ..Default::default()
+            },
+            CreateCacheRequest {
+                name: "zero_idle_key_ttl".to_string(),
+                cache_type: Some("mru".to_string()),
+                capacity: 10,
+                idle_key_ttl: Some(0),
+                ..Default::default()
+            },
+            CreateCacheRequest {
+                name: "zero_write_buffer".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                write_buffer_ms: Some(0),
+                ..Default::default()
+            },
+            CreateCacheRequest {
+                name: "buffered_hard_capacity".to_string(),
+                cache_type: Some("lru".to_string()),
+                soft_capacity: Some(5),
+                hard_capacity: Some(10),
+                write_buffer_ms: Some(10),
+                ..Default::default()
+            },
+            CreateCacheRequest {
+                name: "orphan_stats_webhook_interval".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                stats_webhook_interval_ms: Some(1000),
+                ..Default::default()
+            },
+        ];
+
+        for case in cases {
+            let req = test::TestRequest::post()
+                .uri("/cache/create")
+                .set_json(&case)
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 422, "{}", case.name);
+            let err: ErrorResponse = test::read_body_json(resp).await;
+            assert_eq!(err.code, "unprocessable");
+        }
+
+        // Malformed JSON is still a plain bad request.
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .insert_header(("content-type", "application/json"))
+            .set_payload(r#"{"name": "test", "capacity": }"#)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+    }
+
+    #[actix_web::test]
+    async fn test_create_cache_stats_webhook() {
+        let app = create_app!(Config {
+            admin_token: Some("secret".to_string()),
+            webhook_allowlist: vec!["127.0.0.1".to_string()],
+            ..Config::default()
+        });
+        let create = |url: &str, interval_ms: Option<u64>| CreateCacheRequest {
+            name: "hooked".to_string(),
+            cache_type: Some("lru".to_string()),
+            capacity: 10,
+            stats_webhook: Some(url.to_string()),
+            stats_webhook_interval_ms: interval_ms,
+            ..Default::default()
+        };
+
+        // Only admins may set a webhook.
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(create("http://127.0.0.1:9/hook", None))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+
+        for (url, interval_ms) in [
+            ("ftp://127.0.0.1/hook", None),
+            ("http://example.com/hook", None),
+            ("http://169.254.169.254/latest/meta-data", None),
+            ("http://localhost:9/hook", None),
+            ("http://127.0.0.1:9/hook", Some(10)),
+        ] {
+            let req = test::TestRequest::post()
+                .uri("/cache/create")
+                .insert_header(("authorization", "Bearer secret"))
+                .set_json(create(url, interval_ms))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 422, "{} {:?}", url, interval_ms);
+        }
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .insert_header(("authorization", "Bearer secret"))
+            .set_json(create("http://127.0.0.1:9/hook", Some(60_000)))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+    }
+
+    #[actix_web::test]
+    async fn test_peek_value() {
+        let app = create_app!(Config {
+            shadow_values: true,
+            ..Config::default()
+        });
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 2,
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+
+        for key in ["a", "b"] {
+            let req = test::TestRequest::put()
+                .uri(&format!("/cache/test/{}", key))
+                .set_payload(key.to_string())
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/a/peek")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(test::read_body(resp).await, "a");
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/missing/peek")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 404);
+
+        // "a" is still least recently used, so it is the one evicted.
+        let req = test::TestRequest::put()
+            .uri("/cache/test/c")
+            .set_payload("c")
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/stats")
+            .to_request();
+        let resp: StatsResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.hits, 0);
+        assert_eq!(resp.gets, 0);
+
+        let req = test::TestRequest::get().uri("/cache/test/a").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 404);
+        let req = test::TestRequest::get().uri("/cache/test/b").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+    }
+
+    #[actix_web::test]
+    async fn test_peek_without_shadowing() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 2,
+                ..Default::default()
+            })
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/a/peek")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 501);
+    }
+
+    #[actix_web::test]
+    async fn test_key_ttl_jitter() {
+        let clock = ManualClock::new();
+        let app = create_app!(Config {
+            clock: clock.clone(),
+            ..Config::default()
+        });
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("ttl".to_string()),
+                capacity: 50,
+                ttl: Some(60.0),
+                jitter: Some(10.0),
+                ..Default::default()
+            })
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+        let mut remaining = Vec::new();
+        for i in 0..50 {
+            let req = test::TestRequest::put()
+                .uri(&format!("/cache/test/key{}", i))
+                .set_payload("value")
+                .to_request();
+            test::call_service(&app, req).await;
+            let req = test::TestRequest::get()
+                .uri(&format!("/cache/test/key{}/ttl", i))
+                .to_request();
+            let ttl: KeyTtlResponse = test::call_and_read_body_json(&app, req).await;
+            let secs = ttl.remaining_seconds.unwrap();
+            assert!((50.0..=70.0).contains(&secs), "{}", secs);
+            let now = unix_millis(clock.now());
+            let expires_at = ttl.expires_at.unwrap();
+            assert!((now + 50_000..=now + 70_000).contains(&expires_at));
+            remaining.push(secs);
+        }
+        assert!(remaining.iter().any(|secs| *secs != remaining[0]));
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/missing/ttl")
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 404);
+    }
+
+    #[actix_web::test]
+    async fn test_get_expired_before_sweep() {
+        let clock = ManualClock::new();
+        let app = create_app!(Config {
+            clock: clock.clone(),
+            ..Config::default()
+        });
+
+        // The cache's own sweep is an hour away, so only the read path can expire the key.
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("ttl".to_string()),
+                capacity: 10,
+                ttl: Some(60.0),
+                check_interval: Some(3600.0),
+                ..Default::default()
+            })
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .set_payload("value")
+            .to_request();
+        test::call_service(&app, req).await;
+
+        clock.advance(Duration::from_secs(59));
+        let req = test::TestRequest::get().uri("/cache/test/key").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+        clock.advance(Duration::from_secs(1));
+        let req = test::TestRequest::get().uri("/cache/test/key").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/stats")
+            .to_request();
+        let stats: StatsResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!((stats.hits, stats.misses, stats.size), (1, 1, 0));
+    }
+
+    #[actix_web::test]
+    async fn test_jittered_expiry() {
+        let clock = ManualClock::new();
+        let app = create_app!(Config {
+            clock: clock.clone(),
+            ..Config::default()
+        });
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("ttl".to_string()),
+                capacity: 10,
+                ttl: Some(60.0),
+                jitter: Some(10.0),
+                check_interval: Some(3600.0),
+                ..Default::default()
+            })
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .set_payload("value")
+            .to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::get()
+            .uri("/cache/test/key/ttl")
+            .to_request();
+        let ttl: KeyTtlResponse = test::call_and_read_body_json(&app, req).await;
+        let remaining = ttl.expires_at.unwrap() - unix_millis(clock.now());
+
+        // The key's own jittered expiry decides, not a second jitter applied by the cache.
+        // `expires_at` is rounded down to the millisecond.
+        clock.advance(Duration::from_millis(remaining - 1));
+        let req = test::TestRequest::get().uri("/cache/test/key").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+        clock.advance(Duration::from_millis(2));
+        let req = test::TestRequest::get().uri("/cache/test/key").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 404);
+    }
+
+    #[actix_web::test]
+    async fn test_ttl_milliseconds() {
+        let clock = ManualClock::new();
+        let app = create_app!(Config {
+            clock: clock.clone(),
+            ..Config::default()
+        });
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("ttl".to_string()),
+                capacity: 10,
+                ttl: Some(2000.0),
+                check_interval: Some(500.0),
+                ttl_unit: TtlUnit::Milliseconds,
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .set_payload("value")
+            .to_request();
+        test::call_service(&app, req).await;
+
+        clock.advance(Duration::from_millis(1999));
+        let req = test::TestRequest::get().uri("/cache/test/key").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        clock.advance(Duration::from_millis(1));
+        let req = test::TestRequest::get().uri("/cache/test/key").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 404);
+    }
+
+    #[actix_web::test]
+    async fn test_sliding_ttl() {
+        let clock = ManualClock::new();
+        let app = create_app!(Config {
+            clock: clock.clone(),
+            ..Config::default()
+        });
+
+        for (name, sliding) in [("sliding", true), ("fixed", false)] {
+            let req = test::TestRequest::post()
+                .uri("/cache/create")
+                .set_json(&CreateCacheRequest {
+                    name: name.to_string(),
+                    cache_type: Some("ttl".to_string()),
+                    capacity: 10,
+                    ttl: Some(2000.0),
+                    check_interval: Some(500.0),
+                    ttl_unit: TtlUnit::Milliseconds,
+                    sliding,
+                    ..Default::default()
+                })
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+
+            let req = test::TestRequest::put()
+                .uri(&format!("/cache/{}/key", name))
+                .set_payload("value")
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+
+        // Reads every second keep the sliding key alive well past its 2s TTL.
+        for _ in 0..4 {
+            clock.advance(Duration::from_millis(1000));
+            let req = test::TestRequest::get()
+                .uri("/cache/sliding/key")
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200);
+        }
+
+        let req = test::TestRequest::get()
+            .uri("/cache/fixed/key")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 404);
+
+        clock.advance(Duration::from_millis(2000));
+        let req = test::TestRequest::get()
+            .uri("/cache/sliding/key")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 404);
+    }
+
+    #[actix_web::test]
+    async fn test_create_require_free_bytes() {
+        let app = create_app!(Config {
+            memory_budget_bytes: Some(100),
+            ..Config::default()
+        });
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "test".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        // 3 bytes of key plus 37 of value.
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .set_payload(vec![0u8; 37])
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "too_big".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                require_free_bytes: Some(61),
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 507);
+
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .set_json(&CreateCacheRequest {
+                name: "fits".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                require_free_bytes: Some(60),
+                ..Default::default()
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+    }
+
+    #[actix_web::test]
+    async fn test_shutdown() {
+        let app = create_app!(Config {
+            admin_token: Some("secret".to_string()),
+            ..Config::default()
+        });
+
+        let req = test::TestRequest::post()
+            .uri("/admin/shutdown")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+
+        let req = test::TestRequest::post()
+            .uri("/admin/shutdown")
+            .insert_header(("authorization", "Bearer wrong"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+
+        // No server handle is set in tests, so this doesn't stop anything.
+        let req = test::TestRequest::post()
+            .uri("/admin/shutdown")
+            .insert_header(("authorization", "Bearer secret"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 202);
+    }
+
+    #[actix_web::test]
+    async fn test_shutdown_without_token() {
+        let app = create_app!();
+
+        let req = test::TestRequest::post()
+            .uri("/admin/shutdown")
+            .insert_header(("authorization", "Bearer "))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+    }
+
+    #[actix_web::test]
+    async fn test_export_import_round_trip() {
+        let config = || Config {
+            admin_token: Some("secret".to_string()),
+            ..Config::default()
+        };
+        let app = create_app!(config());
+
+        for (name, cache_type) in [("lru", "lru"), ("ttl", "ttl")] {
+            let req = test::TestRequest::post()
+                .uri("/cache/create")
+                .set_json(&CreateCacheRequest {
+                    name: name.to_string(),
+                    cache_type: Some(cache_type.to_string()),
+                    capacity: 5,
+                    ..Default::default()
+                })
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+        let req = test::TestRequest::put()
+            .uri("/cache/lru/text")
+            .set_payload("value")
+            .to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::put()
+            .uri("/cache/ttl/binary")
+            .set_payload(vec![0u8, 0xff, 10])
             .to_request();
+        test::call_service(&app, req).await;
 
-        let resp = test::call_service(&mut app, req).await;
-        assert_eq!(resp.status(), 200);
-    }
+        let req = test::TestRequest::get().uri("/admin/export").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
 
-    #[actix_web::test]
-    async fn test_create_cache_already_exists() {
-        let mut app = create_app!();
+        let req = test::TestRequest::get()
+            .uri("/admin/export")
+            .insert_header(("authorization", "Bearer secret"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(
+            resp.headers().get("content-type"),
+            Some(&HeaderValue::from_static("application/x-tar"))
+        );
+        let archive = test::read_body(resp).await;
 
+        let fresh = create_app!(config());
         let req = test::TestRequest::post()
-            .uri("/cache/create")
-            .set_json(&CreateCacheRequest {
-                name: "test".to_string(),
-                cache_type: "lru".to_string(),
-                capacity: 10,
-                ttl: None,
-                check_interval: None,
-                jitter: None,
-            })
+            .uri("/admin/import")
+            .insert_header(("authorization", "Bearer secret"))
+            .set_payload(archive.clone())
             .to_request();
-
-        let resp = test::call_service(&mut app, req).await;
+        let resp = test::call_service(&fresh, req).await;
         assert_eq!(resp.status(), 200);
+        let resp: CountResponse = test::read_body_json(resp).await;
+        assert_eq!(resp.count, 2);
 
-        let req = test::TestRequest::post()
-            .uri("/cache/create")
-            .set_json(&CreateCacheRequest {
-                name: "test".to_string(),
-                cache_type: "lru".to_string(),
-                capacity: 10,
-                ttl: None,
-                check_interval: None,
-                jitter: None,
-            })
+        let req = test::TestRequest::get().uri("/cache/lru/text").to_request();
+        let resp = test::call_service(&fresh, req).await;
+        assert_eq!(test::read_body(resp).await, "value");
+        let req = test::TestRequest::get()
+            .uri("/cache/ttl/binary")
+            .to_request();
+        let resp = test::call_service(&fresh, req).await;
+        assert_eq!(test::read_body(resp).await.as_ref(), &[0u8, 0xff, 10]);
+        let req = test::TestRequest::get()
+            .uri("/cache/ttl/stats")
             .to_request();
+        let resp: StatsResponse = test::call_and_read_body_json(&fresh, req).await;
+        assert_eq!(resp.capacity, 5);
 
-        let resp = test::call_service(&mut app, req).await;
+        // Importing again would clobber the existing caches.
+        let req = test::TestRequest::post()
+            .uri("/admin/import")
+            .insert_header(("authorization", "Bearer secret"))
+            .set_payload(archive)
+            .to_request();
+        let resp = test::call_service(&fresh, req).await;
         assert_eq!(resp.status(), 409);
     }
 
     #[actix_web::test]
-    async fn test_delete_cache() {
-        let mut app = create_app!();
-
+    async fn test_export_import_larger_than_body_limit() {
+        // The server's own app, so its body limit applies as in production.
+        let config = || Config {
+            admin_token: Some("secret".to_string()),
+            max_body_bytes: 4096,
+            dump_chunk_bytes: 1024,
+            ..Config::default()
+        };
+        let routes: fn(&mut web::ServiceConfig) = |cfg| {
+            crate::routes::data(cfg);
+            crate::routes::admin(cfg);
+        };
+        let app =
+            test::init_service(crate::app(web::Data::new(AppState::new(config())), routes)).await;
         let req = test::TestRequest::post()
             .uri("/cache/create")
             .set_json(&CreateCacheRequest {
-                name: "test".to_string(),
-                cache_type: "lru".to_string(),
-                capacity: 10,
-                ttl: None,
-                check_interval: None,
-                jitter: None,
+                name: "big".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 1000,
+                ..Default::default()
             })
             .to_request();
+        test::call_service(&app, req).await;
+        for i in 0..200 {
+            let req = test::TestRequest::put()
+                .uri(&format!("/cache/big/key{}", i))
+                .set_payload(vec![b'a' + (i % 26) as u8; 100])
+                .to_request();
+            test::call_service(&app, req).await;
+        }
 
-        let resp = test::call_service(&mut app, req).await;
-        assert_eq!(resp.status(), 200);
+        let req = test::TestRequest::get()
+            .uri("/admin/export")
+            .insert_header(("authorization", "Bearer secret"))
+            .to_request();
+        let archive = test::call_and_read_body(&app, req).await;
+        assert!(archive.len() > 4 * 4096);
 
+        let fresh =
+            test::init_service(crate::app(web::Data::new(AppState::new(config())), routes)).await;
+        let mut gzipped = GzEncoder::new(Vec::new(), Compression::default());
+        gzipped.write_all(&archive).unwrap();
         let req = test::TestRequest::post()
-            .uri("/cache/delete")
-            .set_json(&DeleteCacheRequest {
-                name: "test".to_string(),
-            })
+            .uri("/admin/import")
+            .insert_header(("authorization", "Bearer secret"))
+            .insert_header(("content-encoding", "gzip"))
+            .set_payload(gzipped.finish().unwrap())
             .to_request();
-
-        let resp = test::call_service(&mut app, req).await;
+        let resp = test::call_service(&fresh, req).await;
         assert_eq!(resp.status(), 200);
+        let req = test::TestRequest::get()
+            .uri("/cache/big/stats")
+            .to_request();
+        let stats: StatsResponse = test::call_and_read_body_json(&fresh, req).await;
+        assert_eq!(stats.size, 200);
+        let req = test::TestRequest::get()
+            .uri("/cache/big/key27")
+            .to_request();
+        let body = test::call_and_read_body(&fresh, req).await;
+        assert_eq!(body.as_ref(), &[b'b'; 100]);
+
+        // The import limit is separate, and still enforced.
+        let limited = test::init_service(crate::app(
+            web::Data::new(AppState::new(Config {
+                max_import_bytes: archive.len() as u64 / 2,
+                ..config()
+            })),
+            routes,
+        ))
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/admin/import")
+            .insert_header(("authorization", "Bearer secret"))
+            .set_payload(archive)
+            .to_request();
+        let resp = test::call_service(&limited, req).await;
+        assert_eq!(resp.status(), 413);
+        let req = test::TestRequest::get()
+            .uri("/cache/big/stats")
+            .to_request();
+        let resp = test::call_service(&limited, req).await;
+        assert_eq!(resp.status(), 404);
     }
 
     #[actix_web::test]
-    async fn test_delete_cache_not_found() {
-        let mut app = create_app!();
+    async fn test_debug_internal() {
+        let app = create_app!(Config {
+            admin_token: Some("secret".to_string()),
+            ..Default::default()
+        });
 
-        let req = test::TestRequest::post()
-            .uri("/cache/delete")
-            .set_json(&DeleteCacheRequest {
-                name: "test".to_string(),
-            })
-            .to_request();
+        for (name, cache_type) in [("lru", "lru"), ("ttl", "ttl")] {
+            let req = test::TestRequest::post()
+                .uri("/cache/create")
+                .set_json(&CreateCacheRequest {
+                    name: name.to_string(),
+                    cache_type: Some(cache_type.to_string()),
+                    capacity: 10,
+                    ..Default::default()
+                })
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+        for key in ["a", "b", "c"] {
+            let req = test::TestRequest::put()
+                .uri(&format!("/cache/lru/{}", key))
+                .set_payload("value")
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+        let req = test::TestRequest::get().uri("/cache/lru/a").to_request();
+        test::call_service(&app, req).await;
 
-        let resp = test::call_service(&mut app, req).await;
-        assert_eq!(resp.status(), 404);
+        let debug = |name: &str| {
+            test::TestRequest::get()
+                .uri(&format!("/debug/caches/{}/internal", name))
+                .insert_header(("authorization", "Bearer secret"))
+                .to_request()
+        };
+        let resp: InternalResponse = test::call_and_read_body_json(&app, debug("lru")).await;
+        assert_eq!(resp.cache_type, "lru");
+        assert_eq!(resp.eviction_order, vec!["b", "c", "a"]);
+
+        let resp = test::call_service(&app, debug("ttl")).await;
+        assert_eq!(resp.status(), 400);
+
+        let req = test::TestRequest::get()
+            .uri("/debug/caches/lru/internal")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
     }
 
     #[actix_web::test]
-    async fn test_get_value() {
-        let mut app = create_app!();
+    async fn test_drain() {
+        // A real server stands in for the peer, since the drain goes over HTTP.
+        let peer_state = web::Data::new(AppState::new(Config::default()));
+        let server_state = peer_state.clone();
+        let peer = actix_web::HttpServer::new(move || {
+            App::new()
+                .app_data(server_state.clone())
+                .app_data(web::JsonConfig::default().limit(1024))
+                .route("/cache/create", web::post().to(create_cache))
+                .route("/cache/{cache_name}/mset", web::post().to(mset))
+        })
+        .workers(1)
+        .bind("127.0.0.1:0")
+        .unwrap();
+        let addr = peer.addrs()[0];
+        let peer = peer.run();
+        let handle = peer.handle();
+        actix_web::rt::spawn(peer);
 
+        let app = create_app!(Config {
+            admin_token: Some("secret".to_string()),
+            webhook_allowlist: vec!["127.0.0.1".to_string()],
+            ..Default::default()
+        });
+        // The name has to be percent-encoded in the peer's URLs.
         let req = test::TestRequest::post()
             .uri("/cache/create")
             .set_json(&CreateCacheRequest {
-                name: "test".to_string(),
-                cache_type: "lru".to_string(),
+                name: "team a?".to_string(),
+                cache_type: Some("fifo".to_string()),
                 capacity: 10,
-                ttl: None,
-                check_interval: None,
-                jitter: None,
+                ..Default::default()
             })
             .to_request();
+        test::call_service(&app, req).await;
+        let big = "x".repeat(2000);
+        for (key, value) in [("a", "1"), ("b", "2"), ("c", "3"), ("big", big.as_str())] {
+            let req = test::TestRequest::put()
+                .uri(&format!("/cache/team%20a%3F/{}", key))
+                .set_payload(value.to_string())
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+        let drain = || {
+            test::TestRequest::post()
+                .uri("/cache/team%20a%3F/drain")
+                .insert_header(("authorization", "Bearer secret"))
+                .set_json(
+                    serde_json::json!({"target": format!("http://{}/", addr), "delete": true}),
+                )
+                .to_request()
+        };
 
-        let resp = test::call_service(&mut app, req).await;
+        // The value over the peer's body limit is reported, and keeps the local cache.
+        let resp = test::call_service(&app, drain()).await;
         assert_eq!(resp.status(), 200);
+        let resp: DrainResponse = test::read_body_json(resp).await;
+        assert_eq!(resp.count, 3);
+        assert_eq!(resp.skipped, vec!["big".to_string()]);
+        assert!(resp.dropped.is_empty());
+        let drained = peer_state.get_cache("team a?").unwrap();
+        assert_eq!(drained.config.cache_type(), "fifo");
+        for (key, value) in [("a", "1"), ("b", "2"), ("c", "3")] {
+            let val = drained.get(&key.to_string()).unwrap().unwrap();
+            assert_eq!(val.as_slice(), value.as_bytes());
+        }
+        assert!(drained.get(&"big".to_string()).unwrap().is_none());
+        let req = test::TestRequest::get()
+            .uri("/cache/team%20a%3F/stats")
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
 
-        let req = test::TestRequest::put()
-            .uri("/cache/test/key")
-            .set_payload("value")
+        let req = test::TestRequest::delete()
+            .uri("/cache/team%20a%3F/big")
+            .to_request();
+        test::call_service(&app, req).await;
+        let resp: DrainResponse = test::call_and_read_body_json(&app, drain()).await;
+        assert_eq!(resp.count, 3);
+        assert!(resp.skipped.is_empty());
+        let req = test::TestRequest::get()
+            .uri("/cache/team%20a%3F/stats")
             .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 404);
 
-        let resp = test::call_service(&mut app, req).await;
+        // The peer would refuse to create a cache with a stats webhook without its admin
+        // token, so the webhook is left behind and reported.
+        let req = test::TestRequest::post()
+            .uri("/cache/create")
+            .insert_header(("authorization", "Bearer secret"))
+            .set_json(&CreateCacheRequest {
+                name: "hooked".to_string(),
+                cache_type: Some("lru".to_string()),
+                capacity: 10,
+                stats_webhook: Some("http://127.0.0.1:9/hook".to_string()),
+                stats_webhook_interval_ms: Some(60_000),
+                ..Default::default()
+            })
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+        let req = test::TestRequest::put()
+            .uri("/cache/hooked/a")
+            .set_payload("1")
+            .to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::post()
+            .uri("/cache/hooked/drain")
+            .insert_header(("authorization", "Bearer secret"))
+            .set_json(serde_json::json!({"target": format!("http://{}/", addr)}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
         assert_eq!(resp.status(), 200);
+        let resp: DrainResponse = test::read_body_json(resp).await;
+        assert_eq!(resp.count, 1);
+        assert_eq!(
+            resp.dropped,
+            vec!["stats_webhook", "stats_webhook_interval_ms"]
+        );
+        let drained = peer_state.get_cache("hooked").unwrap();
+        assert!(drained.config.stats_webhook.is_none());
+        assert_eq!(
+            drained.get(&"a".to_string()).unwrap().as_deref(),
+            Some(&b"1".to_vec())
+        );
+
+        handle.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn test_maintenance() {
+        let app = create_app!(Config {
+            admin_token: Some("secret".to_string()),
+            ..Default::default()
+        });
+        let create = |name: &str| {
+            test::TestRequest::post()
+                .uri("/cache/create")
+                .set_json(&CreateCacheRequest {
+                    name: name.to_string(),
+                    cache_type: Some("lru".to_string()),
+                    capacity: 10,
+                    ..Default::default()
+                })
+                .to_request()
+        };
+        let put = || {
+            test::TestRequest::put()
+                .uri("/cache/test/key")
+                .set_payload("value")
+                .to_request()
+        };
+        let toggle = |enabled: bool| {
+            test::TestRequest::post()
+                .uri("/admin/maintenance")
+                .insert_header(("authorization", "Bearer secret"))
+                .set_json(serde_json::json!({ "enabled": enabled }))
+                .to_request()
+        };
+        test::call_service(&app, create("test")).await;
+        assert_eq!(test::call_service(&app, put()).await.status(), 200);
 
+        assert_eq!(test::call_service(&app, toggle(true)).await.status(), 200);
+        let resp = test::call_service(&app, put()).await;
+        assert_eq!(resp.status(), 503);
+        assert_eq!(
+            resp.headers().get(header::RETRY_AFTER),
+            Some(&HeaderValue::from_static("30"))
+        );
+        let err: ErrorResponse = test::read_body_json(resp).await;
+        assert_eq!(err.code, "maintenance");
+        let req = test::TestRequest::delete()
+            .uri("/cache/test/key")
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 503);
+        assert_eq!(
+            test::call_service(&app, create("other")).await.status(),
+            503
+        );
+        // Reads still work, including get-or-set hits; its misses would write.
         let req = test::TestRequest::get().uri("/cache/test/key").to_request();
-        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(test::call_and_read_body(&app, req).await, "value");
+        let get_or_set = |key: &str| {
+            test::TestRequest::post()
+                .uri(&format!("/cache/test/{}/get-or-set", key))
+                .set_payload("default")
+                .to_request()
+        };
+        let resp = test::call_service(&app, get_or_set("key")).await;
         assert_eq!(resp.status(), 200);
+        assert_eq!(test::read_body(resp).await, "value");
+        let resp = test::call_service(&app, get_or_set("missing")).await;
+        assert_eq!(resp.status(), 503);
+        let req = test::TestRequest::get()
+            .uri("/cache/test/missing")
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+        assert_eq!(test::call_service(&app, toggle(false)).await.status(), 200);
+        assert_eq!(test::call_service(&app, put()).await.status(), 200);
         assert_eq!(
-            resp.headers().get("content-type"),
-            Some(&HeaderValue::from_static("application/octet-stream"))
+            test::call_service(&app, create("other")).await.status(),
+            200
         );
-        let body = test::read_body(resp).await;
-        assert_eq!(body.as_ref(), b"value");
     }
 
     #[actix_web::test]
-    async fn test_get_value_not_found() {
-        let mut app = create_app!();
+    async fn test_config_update() {
+        let app = create_app!(Config {
+            admin_token: Some("secret".to_string()),
+            ..Default::default()
+        });
+        for (name, cache_type) in [
+            ("session-1", "ttl"),
+            ("session-2", "ttl"),
+            ("session-lru", "lru"),
+            ("users", "ttl"),
+        ] {
+            let req = test::TestRequest::post()
+                .uri("/cache/create")
+                .set_json(&CreateCacheRequest {
+                    name: name.to_string(),
+                    cache_type: Some(cache_type.to_string()),
+                    capacity: 10,
+                    ..Default::default()
+                })
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 200);
+        }
+        let req = test::TestRequest::put()
+            .uri("/cache/session-1/key")
+            .set_payload("value")
+            .to_request();
+        test::call_service(&app, req).await;
 
-        let req = test::TestRequest::get().uri("/cache/test/key").to_request();
-        let resp = test::call_service(&mut app, req).await;
-        assert_eq!(resp.status(), 404);
+        let update = |body: serde_json::Value| {
+            test::TestRequest::post()
+                .uri("/admin/config-update")
+                .insert_header(("authorization", "Bearer secret"))
+                .set_json(body)
+                .to_request()
+        };
+        let req = update(serde_json::json!({"pattern": "session-*", "check_interval": 30}));
+        let resp: ConfigUpdateResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.updated, vec!["session-1", "session-2"]);
+
+        for (name, check_interval) in [("session-1", 30.0), ("session-2", 30.0), ("users", 10.0)] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/cache/{}/stats", name))
+                .to_request();
+            let stats: StatsResponse = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(stats.check_interval, Some(check_interval), "{}", name);
+            assert_eq!(stats.ttl, Some(60.0), "{}", name);
+        }
+        let req = test::TestRequest::get()
+            .uri("/cache/session-lru/stats")
+            .to_request();
+        let stats: StatsResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(stats.check_interval, None);
+        let req = test::TestRequest::get()
+            .uri("/cache/session-1/key")
+            .to_request();
+        assert_eq!(test::call_and_read_body(&app, req).await, "value");
+
+        let req = update(serde_json::json!({"pattern": "*"}));
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+        let req = test::TestRequest::post()
+            .uri("/admin/config-update")
+            .set_json(serde_json::json!({"pattern": "*", "ttl": 5}))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 401);
     }
 
     #[actix_web::test]
-    async fn test_set_value() {
-        let mut app = create_app!();
-
+    async fn test_config_update_keeps_expiry() {
+        let clock = ManualClock::new();
+        let app = create_app!(Config {
+            admin_token: Some("secret".to_string()),
+            clock: clock.clone(),
+            ..Default::default()
+        });
         let req = test::TestRequest::post()
             .uri("/cache/create")
             .set_json(&CreateCacheRequest {
-                name: "test".to_string(),
-                cache_type: "lru".to_string(),
+                name: "session".to_string(),
+                cache_type: Some("ttl".to_string()),
                 capacity: 10,
-                ttl: None,
-                check_interval: None,
-                jitter: None,
+                ttl: Some(60.0),
+                ..Default::default()
             })
             .to_request();
-
-        let resp = test::call_service(&mut app, req).await;
-        assert_eq!(resp.status(), 200);
-
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
         let req = test::TestRequest::put()
-            .uri("/cache/test/key")
+            .uri("/cache/session/key")
             .set_payload("value")
             .to_request();
+        test::call_service(&app, req).await;
+        let expires_at = || async {
+            let req = test::TestRequest::get()
+                .uri("/cache/session/key/ttl")
+                .to_request();
+            let ttl: KeyTtlResponse = test::call_and_read_body_json(&app, req).await;
+            ttl.expires_at.unwrap()
+        };
+        let update = |body: serde_json::Value| {
+            test::TestRequest::post()
+                .uri("/admin/config-update")
+                .insert_header(("authorization", "Bearer secret"))
+                .set_json(body)
+                .to_request()
+        };
+        let set_at = unix_millis(clock.now());
+        assert_eq!(expires_at().await, set_at + 60_000);
 
-        let resp = test::call_service(&mut app, req).await;
-        assert_eq!(resp.status(), 200);
+        // A new check_interval leaves every key's expiry alone.
+        clock.advance(Duration::from_secs(30));
+        let req = update(serde_json::json!({"pattern": "*", "check_interval": 5}));
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+        assert_eq!(expires_at().await, set_at + 60_000);
+
+        // A new ttl starts them afresh.
+        let req = update(serde_json::json!({"pattern": "*", "ttl": 120}));
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+        assert_eq!(expires_at().await, set_at + 30_000 + 120_000);
     }
 
     #[actix_web::test]
-    async fn test_delete_value() {
-        let mut app = create_app!();
+    async fn test_reload_manifest() {
+        let path =
+            std::env::temp_dir().join(format!("cachers-manifest-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"{"caches": [{"name": "users", "cache_type": "lru", "capacity": 10}]}"#,
+        )
+        .unwrap();
+        let app = create_app!(Config {
+            admin_token: Some("secret".to_string()),
+            manifest_file: Some(path.to_str().unwrap().to_string()),
+            ..Default::default()
+        });
+        let reload = || {
+            test::TestRequest::post()
+                .uri("/admin/reload-manifest")
+                .insert_header(("authorization", "Bearer secret"))
+                .to_request()
+        };
 
+        let resp: ManifestResponse = test::call_and_read_body_json(&app, reload()).await;
+        assert_eq!(resp.created, vec!["users"]);
+        let req = test::TestRequest::put()
+            .uri("/cache/users/alice")
+            .set_payload("1")
+            .to_request();
+        test::call_service(&app, req).await;
         let req = test::TestRequest::post()
             .uri("/cache/create")
             .set_json(&CreateCacheRequest {
-                name: "test".to_string(),
-                cache_type: "lru".to_string(),
-                capacity: 10,
-                ttl: None,
-                check_interval: None,
-                jitter: None,
+                name: "extra".to_string(),
+                cache_type: Some("fifo".to_string()),
+                capacity: 5,
+                ..Default::default()
             })
             .to_request();
+        test::call_service(&app, req).await;
 
-        let resp = test::call_service(&mut app, req).await;
-        assert_eq!(resp.status(), 200);
+        // A changed config for an existing cache doesn't recreate it.
+        std::fs::write(
+            &path,
+            r#"{"caches": [
+                {"name": "users", "cache_type": "lru", "capacity": 99},
+                {"name": "sessions", "cache_type": "ttl", "capacity": 10, "ttl": 300}
+            ]}"#,
+        )
+        .unwrap();
+        let resp: ManifestResponse = test::call_and_read_body_json(&app, reload()).await;
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(resp.created, vec!["sessions"]);
 
-        let req = test::TestRequest::put()
-            .uri("/cache/test/key")
-            .set_payload("value")
+        let req = test::TestRequest::get()
+            .uri("/cache/users/alice")
             .to_request();
-
-        let resp = test::call_service(&mut app, req).await;
-        assert_eq!(resp.status(), 200);
-
-        let req = test::TestRequest::delete()
-            .uri("/cache/test/key")
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(test::read_body(resp).await, "1");
+        let req = test::TestRequest::get()
+            .uri("/cache/users/stats")
             .to_request();
+        let resp: StatsResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.capacity, 10);
+        for name in ["extra", "sessions"] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/cache/{}/stats", name))
+                .to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), 200);
+        }
 
-        let resp = test::call_service(&mut app, req).await;
-        assert_eq!(resp.status(), 200);
+        // Unreadable manifests are rejected.
+        let resp = test::call_service(&app, reload()).await;
+        assert_eq!(resp.status(), 422);
     }
 
     #[actix_web::test]
-    async fn test_stats() {
-        let mut app = create_app!();
+    async fn test_reset_metrics() {
+        let app = create_app!(Config {
+            admin_token: Some("secret".to_string()),
+            ..Config::default()
+        });
 
         let req = test::TestRequest::post()
             .uri("/cache/create")
             .set_json(&CreateCacheRequest {
                 name: "test".to_string(),
-                cache_type: "lru".to_string(),
+                cache_type: Some("lru".to_string()),
                 capacity: 10,
-                ttl: None,
-                check_interval: None,
-                jitter: None,
+                ..Default::default()
             })
             .to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .set_payload("value")
+            .to_request();
+        test::call_service(&app, req).await;
+        for key in ["key", "missing"] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/cache/test/{}", key))
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+
+        let req = test::TestRequest::get().uri("/metrics").to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        let body = std::str::from_utf8(&body).unwrap();
+        assert!(body.contains("cachers_cache_hits_total{cache=\"test\"} 1\n"));
+        assert!(body.contains("cachers_cache_sets_total{cache=\"test\"} 1\n"));
 
-        let resp = test::call_service(&mut app, req).await;
+        // Without the admin token nothing is reset.
+        let req = test::TestRequest::post().uri("/metrics/reset").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+        let req = test::TestRequest::get().uri("/metrics").to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        let body = std::str::from_utf8(&body).unwrap();
+        assert!(body.contains("cachers_cache_hits_total{cache=\"test\"} 1\n"));
+
+        let req = test::TestRequest::post()
+            .uri("/metrics/reset")
+            .insert_header(("authorization", "Bearer secret"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
         assert_eq!(resp.status(), 200);
 
+        let req = test::TestRequest::get().uri("/metrics").to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        let body = std::str::from_utf8(&body).unwrap();
+        for counter in ["hits", "misses", "gets", "sets", "deletes"] {
+            let line = format!("cachers_cache_{}_total{{cache=\"test\"}} 0\n", counter);
+            assert!(body.contains(&line), "{}", body);
+        }
+        assert!(body.contains("cachers_cache_size{cache=\"test\"} 1\n"));
+
+        // Counting resumes from zero.
+        let req = test::TestRequest::get().uri("/cache/test/key").to_request();
+        test::call_service(&app, req).await;
         let req = test::TestRequest::get()
             .uri("/cache/test/stats")
             .to_request();
-        let resp = test::call_service(&mut app, req).await;
-        assert_eq!(resp.status(), 200);
-        assert_eq!(
-            resp.headers().get("content-type"),
-            Some(&HeaderValue::from_static("application/json"))
-        );
-        let body = test::read_body(resp).await;
-        assert_eq!(
-            body.as_ref(),
-            br#"{"hits":0,"misses":0,"size":0,"capacity":10}"#
-        );
+        let resp: StatsResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!((resp.hits, resp.misses, resp.gets), (1, 0, 1));
     }
 
     #[actix_web::test]
-    async fn test_stats_not_found() {
-        let mut app = create_app!();
+    async fn test_ping() {
+        let clock = ManualClock::new();
+        let app = create_app!(Config {
+            clock: clock.clone(),
+            ..Config::default()
+        });
 
-        let req = test::TestRequest::get()
-            .uri("/cache/test/stats")
-            .to_request();
-        let resp = test::call_service(&mut app, req).await;
-        assert_eq!(resp.status(), 404);
+        let req = test::TestRequest::get().uri("/ping").to_request();
+        let resp: PingResponse = test::call_and_read_body_json(&app, req).await;
+        assert!(resp.pong);
+        assert_eq!(resp.server_time, unix_millis(clock.now()));
     }
 
     #[actix_web::test]
-    async fn test_unknown_cache_type() {
-        let mut app = create_app!();
+    async fn test_route_key() {
+        let app = create_app!(Config {
+            peers: vec!["http://a:8080".to_string(), "http://b:8080".to_string()],
+            ..Default::default()
+        });
+
+        let req = test::TestRequest::get().uri("/route/user:1").to_request();
+        let first: RouteResponse = test::call_and_read_body_json(&app, req).await;
+        assert!(["http://a:8080", "http://b:8080"].contains(&first.peer.as_str()));
+        let req = test::TestRequest::get().uri("/route/user:1").to_request();
+        let second: RouteResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(first.peer, second.peer);
+
+        let app = create_app!();
+        let req = test::TestRequest::get().uri("/route/user:1").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 422);
+    }
+
+    #[actix_web::test]
+    async fn test_schema() {
+        let app = create_app!();
+
+        let req = test::TestRequest::get().uri("/schema").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(
+            resp.headers().get("content-type"),
+            Some(&HeaderValue::from_static("application/schema+json"))
+        );
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        let required = |name: &str| body["$defs"][name]["required"].clone();
+        assert_eq!(required("CreateCacheRequest"), serde_json::json!(["name"]));
+        assert_eq!(required("DeleteCacheRequest"), serde_json::json!(["name"]));
+        assert_eq!(required("MGetRequest"), serde_json::json!(["keys"]));
+    }
+
+    #[actix_web::test]
+    async fn test_create_ttl_cache() {
+        let app = create_app!();
 
         let req = test::TestRequest::post()
             .uri("/cache/create")
             .set_json(&CreateCacheRequest {
                 name: "test".to_string(),
-                cache_type: "unknown".to_string(),
+                cache_type: Some("ttl".to_string()),
                 capacity: 10,
-                ttl: None,
-                check_interval: None,
-                jitter: None,
+                ttl: Some(60.0),
+                check_interval: Some(10.0),
+                jitter: Some(0.0),
+                ..Default::default()
             })
             .to_request();
-        let resp = test::call_service(&mut app, req).await;
-        assert_eq!(resp.status(), 400);
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
     }
 
     #[actix_web::test]
-    async fn test_create_ttl_cache() {
-        let mut app = create_app!();
+    async fn test_create_ttl_cache_defaults() {
+        let app = create_app!();
 
         let req = test::TestRequest::post()
             .uri("/cache/create")
             .set_json(&CreateCacheRequest {
                 name: "test".to_string(),
-                cache_type: "ttl".to_string(),
+                cache_type: Some("ttl".to_string()),
                 capacity: 10,
-                ttl: Some(60),
-                check_interval: Some(10),
-                jitter: Some(0),
+                ttl: None,
+                check_interval: None,
+                jitter: None,
+                ..Default::default()
             })
             .to_request();
-        let resp = test::call_service(&mut app, req).await;
+        let resp = test::call_service(&app, req).await;
         assert_eq!(resp.status(), 200);
     }
 
     #[actix_web::test]
-    async fn test_create_ttl_cache_defaults() {
-        let mut app = create_app!();
+    async fn test_stats_rates() {
+        let state = web::Data::new(AppState::new(Config::default()));
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(crate::routes::data),
+        )
+        .await;
 
         let req = test::TestRequest::post()
             .uri("/cache/create")
             .set_json(&CreateCacheRequest {
                 name: "test".to_string(),
-                cache_type: "ttl".to_string(),
+                cache_type: Some("lru".to_string()),
                 capacity: 10,
-                ttl: None,
-                check_interval: None,
-                jitter: None,
+                ..Default::default()
             })
             .to_request();
-        let resp = test::call_service(&mut app, req).await;
-        assert_eq!(resp.status(), 200);
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::put()
+            .uri("/cache/test/key")
+            .set_payload("value")
+            .to_request();
+        test::call_service(&app, req).await;
+        state.sample_rates();
+
+        for _ in 0..30 {
+            let req = test::TestRequest::get().uri("/cache/test/key").to_request();
+            test::call_service(&app, req).await;
+        }
+        state.sample_rates();
+        let rates = || async {
+            let req = test::TestRequest::get()
+                .uri("/cache/test/stats")
+                .to_request();
+            let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+            (
+                body["hit_rate_1m"].as_f64().unwrap(),
+                body["miss_rate_1m"].as_f64().unwrap(),
+            )
+        };
+        assert_eq!(rates().await, (30.0, 0.0));
+
+        // The burst is averaged over a longer span as the window fills, then drops out.
+        for _ in 0..9 {
+            state.sample_rates();
+        }
+        assert_eq!(rates().await, (3.0, 0.0));
+        for _ in 0..60 {
+            state.sample_rates();
+        }
+        assert_eq!(rates().await, (0.0, 0.0));
     }
 }
@@ -0,0 +1,723 @@
+use crate::state::{SharedClock, SystemClock};
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
+
+// Which key a cache gives up when it is full, mirrored from its type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionOrder {
+    // Least recently used first (lru).
+    LeastRecent,
+    // Most recently used first (mru).
+    MostRecent,
+    // Oldest insertion first; reads don't change the order (fifo, ttl).
+    Insertion,
+}
+
+impl EvictionOrder {
+    pub fn for_cache_type(cache_type: &str) -> Self {
+        match cache_type {
+            "lru" => EvictionOrder::LeastRecent,
+            "mru" => EvictionOrder::MostRecent,
+            _ => EvictionOrder::Insertion,
+        }
+    }
+}
+
+// Server-side index of the keys held by a cache.
+//
+// The cachers crate doesn't report evictions, so the index mirrors the cache's eviction
+// order and names the key to evict before a new key would overflow capacity. The caller
+// removes that key from the cache itself, which keeps the index in step with the cache's
+// contents. Keys the cache drops on its own (TTL expiry) are pruned when a read misses.
+pub struct KeyIndex {
+    order: EvictionOrder,
+    capacity: u64,
+    next_stamp: u64,
+    keys: BTreeMap<String, KeyState>,
+    by_stamp: BTreeMap<u64, String>,
+    // Number of keys at each priority, so the lowest one is known without a scan.
+    priorities: BTreeMap<i32, usize>,
+    // Sum of `size` over every tracked key.
+    bytes: u64,
+    // Keep a copy of each value so it can be peeked at without going through the cache.
+    shadow: bool,
+    // How long a write stays readable (ttl caches only), give or take up to `jitter`.
+    ttl: Option<Duration>,
+    jitter: Duration,
+    clock: SharedClock,
+}
+
+struct KeyState {
+    // Position in eviction order; lower stamps are older.
+    stamp: u64,
+    last_access: SystemTime,
+    // When the value was last written.
+    modified: SystemTime,
+    // When the value stops being readable, if the cache has a TTL.
+    expires: Option<SystemTime>,
+    // Key plus value length, in bytes.
+    size: u64,
+    // Length of the stored value, even once it has been spilled.
+    value_len: u64,
+    // Copy of the value, when the index shadows values.
+    value: Option<Arc<Vec<u8>>>,
+    // Opaque client flags stored alongside the value (memcached-style).
+    flags: u32,
+    // Keys with a lower priority are evicted before any with a higher one.
+    priority: i32,
+    // Opaque client JSON stored alongside the value.
+    meta: Option<Arc<serde_json::Value>>,
+    // File holding the value, when it was too large to keep in memory. The file is
+    // deleted as soon as the key is overwritten or forgotten.
+    spill: Option<PathBuf>,
+}
+
+impl KeyIndex {
+    pub fn new(order: EvictionOrder, capacity: u64, shadow: bool) -> Self {
+        KeyIndex {
+            order,
+            capacity,
+            next_stamp: 0,
+            keys: BTreeMap::new(),
+            by_stamp: BTreeMap::new(),
+            priorities: BTreeMap::new(),
+            bytes: 0,
+            shadow,
+            ttl: None,
+            jitter: Duration::ZERO,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    pub fn with_ttl(mut self, ttl: Option<Duration>, jitter: Duration) -> Self {
+        self.ttl = ttl;
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    // Bytes held by the tracked keys and their values.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    // Record a read hit on `key`.
+    pub fn touch(&mut self, key: &str) {
+        let Some(state) = self.keys.get_mut(key) else {
+            return;
+        };
+        state.last_access = self.clock.now();
+        if self.order != EvictionOrder::Insertion {
+            self.restamp(key);
+        }
+    }
+
+    // Record a write of `key`, returning the key that has to be evicted to make room.
+    pub fn insert(&mut self, key: &str, value: &[u8], flags: u32) -> Option<String> {
+        let size = (key.len() + value.len()) as u64;
+        let value_len = value.len() as u64;
+        let value = self.shadow.then(|| Arc::new(value.to_vec()));
+        let now = self.clock.now();
+        let expires = self.expiry(now);
+        if let Some(state) = self.keys.get_mut(key) {
+            self.bytes = self.bytes - state.size + size;
+            state.size = size;
+            state.value_len = value_len;
+            state.value = value;
+            state.flags = flags;
+            state.modified = now;
+            state.expires = expires;
+            if let Some(path) = state.spill.take() {
+                remove_spill(&path);
+            }
+            self.touch(key);
+            return None;
+        }
+        let victim = match self.keys.len() as u64 >= self.capacity {
+            true => self.next_victim(),
+            false => None,
+        };
+        if let Some(victim) = &victim {
+            self.remove(victim);
+        }
+        let state = KeyState {
+            stamp: self.take_stamp(),
+            last_access: now,
+            modified: now,
+            expires,
+            size,
+            value_len,
+            value,
+            flags,
+            priority: 0,
+            meta: None,
+            spill: None,
+        };
+        *self.priorities.entry(0).or_default() += 1;
+        self.by_stamp.insert(state.stamp, key.to_string());
+        self.keys.insert(key.to_string(), state);
+        self.bytes += size;
+        victim
+    }
+
+    // Forget `key`, returning whether it was tracked.
+    pub fn remove(&mut self, key: &str) -> bool {
+        match self.keys.remove(key) {
+            Some(state) => {
+                self.by_stamp.remove(&state.stamp);
+                self.forget_priority(state.priority);
+                self.bytes -= state.size;
+                if let Some(path) = &state.spill {
+                    remove_spill(path);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.keys.contains_key(key)
+    }
+
+    // Bytes `key` and its value account for, if it's tracked.
+    pub fn size(&self, key: &str) -> Option<u64> {
+        self.keys.get(key).map(|state| state.size)
+    }
+
+    // Forget keys in eviction order until at most `target` remain, returning them.
+    pub fn trim_to(&mut self, target: u64) -> Vec<String> {
+        let mut trimmed = Vec::new();
+        while self.keys.len() as u64 > target {
+            let Some(victim) = self.next_victim() else {
+                break;
+            };
+            self.remove(&victim);
+            trimmed.push(victim);
+        }
+        trimmed
+    }
+
+    // Whether `key` has outlived its TTL. The cache drops expired entries on its own
+    // schedule, so reads check this first rather than serve one that hasn't gone yet.
+    pub fn expired(&self, key: &str) -> bool {
+        self.keys
+            .get(key)
+            .and_then(|state| state.expires)
+            .is_some_and(|expires| self.clock.now() >= expires)
+    }
+
+    // Give `key` a fresh TTL, for sliding expiry.
+    pub fn restart_ttl(&mut self, key: &str) {
+        let expires = self.expiry(self.clock.now());
+        if let Some(state) = self.keys.get_mut(key) {
+            state.expires = expires;
+        }
+    }
+
+    // Carry over an expiry picked elsewhere, such as by the cache this one replaces.
+    pub fn set_expires(&mut self, key: &str, expires: Option<SystemTime>) {
+        if let Some(state) = self.keys.get_mut(key) {
+            state.expires = expires;
+        }
+    }
+
+    // When `key`'s value stops being readable; None if the key is untracked or the cache
+    // has no TTL.
+    pub fn expires(&self, key: &str) -> Option<SystemTime> {
+        self.keys.get(key).and_then(|state| state.expires)
+    }
+
+    // Shadowed value of `key`, leaving its eviction order and last access untouched.
+    pub fn peek(&self, key: &str) -> Option<Arc<Vec<u8>>> {
+        self.keys.get(key).and_then(|state| state.value.clone())
+    }
+
+    // Record that `key`'s value lives in the file at `path` rather than in memory.
+    pub fn spill(&mut self, key: &str, path: PathBuf) {
+        if let Some(state) = self.keys.get_mut(key) {
+            let size = key.len() as u64;
+            self.bytes = self.bytes - state.size + size;
+            state.size = size;
+            state.spill = Some(path);
+        }
+    }
+
+    pub fn spill_path(&self, key: &str) -> Option<&Path> {
+        self.keys.get(key).and_then(|state| state.spill.as_deref())
+    }
+
+    // Flags stored with `key`'s current value.
+    pub fn flags(&self, key: &str) -> Option<u32> {
+        self.keys.get(key).map(|state| state.flags)
+    }
+
+    // Set the eviction priority of `key`. New keys start at 0.
+    pub fn set_priority(&mut self, key: &str, priority: i32) {
+        let Some(state) = self.keys.get_mut(key) else {
+            return;
+        };
+        let old = std::mem::replace(&mut state.priority, priority);
+        self.forget_priority(old);
+        *self.priorities.entry(priority).or_default() += 1;
+    }
+
+    pub fn priority(&self, key: &str) -> Option<i32> {
+        self.keys.get(key).map(|state| state.priority)
+    }
+
+    pub fn set_meta(&mut self, key: &str, meta: Option<Arc<serde_json::Value>>) {
+        if let Some(state) = self.keys.get_mut(key) {
+            state.meta = meta;
+        }
+    }
+
+    pub fn meta(&self, key: &str) -> Option<Arc<serde_json::Value>> {
+        self.keys.get(key).and_then(|state| state.meta.clone())
+    }
+
+    pub fn shadows_values(&self) -> bool {
+        self.shadow
+    }
+
+    pub fn count_prefix(&self, prefix: &str) -> usize {
+        self.keys
+            .range(prefix.to_string()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .count()
+    }
+
+    // All tracked keys, in key order.
+    pub fn keys(&self) -> Vec<String> {
+        self.keys.keys().cloned().collect()
+    }
+
+    // All tracked keys in eviction-order age, oldest first.
+    pub fn keys_by_age(&self) -> Vec<String> {
+        self.by_stamp.values().cloned().collect()
+    }
+
+    // All tracked keys in the order they would be evicted, next victim first.
+    pub fn eviction_order(&self) -> Vec<String> {
+        let mut keys = self.keys_by_age();
+        if self.order == EvictionOrder::MostRecent {
+            keys.reverse();
+        }
+        keys.sort_by_key(|key| self.keys[key].priority);
+        keys
+    }
+
+    // Up to `limit` tracked keys that sort after `after` (or from the start), in key order.
+    pub fn keys_after(&self, after: Option<&str>, limit: usize) -> Vec<String> {
+        let start = match after {
+            Some(after) => Bound::Excluded(after.to_string()),
+            None => Bound::Unbounded,
+        };
+        self.keys
+            .range((start, Bound::Unbounded))
+            .take(limit)
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    // Length of every tracked value, in key order.
+    pub fn value_sizes(&self) -> Vec<u64> {
+        self.keys.values().map(|state| state.value_len).collect()
+    }
+
+    // Keys written after `since`, with when they were written, oldest write first.
+    pub fn modified_after(&self, since: SystemTime) -> Vec<(String, SystemTime)> {
+        let mut modified: Vec<(String, SystemTime)> = self
+            .keys
+            .iter()
+            .filter(|(_, state)| state.modified > since)
+            .map(|(key, state)| (key.clone(), state.modified))
+            .collect();
+        modified.sort_by_key(|(_, modified)| *modified);
+        modified
+    }
+
+    // Keys that haven't been read or written within `idle`.
+    pub fn idle_keys(&self, idle: Duration) -> Vec<String> {
+        let now = self.clock.now();
+        self.keys
+            .iter()
+            .filter(|(_, state)| {
+                // A clock that has gone backwards counts as no time passing.
+                now.duration_since(state.last_access).unwrap_or_default() >= idle
+            })
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    // Cross-check the index's own bookkeeping, describing each inconsistency found.
+    pub fn verify(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.by_stamp.len() != self.keys.len() {
+            problems.push(format!(
+                "eviction order holds {} keys but the index holds {}",
+                self.by_stamp.len(),
+                self.keys.len()
+            ));
+        }
+        let mut bytes = 0;
+        let mut priorities: BTreeMap<i32, usize> = BTreeMap::new();
+        for (key, state) in &self.keys {
+            if self.by_stamp.get(&state.stamp) != Some(key) {
+                problems.push(format!("key {} is missing from the eviction order", key));
+            }
+            if state.spill.as_ref().is_some_and(|path| !path.exists()) {
+                problems.push(format!("spill file of key {} is missing", key));
+            }
+            bytes += state.size;
+            *priorities.entry(state.priority).or_default() += 1;
+        }
+        if bytes != self.bytes {
+            problems.push(format!(
+                "byte counter is {} but the keys hold {}",
+                self.bytes, bytes
+            ));
+        }
+        if priorities != self.priorities {
+            problems.push("priority counts don't match the keys".to_string());
+        }
+        problems
+    }
+
+    // When a value written or refreshed at `from` expires: the TTL moved by a random
+    // amount within ±jitter, so keys written together don't all expire together. Never
+    // earlier than `from`.
+    fn expiry(&self, from: SystemTime) -> Option<SystemTime> {
+        let ttl = self.ttl?;
+        let span = self.jitter.as_nanos().saturating_mul(2) + 1;
+        let offset = Duration::from_nanos((random_u64() as u128 % span) as u64);
+        let expires = (from + ttl + offset).checked_sub(self.jitter);
+        Some(expires.map_or(from, |expires| expires.max(from)))
+    }
+
+    // The first key in the cache's own order among those with the lowest priority.
+    fn next_victim(&self) -> Option<String> {
+        let (&lowest, _) = self.priorities.first_key_value()?;
+        let mut keys: Box<dyn Iterator<Item = &String>> = match self.order {
+            EvictionOrder::MostRecent => Box::new(self.by_stamp.values().rev()),
+            _ => Box::new(self.by_stamp.values()),
+        };
+        keys.find(|key| self.keys[*key].priority == lowest).cloned()
+    }
+
+    fn forget_priority(&mut self, priority: i32) {
+        if let Some(count) = self.priorities.get_mut(&priority) {
+            *count -= 1;
+            if *count == 0 {
+                self.priorities.remove(&priority);
+            }
+        }
+    }
+
+    // Move `key` to the most recent end of the order.
+    fn restamp(&mut self, key: &str) {
+        let stamp = self.take_stamp();
+        if let Some(state) = self.keys.get_mut(key) {
+            self.by_stamp.remove(&state.stamp);
+            state.stamp = stamp;
+            self.by_stamp.insert(stamp, key.to_string());
+        }
+    }
+
+    fn take_stamp(&mut self) -> u64 {
+        let stamp = self.next_stamp;
+        self.next_stamp += 1;
+        stamp
+    }
+}
+
+// Dropping the index (with its cache) deletes any spill files it still owns.
+impl Drop for KeyIndex {
+    fn drop(&mut self) {
+        for path in self.keys.values().filter_map(|state| state.spill.as_ref()) {
+            remove_spill(path);
+        }
+    }
+}
+
+fn random_u64() -> u64 {
+    Uuid::new_v4().as_u64_pair().0
+}
+
+pub fn remove_spill(path: &Path) {
+    if let Err(e) = std::fs::remove_file(path) {
+        log::warn!("failed to remove spill file {}: {}", path.display(), e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{Clock, ManualClock};
+
+    fn filled(order: EvictionOrder) -> KeyIndex {
+        let mut index = KeyIndex::new(order, 3, false);
+        for key in ["a", "b", "c"] {
+            assert_eq!(index.insert(key, &[0; 1], 0), None);
+        }
+        index.touch("a");
+        index
+    }
+
+    #[test]
+    fn test_least_recent_victim() {
+        let mut index = filled(EvictionOrder::LeastRecent);
+        assert_eq!(index.insert("d", &[0; 1], 0), Some("b".to_string()));
+        assert_eq!(index.count_prefix(""), 3);
+    }
+
+    #[test]
+    fn test_most_recent_victim() {
+        let mut index = filled(EvictionOrder::MostRecent);
+        assert_eq!(index.insert("d", &[0; 1], 0), Some("a".to_string()));
+    }
+
+    #[test]
+    fn test_insertion_victim() {
+        let mut index = filled(EvictionOrder::Insertion);
+        assert_eq!(index.insert("a", &[0; 1], 0), None);
+        assert_eq!(index.insert("d", &[0; 1], 0), Some("a".to_string()));
+    }
+
+    #[test]
+    fn test_bytes() {
+        let mut index = KeyIndex::new(EvictionOrder::LeastRecent, 2, false);
+        index.insert("a", &[0; 10], 0);
+        index.insert("b", &[0; 20], 0);
+        assert_eq!(index.bytes(), 32);
+        index.insert("a", &[0; 5], 0);
+        assert_eq!(index.bytes(), 27);
+        // Evicts "b".
+        index.insert("c", &[0; 1], 0);
+        assert_eq!(index.bytes(), 8);
+        index.remove("a");
+        assert_eq!(index.bytes(), 2);
+    }
+
+    #[test]
+    fn test_trim_to() {
+        let mut index = KeyIndex::new(EvictionOrder::LeastRecent, 4, false);
+        for key in ["a", "b", "c", "d"] {
+            index.insert(key, &[0; 1], 0);
+        }
+        index.touch("a");
+        assert_eq!(index.keys_by_age(), vec!["b", "c", "d", "a"]);
+        assert_eq!(index.trim_to(2), vec!["b".to_string(), "c".to_string()]);
+        assert_eq!(index.keys(), vec!["a".to_string(), "d".to_string()]);
+        assert!(index.trim_to(2).is_empty());
+    }
+
+    #[test]
+    fn test_flags() {
+        let mut index = KeyIndex::new(EvictionOrder::Insertion, 4, false);
+        index.insert("a", &[0; 1], 7);
+        assert_eq!(index.flags("a"), Some(7));
+        // Each write replaces the flags along with the value.
+        index.insert("a", &[0; 1], 0);
+        assert_eq!(index.flags("a"), Some(0));
+        index.remove("a");
+        assert_eq!(index.flags("a"), None);
+    }
+
+    #[test]
+    fn test_priority_victim() {
+        let mut index = filled(EvictionOrder::LeastRecent);
+        index.set_priority("b", 5);
+        assert_eq!(index.eviction_order(), vec!["c", "a", "b"]);
+        assert_eq!(index.insert("d", &[0; 1], 0), Some("c".to_string()));
+        index.set_priority("a", -1);
+        assert_eq!(index.insert("e", &[0; 1], 0), Some("a".to_string()));
+        // With every remaining key above "b", it finally goes.
+        index.set_priority("d", 9);
+        index.set_priority("e", 9);
+        assert_eq!(index.trim_to(2), vec!["b".to_string()]);
+        assert_eq!(index.priority("d"), Some(9));
+    }
+
+    #[test]
+    fn test_verify() {
+        let mut index = filled(EvictionOrder::LeastRecent);
+        index.set_priority("b", 3);
+        assert_eq!(index.insert("d", &[0; 5], 0), Some("c".to_string()));
+        index.remove("a");
+        assert!(index.verify().is_empty());
+
+        index.bytes += 1;
+        index.by_stamp.clear();
+        // The count mismatch, both keys' missing stamps and the byte counter.
+        assert_eq!(index.verify().len(), 4);
+    }
+
+    #[test]
+    fn test_spill_files_removed() {
+        let dir = std::env::temp_dir();
+        let spill = |name: &str| {
+            let path = dir.join(format!("cachers-keys-{}-{}.bin", name, std::process::id()));
+            std::fs::write(&path, [0; 100]).unwrap();
+            path
+        };
+        let (a, b, c) = (spill("a"), spill("b"), spill("c"));
+
+        let mut index = KeyIndex::new(EvictionOrder::Insertion, 2, false);
+        index.insert("a", &[0; 100], 0);
+        index.spill("a", a.clone());
+        assert_eq!(index.bytes(), 1);
+        assert_eq!(index.spill_path("a"), Some(a.as_path()));
+        index.insert("b", &[0; 100], 0);
+        index.spill("b", b.clone());
+        // Overwriting, evicting and dropping each delete the file they replace.
+        index.insert("b", &[0; 1], 0);
+        assert!(!b.exists());
+        assert_eq!(index.insert("c", &[0; 100], 0), Some("a".to_string()));
+        assert!(!a.exists());
+        index.spill("c", c.clone());
+        drop(index);
+        assert!(!c.exists());
+    }
+
+    #[test]
+    fn test_eviction_order() {
+        let mut lru = KeyIndex::new(EvictionOrder::LeastRecent, 4, false);
+        let mut mru = KeyIndex::new(EvictionOrder::MostRecent, 4, false);
+        for index in [&mut lru, &mut mru] {
+            for key in ["a", "b", "c"] {
+                index.insert(key, &[0; 1], 0);
+            }
+            index.touch("a");
+        }
+        assert_eq!(lru.eviction_order(), vec!["b", "c", "a"]);
+        assert_eq!(mru.eviction_order(), vec!["a", "c", "b"]);
+    }
+
+    #[test]
+    fn test_keys_after() {
+        let mut index = KeyIndex::new(EvictionOrder::Insertion, 10, false);
+        for key in ["c", "a", "d", "b"] {
+            index.insert(key, &[0; 1], 0);
+        }
+        assert_eq!(index.keys_after(None, 2), vec!["a", "b"]);
+        assert_eq!(index.keys_after(Some("b"), 2), vec!["c", "d"]);
+        // The cursor key itself needn't still exist.
+        index.remove("c");
+        assert_eq!(index.keys_after(Some("bb"), 5), vec!["d"]);
+        assert!(index.keys_after(Some("d"), 2).is_empty());
+    }
+
+    #[test]
+    fn test_peek() {
+        let mut index = KeyIndex::new(EvictionOrder::LeastRecent, 2, true);
+        index.insert("a", b"1", 0);
+        index.insert("b", b"2", 0);
+        assert_eq!(index.peek("a").as_deref(), Some(&b"1".to_vec()));
+        // Peeking at "a" leaves it least recent.
+        assert_eq!(index.insert("c", b"3", 0), Some("a".to_string()));
+        assert_eq!(index.peek("a"), None);
+
+        let mut index = KeyIndex::new(EvictionOrder::LeastRecent, 2, false);
+        index.insert("a", b"1", 0);
+        assert_eq!(index.peek("a"), None);
+    }
+
+    #[test]
+    fn test_idle_keys() {
+        let clock = ManualClock::new();
+        let mut index =
+            KeyIndex::new(EvictionOrder::Insertion, 10, false).with_clock(clock.clone());
+        index.insert("a", &[0; 1], 0);
+        index.insert("b", &[0; 1], 0);
+        clock.advance(Duration::from_millis(20));
+        index.touch("a");
+        assert_eq!(index.idle_keys(Duration::from_millis(10)), vec!["b"]);
+    }
+
+    #[test]
+    fn test_expired() {
+        let clock = ManualClock::new();
+        let mut index = KeyIndex::new(EvictionOrder::Insertion, 10, false)
+            .with_clock(clock.clone())
+            .with_ttl(Some(Duration::from_secs(60)), Duration::ZERO);
+        index.insert("a", &[0; 1], 0);
+        index.insert("b", &[0; 1], 0);
+        clock.advance(Duration::from_secs(30));
+        index.restart_ttl("b");
+        assert!(!index.expired("a"));
+        clock.advance(Duration::from_secs(30));
+        assert!(index.expired("a"));
+        assert!(!index.expired("b"));
+        // Overwriting starts a new TTL.
+        index.insert("a", &[0; 1], 0);
+        assert!(!index.expired("a"));
+        assert!(!index.expired("missing"));
+    }
+
+    #[test]
+    fn test_expiry_jitter() {
+        let clock = ManualClock::new();
+        let (ttl, jitter) = (Duration::from_secs(60), Duration::from_secs(10));
+        let mut index = KeyIndex::new(EvictionOrder::Insertion, 100, false)
+            .with_clock(clock.clone())
+            .with_ttl(Some(ttl), jitter);
+        let now = clock.now();
+        let mut expiries = Vec::new();
+        for i in 0..100 {
+            let key = i.to_string();
+            index.insert(&key, &[0; 1], 0);
+            let expires = index.expires(&key).unwrap();
+            assert!(expires >= now + ttl - jitter && expires <= now + ttl + jitter);
+            expiries.push(expires);
+        }
+        expiries.sort();
+        expiries.dedup();
+        assert!(expiries.len() > 1);
+    }
+
+    #[test]
+    fn test_count_prefix() {
+        let mut index = KeyIndex::new(EvictionOrder::Insertion, 10, false);
+        for key in ["user:1", "user:2", "users", "session:1"] {
+            index.insert(key, &[0; 1], 0);
+        }
+        assert_eq!(index.count_prefix("user:"), 2);
+        assert_eq!(index.count_prefix("user"), 3);
+        assert_eq!(index.count_prefix(""), 4);
+        assert!(index.remove("user:1"));
+        assert_eq!(index.count_prefix("user:"), 1);
+    }
+
+    #[test]
+    fn test_modified_after() {
+        let mut index = KeyIndex::new(EvictionOrder::LeastRecent, 10, false);
+        index.insert("a", b"1", 0);
+        index.insert("b", b"2", 0);
+        let since = SystemTime::now();
+        std::thread::sleep(Duration::from_millis(2));
+        index.insert("c", b"3", 0);
+        index.insert("a", b"4", 0);
+        // Reads don't count as modifications.
+        index.touch("b");
+
+        let keys: Vec<String> = index
+            .modified_after(since)
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(keys, vec!["c", "a"]);
+    }
+}
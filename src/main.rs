@@ -1,39 +1,239 @@
+mod archive;
+mod config;
 mod errors;
+mod factory;
+mod glob;
 mod handlers;
+mod keys;
+mod manifest;
+mod metrics;
+mod middleware;
+mod rates;
 mod request_types;
+mod response_types;
+mod ring;
+mod routes;
+mod seed;
 mod state;
+mod transform;
+mod webhook;
 
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::http::KeepAlive;
+use actix_web::middleware::{from_fn, Logger};
 use actix_web::{web, App, HttpServer};
+use config::Config;
+use futures_util::future;
 use state::AppState;
-use std::collections::HashMap;
-use std::sync::Mutex;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
 
 #[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    let state = web::Data::new(AppState {
-        caches: Mutex::new(HashMap::new()),
-    });
-
-    HttpServer::new(move || {
-        App::new()
-            .app_data(state.clone())
-            .route("/cache/create", web::post().to(handlers::create_cache))
-            .route("/cache/delete", web::post().to(handlers::delete_cache))
-            .route("/cache/{cache_name}/stats", web::get().to(handlers::stats))
-            .route(
-                "/cache/{cache_name}/{key}",
-                web::get().to(handlers::get_value),
-            )
-            .route(
-                "/cache/{cache_name}/{key}",
-                web::put().to(handlers::set_value),
-            )
-            .route(
-                "/cache/{cache_name}/{key}",
-                web::delete().to(handlers::delete_value),
-            )
-    })
-    .bind("127.0.0.1:8080")?
-    .run()
-    .await
+async fn main() -> io::Result<()> {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+
+    let config = Config::from_env();
+    log::info!("effective config: {}", config.summary());
+    let state = web::Data::new(AppState::new(config));
+    if let Some(path) = &state.config.seed_file {
+        seed::load_seed_file(&state, Path::new(path))?;
+    }
+    if let Some(path) = &state.config.manifest_file {
+        let manifest = manifest::read_manifest(Path::new(path)).map_err(io::Error::other)?;
+        manifest::apply_manifest(&state, manifest).map_err(io::Error::other)?;
+    }
+    state.spawn_rate_sampler();
+    state.spawn_metrics_pusher();
+    let bind = state.config.bind.clone();
+    let admin_bind = state.config.admin_bind.clone();
+    let app_state = state.clone();
+
+    // Admin routes move to their own server when CACHERS_ADMIN_BIND is set.
+    let main_routes: fn(&mut web::ServiceConfig) = match admin_bind {
+        Some(_) => routes::data,
+        None => |cfg| {
+            routes::data(cfg);
+            routes::admin(cfg);
+        },
+    };
+    let main_state = state.clone();
+    let keep_alive = keep_alive(&state.config);
+    let client_timeout = Duration::from_millis(state.config.client_timeout_ms);
+    let max_connections = state.config.max_connections;
+    let server = HttpServer::new(move || app(main_state.clone(), main_routes))
+        .keep_alive(keep_alive)
+        .client_request_timeout(client_timeout)
+        .max_connections(max_connections);
+
+    let server = match bind.strip_prefix("unix:") {
+        #[cfg(unix)]
+        Some(path) => {
+            remove_stale_socket(path)?;
+            server.bind_uds(path)?
+        }
+        #[cfg(not(unix))]
+        Some(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Unix domain sockets are not supported on this platform",
+            ))
+        }
+        // TCP listeners accept HTTP/1.1 and HTTP/2 with prior knowledge (h2c).
+        None => server.bind_auto_h2c(&bind)?,
+    };
+
+    let server = server.run();
+    app_state.add_server_handle(server.handle());
+    let Some(admin_bind) = admin_bind else {
+        return server.await;
+    };
+
+    let admin = HttpServer::new(move || app(state.clone(), routes::admin))
+        .keep_alive(keep_alive)
+        .client_request_timeout(client_timeout)
+        .max_connections(max_connections)
+        .bind_auto_h2c(&admin_bind)?
+        .run();
+    app_state.add_server_handle(admin.handle());
+    future::try_join(server, admin).await.map(|_| ())
+}
+
+// An app serving `routes` with the middleware and limits every server shares.
+fn app(
+    state: web::Data<AppState>,
+    routes: fn(&mut web::ServiceConfig),
+) -> App<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse<impl MessageBody>,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    let max_body_bytes = state.config.max_body_bytes;
+    App::new()
+        .app_data(state)
+        // Compressed bodies (gzip, br, ...) are decoded by actix before this limit is applied.
+        // /admin/import streams its body instead, under CACHERS_MAX_IMPORT_BYTES.
+        .app_data(web::PayloadConfig::new(max_body_bytes))
+        .wrap(from_fn(middleware::slow_log))
+        .wrap(from_fn(middleware::server_header))
+        .wrap(from_fn(middleware::request_id))
+        .wrap(Logger::new(
+            r#"%a "%r" %s %b %T request_id=%{x-request-id}o"#,
+        ))
+        .configure(routes)
+}
+
+fn keep_alive(config: &Config) -> KeepAlive {
+    match config.keep_alive_secs {
+        0 => KeepAlive::Disabled,
+        secs => KeepAlive::Timeout(Duration::from_secs(secs)),
+    }
+}
+
+// Remove a socket file left behind by a previous run so `bind_uds` can reuse the path.
+// Anything at the path that isn't a socket is left alone and reported as an error.
+#[cfg(unix)]
+fn remove_stale_socket(path: &str) -> io::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => std::fs::remove_file(path),
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists and is not a socket", path),
+        )),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::UnixStream;
+
+    #[actix_web::test]
+    async fn test_bind_unix_socket() {
+        let path = std::env::temp_dir().join(format!("cachers-test-{}.sock", std::process::id()));
+        let path_str = path.to_str().unwrap();
+
+        // Leave a stale socket file behind, as a crashed server would.
+        drop(UnixListener::bind(&path).unwrap());
+        remove_stale_socket(path_str).unwrap();
+
+        let state = web::Data::new(AppState::new(Config::default()));
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(state.clone())
+                .route("/cache/create", web::post().to(handlers::create_cache))
+        })
+        .workers(1)
+        .bind_uds(path_str)
+        .unwrap()
+        .run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let body = r#"{"name":"test","cache_type":"lru","capacity":10}"#;
+        let request = format!(
+            "POST /cache/create HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let mut stream = UnixStream::connect(&path).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.contains(r#"{"name":"test","type":"lru","capacity":10,"created_at":"#));
+
+        handle.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn test_tuned_server_http1_and_h2c() {
+        let config = Config {
+            keep_alive_secs: 30,
+            client_timeout_ms: 1000,
+            ..Config::default()
+        };
+        let keep_alive = keep_alive(&config);
+        let client_timeout = Duration::from_millis(config.client_timeout_ms);
+        let state = web::Data::new(AppState::new(config));
+        let server = HttpServer::new(move || app(state.clone(), routes::data))
+            .workers(1)
+            .keep_alive(keep_alive)
+            .client_request_timeout(client_timeout)
+            .bind_auto_h2c("127.0.0.1:0")
+            .unwrap();
+        let addr = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let url = format!("http://{}/ping", addr);
+        for (client, version) in [
+            (reqwest::Client::new(), reqwest::Version::HTTP_11),
+            (
+                reqwest::Client::builder()
+                    .http2_prior_knowledge()
+                    .build()
+                    .unwrap(),
+                reqwest::Version::HTTP_2,
+            ),
+        ] {
+            let resp = client.get(&url).send().await.unwrap();
+            assert_eq!(resp.status(), 200);
+            assert_eq!(resp.version(), version);
+        }
+
+        handle.stop(true).await;
+    }
 }
@@ -0,0 +1,111 @@
+use crate::errors::CacheError;
+use crate::factory::build_cache;
+use crate::request_types::CreateCacheRequest;
+use crate::state::{AppState, CacheEntry};
+use serde::Deserialize;
+use std::path::Path;
+
+// Caches that should always exist, read from `CACHERS_MANIFEST`.
+#[derive(Debug, Deserialize)]
+pub struct Manifest {
+    pub caches: Vec<CreateCacheRequest>,
+}
+
+// Read and parse a manifest file. Unreadable or malformed files are unprocessable.
+pub fn read_manifest(path: &Path) -> Result<Manifest, CacheError> {
+    let invalid = |e: &dyn std::fmt::Display| {
+        CacheError::Unprocessable(format!("invalid manifest {}: {}", path.display(), e))
+    };
+    let contents = std::fs::read_to_string(path).map_err(|e| invalid(&e))?;
+    serde_json::from_str(&contents).map_err(|e| invalid(&e))
+}
+
+// Create every cache in the manifest that doesn't exist yet and return their names.
+// Running caches are left alone, including ones the manifest doesn't mention. Every
+// listed cache is validated first, so a bad manifest creates nothing.
+pub fn apply_manifest(state: &AppState, manifest: Manifest) -> Result<Vec<String>, CacheError> {
+    let caches: Vec<CreateCacheRequest> = manifest
+        .caches
+        .into_iter()
+        .map(|config| state.with_defaults(config))
+        .collect();
+    let mut built: Vec<CacheEntry> = Vec::new();
+    for (i, config) in caches.iter().enumerate() {
+        if caches[..i].iter().any(|c| c.name == config.name) {
+            return Err(CacheError::Unprocessable(format!(
+                "duplicate cache name {} in manifest",
+                config.name
+            )));
+        }
+        let cache = build_cache(config)?;
+        if state.cache_exists(&config.name).is_err() {
+            built.push(CacheEntry::new(cache, config, &state.config)?);
+        }
+    }
+
+    let mut created = Vec::with_capacity(built.len());
+    for cache in built {
+        let name = cache.config.name.clone();
+        // A cache created concurrently by a request already satisfies the manifest.
+        match state.insert_cache(name.clone(), cache) {
+            Ok(()) => created.push(name),
+            Err(CacheError::CacheAlreadyExists) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(created)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn manifest(json: &str) -> Manifest {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_apply_manifest() {
+        let state = AppState::new(Config::default());
+        let m = manifest(
+            r#"{"caches": [
+                {"name": "users", "cache_type": "lru", "capacity": 10},
+                {"name": "sessions", "cache_type": "ttl", "capacity": 10, "ttl": 300}
+            ]}"#,
+        );
+        assert_eq!(
+            apply_manifest(&state, m).unwrap(),
+            vec!["users", "sessions"]
+        );
+        assert!(state.get_cache("users").is_ok());
+        assert!(state.get_cache("sessions").is_ok());
+    }
+
+    #[test]
+    fn test_apply_manifest_rejects_invalid() {
+        let state = AppState::new(Config::default());
+        let m = manifest(
+            r#"{"caches": [
+                {"name": "users", "cache_type": "lru", "capacity": 10},
+                {"name": "users", "cache_type": "fifo", "capacity": 10}
+            ]}"#,
+        );
+        assert!(matches!(
+            apply_manifest(&state, m),
+            Err(CacheError::Unprocessable(_))
+        ));
+
+        let m = manifest(
+            r#"{"caches": [
+                {"name": "users", "cache_type": "lru", "capacity": 10},
+                {"name": "broken", "cache_type": "nope", "capacity": 10}
+            ]}"#,
+        );
+        assert!(matches!(
+            apply_manifest(&state, m),
+            Err(CacheError::UnknownCacheType)
+        ));
+        assert!(state.get_cache("users").is_err());
+    }
+}
@@ -0,0 +1,201 @@
+use crate::state::CacheEntry;
+use std::fmt::Write;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+// Pushgateway job label the server's metrics are grouped under.
+const PUSH_JOB: &str = "cachers";
+
+#[derive(Clone, Copy)]
+enum MetricType {
+    Counter,
+    Gauge,
+}
+
+impl MetricType {
+    fn as_str(self) -> &'static str {
+        match self {
+            MetricType::Counter => "counter",
+            MetricType::Gauge => "gauge",
+        }
+    }
+}
+
+struct Metric {
+    name: &'static str,
+    help: &'static str,
+    kind: MetricType,
+    value: fn(&CacheEntry) -> u64,
+}
+
+const CACHE_METRICS: &[Metric] = &[
+    Metric {
+        name: "cachers_cache_hits_total",
+        help: "Lookups that found a value.",
+        kind: MetricType::Counter,
+        value: |c| c.stats().hits,
+    },
+    Metric {
+        name: "cachers_cache_misses_total",
+        help: "Lookups that found no value.",
+        kind: MetricType::Counter,
+        value: |c| c.stats().misses,
+    },
+    Metric {
+        name: "cachers_cache_gets_total",
+        help: "Get operations served.",
+        kind: MetricType::Counter,
+        value: |c| c.counters.gets.load(Ordering::Relaxed),
+    },
+    Metric {
+        name: "cachers_cache_sets_total",
+        help: "Set operations applied.",
+        kind: MetricType::Counter,
+        value: |c| c.counters.sets.load(Ordering::Relaxed),
+    },
+    Metric {
+        name: "cachers_cache_deletes_total",
+        help: "Delete operations applied.",
+        kind: MetricType::Counter,
+        value: |c| c.counters.deletes.load(Ordering::Relaxed),
+    },
+    Metric {
+        name: "cachers_cache_slow_operations_total",
+        help: "Requests that took longer than CACHERS_SLOW_MS.",
+        kind: MetricType::Counter,
+        value: |c| c.counters.slow.load(Ordering::Relaxed),
+    },
+    Metric {
+        name: "cachers_cache_size",
+        help: "Entries currently stored.",
+        kind: MetricType::Gauge,
+        value: |c| c.stats().size,
+    },
+    Metric {
+        name: "cachers_cache_capacity",
+        help: "Maximum number of entries.",
+        kind: MetricType::Gauge,
+        value: |c| c.stats().capacity,
+    },
+];
+
+// Render per-cache metrics in the Prometheus text exposition format, with a
+// `# HELP` and `# TYPE` line ahead of each metric family.
+pub fn format_metrics(caches: &[(String, Arc<CacheEntry>)]) -> String {
+    let mut caches: Vec<&(String, Arc<CacheEntry>)> = caches.iter().collect();
+    caches.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut out = String::new();
+    for metric in CACHE_METRICS {
+        let _ = writeln!(out, "# HELP {} {}", metric.name, metric.help);
+        let _ = writeln!(out, "# TYPE {} {}", metric.name, metric.kind.as_str());
+        for (name, cache) in &caches {
+            let _ = writeln!(
+                out,
+                "{}{{cache=\"{}\"}} {}",
+                metric.name,
+                escape_label(name),
+                (metric.value)(cache)
+            );
+        }
+    }
+    out
+}
+
+// Where metrics are pushed on a Pushgateway at `gateway`.
+pub fn push_url(gateway: &str) -> String {
+    format!("{}/metrics/job/{}", gateway.trim_end_matches('/'), PUSH_JOB)
+}
+
+// Push rendered metrics to a Pushgateway. PUT replaces the whole group, so caches deleted
+// since the last push disappear from the gateway too.
+pub async fn push(client: &reqwest::Client, url: &str, body: String) -> Result<(), String> {
+    let resp = client
+        .put(url)
+        .header(reqwest::header::CONTENT_TYPE, CONTENT_TYPE)
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    match resp.status() {
+        status if status.is_success() => Ok(()),
+        status => Err(format!("gateway returned {}", status)),
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::factory::build_cache;
+    use crate::request_types::CreateCacheRequest;
+
+    fn entry(name: &str) -> (String, Arc<CacheEntry>) {
+        let config = CreateCacheRequest {
+            name: name.to_string(),
+            cache_type: Some("lru".to_string()),
+            capacity: 10,
+            ttl: None,
+            check_interval: None,
+            jitter: None,
+            ..Default::default()
+        };
+        let cache =
+            CacheEntry::new(build_cache(&config).unwrap(), &config, &Config::default()).unwrap();
+        (name.to_string(), Arc::new(cache))
+    }
+
+    #[test]
+    fn test_help_and_type_lines() {
+        let caches = vec![entry("b"), entry("a")];
+        caches[0]
+            .1
+            .set("key".to_string(), b"value".to_vec())
+            .unwrap();
+        let out = format_metrics(&caches);
+
+        assert!(out.contains("# HELP cachers_cache_hits_total "));
+        assert!(out.contains("# TYPE cachers_cache_hits_total counter\n"));
+        assert!(out.contains("# TYPE cachers_cache_misses_total counter\n"));
+        assert!(out.contains("# TYPE cachers_cache_size gauge\n"));
+        assert!(out.contains("# TYPE cachers_cache_capacity gauge\n"));
+        assert!(out.contains("cachers_cache_size{cache=\"b\"} 1\n"));
+        assert!(out.contains("cachers_cache_capacity{cache=\"a\"} 10\n"));
+
+        // Every family is introduced by HELP then TYPE before any samples.
+        let lines: Vec<&str> = out.lines().collect();
+        for metric in CACHE_METRICS {
+            let help = lines
+                .iter()
+                .position(|l| l.starts_with(&format!("# HELP {} ", metric.name)))
+                .unwrap();
+            assert_eq!(
+                lines[help + 1],
+                format!("# TYPE {} {}", metric.name, metric.kind.as_str())
+            );
+            assert!(lines[help + 2].starts_with(&format!("{}{{", metric.name)));
+        }
+    }
+
+    #[test]
+    fn test_push_url() {
+        assert_eq!(
+            push_url("http://gateway:9091/"),
+            "http://gateway:9091/metrics/job/cachers"
+        );
+    }
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!(escape_label(r#"a"b\c"#), r#"a\"b\\c"#);
+    }
+}
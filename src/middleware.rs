@@ -0,0 +1,250 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{web, Error};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+use crate::state::AppState;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+// Propagate the caller's `X-Request-Id` (or generate a UUID when absent) and echo it
+// on every response, including errors. A generated ID is also written back onto the
+// request so handlers and logging further in see the same value.
+pub async fn request_id(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty())
+        .map(String::from)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    let value = HeaderValue::from_str(&id).map_err(actix_web::error::ErrorBadRequest)?;
+    req.headers_mut().insert(REQUEST_ID_HEADER, value.clone());
+
+    let mut res = next.call(req).await?;
+    res.headers_mut().insert(REQUEST_ID_HEADER, value);
+    Ok(res)
+}
+
+// Apply `CACHERS_SERVER_HEADER` to every response: an empty value removes the `Server`
+// header, anything else replaces it. Responses are left alone when it's unset.
+pub async fn server_header(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let configured = req
+        .app_data::<web::Data<AppState>>()
+        .and_then(|state| state.config.server_header.clone());
+    let mut res = next.call(req).await?;
+    match configured.as_deref() {
+        None => {}
+        Some("") => {
+            res.headers_mut().remove(header::SERVER);
+        }
+        Some(value) => {
+            let value =
+                HeaderValue::from_str(value).map_err(actix_web::error::ErrorInternalServerError)?;
+            res.headers_mut().insert(header::SERVER, value);
+        }
+    }
+    Ok(res)
+}
+
+// Log requests on a cache that take at least `CACHERS_SLOW_MS`, at warn level, and count
+// them against the cache. Requests that don't name a cache aren't timed.
+pub async fn slow_log(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let state = req.app_data::<web::Data<AppState>>().cloned();
+    let threshold = state
+        .as_ref()
+        .and_then(|state| state.config.slow_ms)
+        .map(Duration::from_millis);
+    let start = Instant::now();
+    let res = next.call(req).await?;
+    let elapsed = start.elapsed();
+
+    let (Some(state), Some(threshold)) = (state, threshold) else {
+        return Ok(res);
+    };
+    let request = res.request();
+    if let (true, Some(cache_name)) = (elapsed >= threshold, request.match_info().get("cache_name"))
+    {
+        let operation = format!(
+            "{} {}",
+            request.method(),
+            request.match_pattern().unwrap_or_default()
+        );
+        log::warn!(
+            "slow operation: cache={} operation=\"{}\" duration={:?}",
+            cache_name,
+            operation,
+            elapsed
+        );
+        if let Ok(cache) = state.get_cache(cache_name) {
+            cache.counters.slow.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::factory::build_cache;
+    use crate::handlers::get_value;
+    use crate::request_types::CreateCacheRequest;
+    use crate::state::{AppState, CacheEntry};
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, App, HttpResponse};
+
+    async fn with_server_header() -> HttpResponse {
+        HttpResponse::Ok()
+            .insert_header((header::SERVER, "actix-web"))
+            .finish()
+    }
+
+    #[actix_web::test]
+    async fn test_request_id_echoed() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AppState::new(Config::default())))
+                .wrap(from_fn(request_id))
+                .route("/cache/{cache_name}/{key}", web::get().to(get_value)),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/cache/test/key")
+            .insert_header(("x-request-id", "abc-123"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 404);
+        assert_eq!(
+            resp.headers().get("x-request-id"),
+            Some(&HeaderValue::from_static("abc-123"))
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_request_id_generated() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AppState::new(Config::default())))
+                .wrap(from_fn(request_id))
+                .route("/cache/{cache_name}/{key}", web::get().to(get_value)),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/cache/test/key").to_request();
+        let resp = test::call_service(&app, req).await;
+        let id = resp
+            .headers()
+            .get("x-request-id")
+            .unwrap()
+            .to_str()
+            .unwrap();
+        assert!(Uuid::parse_str(id).is_ok());
+    }
+
+    async fn slow_get(path: web::Path<(String, String)>) -> HttpResponse {
+        if path.1 == "slow" {
+            actix_web::rt::time::sleep(Duration::from_millis(50)).await;
+        }
+        HttpResponse::Ok().finish()
+    }
+
+    #[actix_web::test]
+    async fn test_slow_log() {
+        let config = Config {
+            slow_ms: Some(20),
+            ..Config::default()
+        };
+        let state = web::Data::new(AppState::new(config));
+        let create = CreateCacheRequest {
+            name: "test".to_string(),
+            cache_type: Some("lru".to_string()),
+            capacity: 10,
+            ..Default::default()
+        };
+        let cache =
+            CacheEntry::new(build_cache(&create).unwrap(), &create, &Config::default()).unwrap();
+        state.insert_cache("test".to_string(), cache).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .wrap(from_fn(slow_log))
+                .route("/cache/{cache_name}/{key}", web::get().to(slow_get)),
+        )
+        .await;
+
+        let slow = || {
+            state
+                .get_cache("test")
+                .unwrap()
+                .counters
+                .slow
+                .load(Ordering::Relaxed)
+        };
+        let req = test::TestRequest::get()
+            .uri("/cache/test/fast")
+            .to_request();
+        test::call_service(&app, req).await;
+        assert_eq!(slow(), 0);
+        let req = test::TestRequest::get()
+            .uri("/cache/test/slow")
+            .to_request();
+        test::call_service(&app, req).await;
+        assert_eq!(slow(), 1);
+    }
+
+    #[actix_web::test]
+    async fn test_server_header_suppressed() {
+        let config = Config {
+            server_header: Some(String::new()),
+            ..Config::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AppState::new(config)))
+                .wrap(from_fn(server_header))
+                .route("/", web::get().to(with_server_header)),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get("server"), None);
+    }
+
+    #[actix_web::test]
+    async fn test_server_header_custom() {
+        let config = Config {
+            server_header: Some("cachers".to_string()),
+            ..Config::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AppState::new(config)))
+                .wrap(from_fn(server_header))
+                .route("/", web::get().to(with_server_header)),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.headers().get("server"),
+            Some(&HeaderValue::from_static("cachers"))
+        );
+    }
+}
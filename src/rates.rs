@@ -0,0 +1,75 @@
+use std::collections::VecDeque;
+
+// Cumulative hit/miss totals sampled once per tick, keeping the last `window` ticks so
+// recent rates come from the difference between the oldest and newest samples.
+pub struct RateWindow {
+    window: usize,
+    samples: VecDeque<(u64, u64)>,
+}
+
+impl RateWindow {
+    pub fn new(window: usize) -> Self {
+        RateWindow {
+            window,
+            samples: VecDeque::with_capacity(window + 1),
+        }
+    }
+
+    pub fn record(&mut self, hits: u64, misses: u64) {
+        self.samples.push_back((hits, misses));
+        while self.samples.len() > self.window + 1 {
+            self.samples.pop_front();
+        }
+    }
+
+    // Average hits and misses per tick across the window; zero until two samples exist.
+    // Totals that went backwards (a counter reset) count as no change.
+    pub fn rates(&self) -> (f64, f64) {
+        let (Some(&(first_hits, first_misses)), Some(&(last_hits, last_misses))) =
+            (self.samples.front(), self.samples.back())
+        else {
+            return (0.0, 0.0);
+        };
+        let ticks = (self.samples.len() - 1) as f64;
+        if ticks == 0.0 {
+            return (0.0, 0.0);
+        }
+        (
+            last_hits.saturating_sub(first_hits) as f64 / ticks,
+            last_misses.saturating_sub(first_misses) as f64 / ticks,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rates_decay() {
+        let mut window = RateWindow::new(3);
+        assert_eq!(window.rates(), (0.0, 0.0));
+        window.record(0, 0);
+        assert_eq!(window.rates(), (0.0, 0.0));
+
+        // A burst of 30 hits and 3 misses in one tick.
+        window.record(30, 3);
+        assert_eq!(window.rates(), (30.0, 3.0));
+        window.record(30, 3);
+        assert_eq!(window.rates(), (15.0, 1.5));
+        window.record(30, 3);
+        assert_eq!(window.rates(), (10.0, 1.0));
+
+        // Once the burst leaves the window the rates fall back to zero.
+        window.record(30, 3);
+        assert_eq!(window.rates(), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_rates_after_reset() {
+        let mut window = RateWindow::new(60);
+        window.record(100, 10);
+        window.record(0, 0);
+        assert_eq!(window.rates(), (0.0, 0.0));
+    }
+}
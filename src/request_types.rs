@@ -1,21 +1,471 @@
-use serde::{Deserialize, Serialize};
-
-// Request for creating a cache.
-#[derive(Debug, Deserialize, Serialize)]
-pub struct CreateCacheRequest {
-    pub name: String,
-    pub cache_type: String,
-    pub capacity: u64,
-    #[serde(default)]
-    pub ttl: Option<u64>,
-    #[serde(default)]
-    pub check_interval: Option<u64>,
-    #[serde(default)]
-    pub jitter: Option<u64>,
-}
-
-// Request for deleting a cache.
-#[derive(Debug, Deserialize, Serialize)]
-pub struct DeleteCacheRequest {
-    pub name: String,
-}
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::time::Duration;
+
+// Request for creating a cache.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct CreateCacheRequest {
+    pub name: String,
+    // Falls back to the server's CACHERS_DEFAULT_CACHE_TYPE when unset.
+    #[serde(default)]
+    pub cache_type: Option<String>,
+    // Fixed capacity; leave unset when using soft_capacity and hard_capacity instead.
+    #[serde(default)]
+    pub capacity: u64,
+    // Keys beyond soft_capacity are trimmed in the background, letting bursts grow the
+    // cache up to hard_capacity. Sets of new keys at hard_capacity are rejected.
+    #[serde(default)]
+    pub soft_capacity: Option<u64>,
+    #[serde(default)]
+    pub hard_capacity: Option<u64>,
+    // ttl, check_interval and jitter are measured in `ttl_unit` and may be fractional.
+    #[serde(default)]
+    pub ttl: Option<f64>,
+    #[serde(default)]
+    pub check_interval: Option<f64>,
+    #[serde(default)]
+    pub jitter: Option<f64>,
+    #[serde(default)]
+    pub ttl_unit: TtlUnit,
+    // Restart a key's TTL every time a client reads it.
+    #[serde(default)]
+    pub sliding: bool,
+    // Evict keys that haven't been read or written for this many seconds.
+    #[serde(default)]
+    pub idle_key_ttl: Option<u64>,
+    // Only create the cache if the server's memory budget has this many bytes to spare.
+    #[serde(default)]
+    pub require_free_bytes: Option<u64>,
+    // Values larger than this are written to CACHERS_SPILL_DIR instead of held in memory.
+    #[serde(default)]
+    pub spill_threshold_bytes: Option<u64>,
+    // Encrypt values with CACHERS_ENCRYPTION_KEY before storing them.
+    #[serde(default)]
+    pub encrypted: bool,
+    // Operations clients may perform on the cache; all of them if unset.
+    #[serde(default)]
+    pub allowed_ops: Option<Vec<CacheOp>>,
+    // Queue unconditional sets for up to this many milliseconds and apply them in a batch.
+    // Reads and writes of a queued key see its queued value without applying the queue.
+    #[serde(default)]
+    pub write_buffer_ms: Option<u64>,
+    // http(s) URL that the cache's stats are POSTed to every stats_webhook_interval_ms
+    // (default 10000).
+    #[serde(default)]
+    pub stats_webhook: Option<String>,
+    #[serde(default)]
+    pub stats_webhook_interval_ms: Option<u64>,
+}
+
+impl CreateCacheRequest {
+    // The requested cache type, or "" if it was never given or defaulted.
+    pub fn cache_type(&self) -> &str {
+        self.cache_type.as_deref().unwrap_or_default()
+    }
+
+    // The most keys the cache can hold.
+    pub fn max_capacity(&self) -> u64 {
+        self.hard_capacity.unwrap_or(self.capacity)
+    }
+
+    // Effective settings of a ttl cache, with defaults filled in; None for other types.
+    // Settings that don't convert (see `validate_create`) also fall back to the default.
+    pub fn ttl_settings(&self) -> Option<TtlSettings> {
+        if self.cache_type() != "ttl" {
+            return None;
+        }
+        let duration = |value: Option<f64>, default: Duration| {
+            value
+                .and_then(|value| self.ttl_unit.to_duration(value))
+                .unwrap_or(default)
+        };
+        Some(TtlSettings {
+            ttl: duration(self.ttl, Duration::from_secs(60)),
+            check_interval: duration(self.check_interval, Duration::from_secs(10)),
+            jitter: duration(self.jitter, Duration::ZERO),
+        })
+    }
+}
+
+pub struct TtlSettings {
+    pub ttl: Duration,
+    pub check_interval: Duration,
+    pub jitter: Duration,
+}
+
+// A client operation on a cache's entries, as named in `allowed_ops`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheOp {
+    // Reading values: get, mget, peek and dumps.
+    Get,
+    Set,
+    Delete,
+}
+
+// Unit of the TTL settings in a create request.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum TtlUnit {
+    #[default]
+    #[serde(rename = "s")]
+    Seconds,
+    #[serde(rename = "ms")]
+    Milliseconds,
+}
+
+impl TtlUnit {
+    // Convert `value` in this unit to a duration, rounded to the nearest millisecond.
+    // Negative and non-finite values have no duration.
+    pub fn to_duration(self, value: f64) -> Option<Duration> {
+        let millis = match self {
+            TtlUnit::Seconds => value * 1000.0,
+            TtlUnit::Milliseconds => value,
+        };
+        match millis.is_finite() && millis >= 0.0 {
+            true => Some(Duration::from_millis(millis.round() as u64)),
+            false => None,
+        }
+    }
+}
+
+// Request for deleting a cache.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DeleteCacheRequest {
+    pub name: String,
+}
+
+// Request for toggling a cache's read-only mode.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ReadOnlyRequest {
+    pub enabled: bool,
+}
+
+// Request for switching a cache to another eviction policy.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PolicyRequest {
+    #[serde(rename = "type")]
+    pub cache_type: String,
+}
+
+// Request for replacing a cache with an empty one; the type defaults to the current one.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ReinitRequest {
+    pub capacity: u64,
+    #[serde(rename = "type", default)]
+    pub cache_type: Option<String>,
+}
+
+// Request for changing the TTL settings of every ttl cache whose name matches the glob
+// `pattern`. Values are in each cache's own `ttl_unit`; unset ones are left as they are.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ConfigUpdateRequest {
+    pub pattern: String,
+    #[serde(default)]
+    pub ttl: Option<f64>,
+    #[serde(default)]
+    pub check_interval: Option<f64>,
+    #[serde(default)]
+    pub jitter: Option<f64>,
+}
+
+// Query parameters for bulk cache statistics.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StatsQuery {
+    #[serde(default)]
+    pub pattern: Option<String>,
+}
+
+// Each query type lists the parameter names it accepts, for CACHERS_STRICT_QUERY.
+impl StatsQuery {
+    pub const PARAMS: &'static [&'static str] = &["pattern"];
+}
+
+// Query parameters for a single cache's statistics.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CacheStatsQuery {
+    // Comma-separated stats fields to return; all of them if unset.
+    #[serde(default)]
+    pub fields: Option<String>,
+    // Long-poll: wait up to this many seconds for the version to move past `since`
+    // (the current version if unset) before responding.
+    #[serde(default)]
+    pub wait: Option<u64>,
+    #[serde(default)]
+    pub since: Option<u64>,
+}
+
+impl CacheStatsQuery {
+    pub const PARAMS: &'static [&'static str] = &["fields", "wait", "since"];
+}
+
+// Query parameters for counter deltas.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StatsDeltaQuery {
+    // Token from a previous call; deltas are totals if unset.
+    #[serde(default)]
+    pub since: Option<u64>,
+}
+
+impl StatsDeltaQuery {
+    pub const PARAMS: &'static [&'static str] = &["since"];
+}
+
+// Request for fetching several keys at once.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MGetRequest {
+    pub keys: Vec<String>,
+}
+
+// Request for deleting several keys at once.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DeleteManyRequest {
+    pub keys: Vec<String>,
+}
+
+// Request for setting several keys at once. Entries are parsed one at a time after the
+// body is read, so an error can name the entry at fault.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MSetRequest {
+    pub entries: Vec<serde_json::Value>,
+    // Store all entries or none, even if the cache can't take them all.
+    #[serde(default)]
+    pub atomic: bool,
+}
+
+// One entry of an mset; the value is base64-encoded.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MSetEntry {
+    pub key: String,
+    pub value: String,
+}
+
+// Request for handing a cache's entries off to a peer server.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DrainRequest {
+    // Base URL of the peer, e.g. `http://10.0.0.2:8080`.
+    pub target: String,
+    // Delete the local cache once every entry has been transferred.
+    #[serde(default)]
+    pub delete: bool,
+}
+
+// Query parameters for fetching a value.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct GetQuery {
+    // Serve the value with this MIME type instead of the negotiated one.
+    #[serde(default)]
+    pub content_type: Option<String>,
+    // With `count=false` the lookup isn't counted as a hit or miss, e.g. for existence
+    // probes. It still counts as a get and as a use of the key.
+    #[serde(default = "default_true")]
+    pub count: bool,
+    // Echo the key's metadata in an `X-Cache-Meta` header.
+    #[serde(default)]
+    pub meta: bool,
+}
+
+impl GetQuery {
+    pub const PARAMS: &'static [&'static str] = &["content_type", "count", "meta"];
+}
+
+// Query parameters for setting a value.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SetQuery {
+    // Respond with the value this set replaced (204 if there was none).
+    #[serde(default)]
+    pub return_old: bool,
+}
+
+impl SetQuery {
+    pub const PARAMS: &'static [&'static str] = &["return_old"];
+}
+
+fn default_true() -> bool {
+    true
+}
+
+// Query parameters for a multi-key fetch.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MGetQuery {
+    // Return an array aligned with the requested keys, with `null` for misses.
+    #[serde(default)]
+    pub ordered: bool,
+}
+
+impl MGetQuery {
+    pub const PARAMS: &'static [&'static str] = &["ordered"];
+}
+
+// Query parameters for scanning keys.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ScanQuery {
+    // Token from the previous page; omit to start from the beginning.
+    #[serde(default)]
+    pub cursor: Option<String>,
+    #[serde(default = "default_scan_count")]
+    pub count: usize,
+}
+
+impl ScanQuery {
+    pub const PARAMS: &'static [&'static str] = &["cursor", "count"];
+}
+
+fn default_scan_count() -> usize {
+    100
+}
+
+// Query parameters for listing changed keys.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ChangesQuery {
+    // Unix time in milliseconds; only keys written after it are listed.
+    pub since: u64,
+    // Include each key's value, base64-encoded.
+    #[serde(default)]
+    pub values: bool,
+}
+
+impl ChangesQuery {
+    pub const PARAMS: &'static [&'static str] = &["since", "values"];
+}
+
+// Query parameters for a value size histogram.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct HistogramQuery {
+    // Comma-separated, increasing bucket upper bounds in bytes; powers of two if unset.
+    #[serde(default)]
+    pub buckets: Option<String>,
+}
+
+impl HistogramQuery {
+    pub const PARAMS: &'static [&'static str] = &["buckets"];
+}
+
+// Query parameters for counting keys.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct KeysQuery {
+    #[serde(default)]
+    pub prefix: String,
+}
+
+impl KeysQuery {
+    pub const PARAMS: &'static [&'static str] = &["prefix"];
+}
+
+// Hand-written JSON Schema (draft 2020-12) for the request bodies, keyed by type name
+// under `$defs`. Keep in step with the structs above.
+pub fn schema() -> serde_json::Value {
+    let count = json!({"type": "integer", "minimum": 0});
+    let duration = json!({"type": "number", "minimum": 0});
+    let cache_type = json!({"enum": crate::factory::cache_types()});
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "$defs": {
+            "CreateCacheRequest": {
+                "type": "object",
+                "required": ["name"],
+                "properties": {
+                    "name": {"type": "string"},
+                    "cache_type": cache_type,
+                    "capacity": count,
+                    "soft_capacity": count,
+                    "hard_capacity": count,
+                    "ttl": duration,
+                    "check_interval": duration,
+                    "jitter": duration,
+                    "ttl_unit": {"enum": ["s", "ms"], "default": "s"},
+                    "sliding": {"type": "boolean", "default": false},
+                    "idle_key_ttl": count,
+                    "require_free_bytes": count,
+                    "spill_threshold_bytes": count,
+                    "encrypted": {"type": "boolean", "default": false},
+                    "allowed_ops": {
+                        "type": "array",
+                        "items": {"enum": ["get", "set", "delete"]}
+                    },
+                    "write_buffer_ms": count,
+                    "stats_webhook": {"type": "string", "format": "uri"},
+                    "stats_webhook_interval_ms": count
+                }
+            },
+            "DeleteCacheRequest": {
+                "type": "object",
+                "required": ["name"],
+                "properties": {
+                    "name": {"type": "string"}
+                }
+            },
+            "ReadOnlyRequest": {
+                "type": "object",
+                "required": ["enabled"],
+                "properties": {
+                    "enabled": {"type": "boolean"}
+                }
+            },
+            "PolicyRequest": {
+                "type": "object",
+                "required": ["type"],
+                "properties": {
+                    "type": cache_type
+                }
+            },
+            "ReinitRequest": {
+                "type": "object",
+                "required": ["capacity"],
+                "properties": {
+                    "capacity": {"type": "integer", "minimum": 1},
+                    "type": cache_type
+                }
+            },
+            "ConfigUpdateRequest": {
+                "type": "object",
+                "required": ["pattern"],
+                "properties": {
+                    "pattern": {"type": "string"},
+                    "ttl": duration,
+                    "check_interval": duration,
+                    "jitter": duration
+                }
+            },
+            "DrainRequest": {
+                "type": "object",
+                "required": ["target"],
+                "properties": {
+                    "target": {"type": "string"},
+                    "delete": {"type": "boolean", "default": false}
+                }
+            },
+            "MSetRequest": {
+                "type": "object",
+                "required": ["entries"],
+                "properties": {
+                    "entries": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "required": ["key", "value"],
+                            "properties": {
+                                "key": {"type": "string"},
+                                "value": {"type": "string", "contentEncoding": "base64"}
+                            }
+                        }
+                    },
+                    "atomic": {"type": "boolean", "default": false}
+                }
+            },
+            "DeleteManyRequest": {
+                "type": "object",
+                "required": ["keys"],
+                "properties": {
+                    "keys": {"type": "array", "items": {"type": "string"}}
+                }
+            },
+            "MGetRequest": {
+                "type": "object",
+                "required": ["keys"],
+                "properties": {
+                    "keys": {"type": "array", "items": {"type": "string"}}
+                }
+            }
+        }
+    })
+}
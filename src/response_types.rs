@@ -0,0 +1,282 @@
+use crate::state::{unix_millis, CacheEntry};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::atomic::Ordering;
+
+// Response for cache statistics.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StatsResponse {
+    pub hits: u64,
+    pub misses: u64,
+    pub size: u64,
+    pub capacity: u64,
+    pub utilization: f64,
+    pub near_full: bool,
+    pub gets: u64,
+    pub sets: u64,
+    pub deletes: u64,
+    // Hits and misses per second over the last minute.
+    pub hit_rate_1m: f64,
+    pub miss_rate_1m: f64,
+    // Unix time in milliseconds the cache was created, and seconds since.
+    pub created_at: u64,
+    pub age_seconds: f64,
+    // Unix time in milliseconds of the cache's last write, delete or eviction.
+    pub last_modified: u64,
+    // Bumped on every write, delete or eviction; pass it back as `since` to long-poll.
+    pub version: u64,
+    // Effective TTL settings in seconds; null unless this is a ttl cache.
+    pub ttl: Option<f64>,
+    pub check_interval: Option<f64>,
+    pub jitter: Option<f64>,
+}
+
+// Body POSTed to a cache's stats_webhook.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StatsWebhookPayload {
+    pub cache: String,
+    pub stats: StatsResponse,
+}
+
+impl StatsResponse {
+    pub fn new(cache: &CacheEntry, near_full_threshold: f64) -> Self {
+        let stats = cache.stats();
+        let ttl_settings = cache.config.ttl_settings();
+        let (hit_rate_1m, miss_rate_1m) = cache.rates();
+        let utilization = if stats.capacity == 0 {
+            0.0
+        } else {
+            stats.size as f64 / stats.capacity as f64
+        };
+        StatsResponse {
+            hits: stats.hits,
+            misses: stats.misses,
+            size: stats.size,
+            capacity: stats.capacity,
+            utilization,
+            near_full: utilization >= near_full_threshold,
+            gets: cache.counters.gets.load(Ordering::Relaxed),
+            sets: cache.counters.sets.load(Ordering::Relaxed),
+            deletes: cache.counters.deletes.load(Ordering::Relaxed),
+            hit_rate_1m,
+            miss_rate_1m,
+            created_at: unix_millis(cache.created_at()),
+            age_seconds: cache.age().as_secs_f64(),
+            last_modified: unix_millis(cache.last_modified()),
+            version: cache.version(),
+            ttl: ttl_settings.as_ref().map(|s| s.ttl.as_secs_f64()),
+            check_interval: ttl_settings
+                .as_ref()
+                .map(|s| s.check_interval.as_secs_f64()),
+            jitter: ttl_settings.as_ref().map(|s| s.jitter.as_secs_f64()),
+        }
+    }
+}
+
+// Change in a cache's counters between two /cache/stats/delta calls.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StatsDelta {
+    pub hits: u64,
+    pub misses: u64,
+    pub sets: u64,
+}
+
+// Per-cache counter deltas, with the token to pass as `since` next time.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StatsDeltaResponse {
+    pub token: u64,
+    pub caches: BTreeMap<String, StatsDelta>,
+}
+
+// One cache in the cache list.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CacheListEntry {
+    pub name: String,
+    pub cache_type: String,
+    pub capacity: u64,
+    // Whether entries expire on their own, so clients needn't parse `cache_type`.
+    pub is_ttl: bool,
+    // Bytes held by keys and values.
+    pub bytes: u64,
+    // Unix time in milliseconds of the cache's last write, delete or eviction.
+    pub last_modified: u64,
+}
+
+// Response for a created cache, with its effective settings.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateCacheResponse {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub cache_type: String,
+    pub capacity: u64,
+    // Unix time in milliseconds the cache was created, and seconds since.
+    pub created_at: u64,
+    pub age_seconds: f64,
+}
+
+impl CreateCacheResponse {
+    pub fn new(name: &str, cache: &CacheEntry) -> Self {
+        CreateCacheResponse {
+            name: name.to_string(),
+            cache_type: cache.config.cache_type().to_string(),
+            capacity: cache.config.max_capacity(),
+            created_at: unix_millis(cache.created_at()),
+            age_seconds: cache.age().as_secs_f64(),
+        }
+    }
+}
+
+// Response for a compaction: stale index entries dropped and the bytes they held.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CompactResponse {
+    pub removed: usize,
+    pub bytes_reclaimed: u64,
+}
+
+// Response for an integrity check of a cache's server-side tracking.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct VerifyResponse {
+    pub ok: bool,
+    pub keys: usize,
+    pub discrepancies: Vec<String>,
+}
+
+// When a key expires, as Unix time in milliseconds and seconds from now; both null
+// unless the cache is a ttl cache.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct KeyTtlResponse {
+    pub expires_at: Option<u64>,
+    pub remaining_seconds: Option<f64>,
+}
+
+// Response for a policy switch.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PolicyResponse {
+    pub old: String,
+    pub new: String,
+}
+
+// Response for a manifest reload: the caches it had to create.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ManifestResponse {
+    pub created: Vec<String>,
+}
+
+// Response for a bulk config update: the caches that were changed.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ConfigUpdateResponse {
+    pub updated: Vec<String>,
+}
+
+// Debug view of a cache's eviction order, next victim first.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct InternalResponse {
+    pub cache_type: String,
+    pub eviction_order: Vec<String>,
+}
+
+// Response naming the peer that owns a key.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RouteResponse {
+    pub peer: String,
+}
+
+// Response for liveness checks.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PingResponse {
+    pub pong: bool,
+    // Unix time in milliseconds when the request was handled.
+    pub server_time: u64,
+}
+
+// One cache in /status.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CacheStatusEntry {
+    pub name: String,
+    pub cache_type: String,
+    pub size: u64,
+    pub capacity: u64,
+    pub bytes: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+// Response for /status: server-wide totals plus brief stats for every cache.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StatusResponse {
+    pub uptime_seconds: f64,
+    pub cache_count: usize,
+    // Sums over `caches`.
+    pub bytes: u64,
+    pub hits: u64,
+    pub misses: u64,
+    pub caches: Vec<CacheStatusEntry>,
+}
+
+// Response body for every error.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ErrorResponse {
+    pub code: String,
+    pub error: String,
+}
+
+// Response for key counts.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CountResponse {
+    pub count: usize,
+}
+
+// Response for draining a cache to a peer. `skipped` lists keys whose values the peer
+// refused as too large; they stay only in the local cache. `dropped` names the config
+// settings the peer's copy was created without.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DrainResponse {
+    pub count: usize,
+    pub skipped: Vec<String>,
+    pub dropped: Vec<String>,
+}
+
+// One page of a key scan. `cursor` is null once the scan is complete.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ScanResponse {
+    pub keys: Vec<String>,
+    pub cursor: Option<String>,
+}
+
+// Values whose size is at most `le` bytes and above the previous bucket's bound. The
+// last bucket has no bound and takes everything larger.
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct HistogramBucket {
+    pub le: Option<u64>,
+    pub count: u64,
+}
+
+// Response for a histogram of a cache's value sizes.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct HistogramResponse {
+    pub buckets: Vec<HistogramBucket>,
+}
+
+// A key written after the requested time.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ChangeEntry {
+    pub key: String,
+    // Unix time in milliseconds of the write.
+    pub modified: u64,
+    // Base64-encoded value, when requested.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+}
+
+// Response listing keys written since a point in time, oldest write first.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ChangesResponse {
+    pub changes: Vec<ChangeEntry>,
+}
+
+// One line of an NDJSON cache dump; the value is base64-encoded.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DumpEntry {
+    pub key: String,
+    pub value: String,
+}
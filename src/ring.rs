@@ -0,0 +1,95 @@
+use std::collections::BTreeMap;
+
+// Points each peer gets on the ring; more points spread keys more evenly.
+const POINTS_PER_PEER: u32 = 160;
+
+// Consistent-hash ring assigning keys to peers. Adding or removing a peer only moves
+// the keys that hash to that peer's points.
+#[derive(Debug, Default)]
+pub struct HashRing {
+    points: BTreeMap<u64, String>,
+}
+
+impl HashRing {
+    pub fn new<S: AsRef<str>>(peers: &[S]) -> Self {
+        let mut points = BTreeMap::new();
+        for peer in peers {
+            let peer = peer.as_ref();
+            for i in 0..POINTS_PER_PEER {
+                points.insert(hash(&format!("{}#{}", peer, i)), peer.to_string());
+            }
+        }
+        HashRing { points }
+    }
+
+    // The peer owning `key`: the first point at or after its hash, wrapping around.
+    pub fn peer_for(&self, key: &str) -> Option<&str> {
+        let h = hash(key);
+        self.points
+            .range(h..)
+            .next()
+            .or_else(|| self.points.iter().next())
+            .map(|(_, peer)| peer.as_str())
+    }
+}
+
+// FNV-1a followed by a splitmix64 finalizer. Assignments must agree across servers and
+// releases, so this can't use std's hasher, whose algorithm isn't guaranteed.
+fn hash(s: &str) -> u64 {
+    let mut h: u64 = 0xcbf29ce484222325;
+    for byte in s.bytes() {
+        h ^= byte as u64;
+        h = h.wrapping_mul(0x100000001b3);
+    }
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d049bb133111eb);
+    h ^ (h >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys() -> Vec<String> {
+        (0..1000).map(|i| format!("key-{}", i)).collect()
+    }
+
+    #[test]
+    fn test_stable_assignment() {
+        let peers = ["http://a:8080", "http://b:8080", "http://c:8080"];
+        let ring = HashRing::new(&peers);
+        let again = HashRing::new(&peers);
+        for key in keys() {
+            let peer = ring.peer_for(&key).unwrap();
+            assert!(peers.contains(&peer));
+            assert_eq!(again.peer_for(&key), Some(peer));
+        }
+        // Every peer gets a share.
+        for peer in peers {
+            assert!(keys().iter().any(|key| ring.peer_for(key) == Some(peer)));
+        }
+        assert_eq!(HashRing::new::<&str>(&[]).peer_for("key"), None);
+    }
+
+    #[test]
+    fn test_adding_peer_moves_few_keys() {
+        let before = HashRing::new(&["http://a:8080", "http://b:8080", "http://c:8080"]);
+        let after = HashRing::new(&[
+            "http://a:8080",
+            "http://b:8080",
+            "http://c:8080",
+            "http://d:8080",
+        ]);
+        let mut moved = 0;
+        for key in keys() {
+            let (old, new) = (before.peer_for(&key), after.peer_for(&key));
+            if old != new {
+                // Keys only ever move to the new peer.
+                assert_eq!(new, Some("http://d:8080"));
+                moved += 1;
+            }
+        }
+        // Ideally a quarter of the keys move.
+        assert!(moved > 100 && moved < 400, "moved {} keys", moved);
+    }
+}
@@ -0,0 +1,148 @@
+use crate::handlers;
+use actix_web::web;
+
+// Routes for working with caches and their values.
+pub fn data(cfg: &mut web::ServiceConfig) {
+    cfg.route("/schema", web::get().to(handlers::schema))
+        .route("/ping", web::get().to(handlers::ping))
+        .route("/status", web::get().to(handlers::status))
+        .route("/route/{key}", web::get().to(handlers::route_key))
+        .route("/cache/create", web::post().to(handlers::create_cache))
+        .route("/cache/delete", web::post().to(handlers::delete_cache))
+        .route("/cache/list", web::get().to(handlers::list_caches))
+        .route("/cache/types", web::get().to(handlers::list_cache_types))
+        .route("/cache/stats", web::get().to(handlers::all_stats))
+        .route("/cache/stats/delta", web::get().to(handlers::stats_delta))
+        .route("/cache/{cache_name}/stats", web::get().to(handlers::stats))
+        .route("/cache/{cache_name}/mget", web::post().to(handlers::mget))
+        .route("/cache/{cache_name}/mset", web::post().to(handlers::mset))
+        .route(
+            "/cache/{cache_name}/delete-many",
+            web::post().to(handlers::delete_many),
+        )
+        .route(
+            "/cache/{cache_name}/dump.ndjson",
+            web::get().to(handlers::dump_ndjson),
+        )
+        .route(
+            "/cache/{cache_name}/keys/count",
+            web::get().to(handlers::count_keys),
+        )
+        .route(
+            "/cache/{cache_name}/scan",
+            web::get().to(handlers::scan_keys),
+        )
+        .route(
+            "/cache/{cache_name}/policy",
+            web::post().to(handlers::set_policy),
+        )
+        .route(
+            "/cache/{cache_name}/histogram",
+            web::get().to(handlers::histogram),
+        )
+        .route(
+            "/cache/{cache_name}/changes",
+            web::get().to(handlers::changes),
+        )
+        .route(
+            "/cache/{cache_name}/reinit",
+            web::post().to(handlers::reinit),
+        )
+        .route(
+            "/cache/{cache_name}/compact",
+            web::post().to(handlers::compact),
+        )
+        .route(
+            "/cache/{cache_name}/verify",
+            web::post().to(handlers::verify),
+        )
+        .route(
+            "/cache/{cache_name}/readonly",
+            web::post().to(handlers::set_read_only),
+        )
+        .route("/cache/{cache_name}/drain", web::post().to(handlers::drain))
+        .route(
+            "/cache/{cache_name}/{key:[^/]*}/peek",
+            web::get().to(handlers::peek_value),
+        )
+        .route(
+            "/cache/{cache_name}/{key:[^/]*}/ttl",
+            web::get().to(handlers::get_key_ttl),
+        )
+        .route(
+            "/cache/{cache_name}/{key:[^/]*}/meta",
+            web::get().to(handlers::get_meta),
+        )
+        .route(
+            "/cache/{cache_name}/{key:[^/]*}/get-or-set",
+            web::post().to(handlers::get_or_set),
+        )
+        .route(
+            "/cache/{cache_name}/{key:[^/]*}",
+            web::get().to(handlers::get_value),
+        )
+        .route(
+            "/cache/{cache_name}/{key:[^/]*}",
+            web::head().to(handlers::get_value),
+        )
+        .route(
+            "/cache/{cache_name}/{key:[^/]*}",
+            web::put().to(handlers::set_value),
+        )
+        .route(
+            "/cache/{cache_name}/{key:[^/]*}",
+            web::delete().to(handlers::delete_value),
+        );
+}
+
+// Operational routes, served on CACHERS_ADMIN_BIND when it's set.
+pub fn admin(cfg: &mut web::ServiceConfig) {
+    cfg.route("/healthz", web::get().to(handlers::ping))
+        .route("/metrics", web::get().to(handlers::metrics))
+        .route("/metrics/reset", web::post().to(handlers::reset_metrics))
+        .route("/admin/shutdown", web::post().to(handlers::shutdown))
+        .route("/admin/export", web::get().to(handlers::export))
+        .route("/admin/import", web::post().to(handlers::import))
+        .route(
+            "/admin/reload-manifest",
+            web::post().to(handlers::reload_manifest),
+        )
+        .route("/admin/maintenance", web::post().to(handlers::maintenance))
+        .route(
+            "/admin/config-update",
+            web::post().to(handlers::config_update),
+        )
+        .route(
+            "/debug/caches/{cache_name}/internal",
+            web::get().to(handlers::debug_internal),
+        );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::state::AppState;
+    use actix_web::{test, App};
+
+    #[actix_web::test]
+    async fn test_split_routes() {
+        let state = web::Data::new(AppState::new(Config::default()));
+        let data_app = test::init_service(App::new().app_data(state.clone()).configure(data)).await;
+        let admin_app = test::init_service(App::new().app_data(state).configure(admin)).await;
+
+        for uri in ["/metrics", "/healthz", "/admin/export"] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            assert_eq!(test::call_service(&data_app, req).await.status(), 404);
+        }
+        let req = test::TestRequest::get().uri("/metrics").to_request();
+        assert_eq!(test::call_service(&admin_app, req).await.status(), 200);
+        let req = test::TestRequest::get().uri("/healthz").to_request();
+        assert_eq!(test::call_service(&admin_app, req).await.status(), 200);
+
+        let req = test::TestRequest::get().uri("/cache/list").to_request();
+        assert_eq!(test::call_service(&data_app, req).await.status(), 200);
+        let req = test::TestRequest::get().uri("/cache/list").to_request();
+        assert_eq!(test::call_service(&admin_app, req).await.status(), 404);
+    }
+}
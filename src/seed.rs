@@ -0,0 +1,123 @@
+use crate::factory::build_cache;
+use crate::request_types::CreateCacheRequest;
+use crate::state::{AppState, CacheEntry};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+// Caches (and their initial entries) to create at startup.
+#[derive(Debug, Deserialize)]
+pub struct SeedFile {
+    pub caches: Vec<SeedCache>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SeedCache {
+    #[serde(flatten)]
+    pub config: CreateCacheRequest,
+    // Values are stored as the UTF-8 bytes of the given strings.
+    #[serde(default)]
+    pub entries: BTreeMap<String, String>,
+}
+
+// Load a seed file and populate `state` with its caches. The whole file is validated
+// before anything is inserted, so a bad file leaves `state` untouched.
+pub fn load_seed_file(state: &AppState, path: &Path) -> io::Result<()> {
+    let contents = std::fs::read_to_string(path)?;
+    let seed: SeedFile = serde_json::from_str(&contents).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid seed file {}: {}", path.display(), e),
+        )
+    })?;
+
+    let mut built = Vec::with_capacity(seed.caches.len());
+    for mut entry in seed.caches {
+        entry.config = state.with_defaults(entry.config);
+        let name = entry.config.name.clone();
+        if state.cache_exists(&name).is_ok() || built.iter().any(|(n, _, _)| *n == name) {
+            return Err(invalid_cache(&name, "duplicate cache name"));
+        }
+        let cache = build_cache(&entry.config).map_err(|e| invalid_cache(&name, e))?;
+        let cache = CacheEntry::new(cache, &entry.config, &state.config)
+            .map_err(|e| invalid_cache(&name, e))?;
+        built.push((name, cache, entry.entries));
+    }
+
+    for (name, cache, entries) in built {
+        for (key, value) in entries {
+            cache
+                .set(key, value.into_bytes())
+                .map_err(|e| invalid_cache(&name, e))?;
+        }
+        state
+            .insert_cache(name.clone(), cache)
+            .map_err(|e| invalid_cache(&name, e))?;
+    }
+    Ok(())
+}
+
+fn invalid_cache(name: &str, reason: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid seed cache {}: {}", name, reason),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use std::path::PathBuf;
+
+    fn write_seed(name: &str, contents: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("cachers-seed-{}-{}.json", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_load_seed_file() {
+        let path = write_seed(
+            "valid",
+            r#"{"caches": [
+                {"name": "users", "cache_type": "lru", "capacity": 10,
+                 "entries": {"alice": "1", "bob": "2"}},
+                {"name": "sessions", "cache_type": "ttl", "capacity": 10, "ttl": 300}
+            ]}"#,
+        );
+        let state = AppState::new(Config::default());
+        load_seed_file(&state, &path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let users = state.get_cache("users").unwrap();
+        assert_eq!(
+            users.get(&"alice".to_string()).unwrap().unwrap().as_slice(),
+            b"1"
+        );
+        assert_eq!(
+            users.get(&"bob".to_string()).unwrap().unwrap().as_slice(),
+            b"2"
+        );
+        assert!(state.get_cache("sessions").is_ok());
+    }
+
+    #[test]
+    fn test_load_seed_file_rejects_invalid() {
+        let path = write_seed(
+            "invalid",
+            r#"{"caches": [
+                {"name": "users", "cache_type": "lru", "capacity": 10},
+                {"name": "broken", "cache_type": "nope", "capacity": 10}
+            ]}"#,
+        );
+        let state = AppState::new(Config::default());
+        let err = load_seed_file(&state, &path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(state.get_cache("users").is_err());
+    }
+}
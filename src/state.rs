@@ -1,47 +1,1865 @@
+use crate::config::Config;
 use crate::errors::CacheError;
+use crate::keys::{remove_spill, EvictionOrder, KeyIndex};
+use crate::metrics;
+use crate::rates::RateWindow;
+use crate::request_types::{CacheOp, CreateCacheRequest};
+use crate::response_types::{StatsResponse, StatsWebhookPayload};
+use crate::ring::HashRing;
+use crate::transform::Pipeline;
+use crate::webhook;
+use actix_web::dev::ServerHandle;
+use cachers::cache::CacheStats;
 use cachers::Cache;
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Notify;
+use uuid::Uuid;
 
-pub struct AppState {
-    pub caches: Mutex<HashMap<String, Arc<dyn Cache<String, Vec<u8>> + Send + Sync>>>,
+pub type SharedCache = Arc<dyn Cache<String, Vec<u8>> + Send + Sync>;
+
+// A value with the client flags and metadata it was stored with.
+pub struct FlaggedValue {
+    pub value: Arc<Vec<u8>>,
+    pub flags: u32,
+    pub meta: Option<Arc<serde_json::Value>>,
 }
 
-impl AppState {
-    pub fn get_cache(
+// Per-cache operation counts, complementing the hit/miss stats kept by the cache itself.
+#[derive(Default)]
+pub struct OpCounters {
+    pub gets: AtomicU64,
+    pub sets: AtomicU64,
+    pub deletes: AtomicU64,
+    // Requests on this cache that took longer than CACHERS_SLOW_MS.
+    pub slow: AtomicU64,
+    // The cache's own hit/miss counts at the last reset, which can't be zeroed directly.
+    hits_offset: AtomicU64,
+    misses_offset: AtomicU64,
+    // Reads answered from the write buffer, which the cache never sees.
+    buffered_hits: AtomicU64,
+}
+
+// Where server-side timestamps, ages and idle expiry get the time from. Entries in ttl
+// caches expire on the cachers crate's own clock, which this doesn't reach.
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+pub type SharedClock = Arc<dyn Clock>;
+
+#[derive(Debug)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+// A clock that only moves when advanced, so tests needn't sleep. It starts on a whole
+// second, so millisecond timestamps taken from it are exact.
+#[cfg(test)]
+#[derive(Debug)]
+pub struct ManualClock(Mutex<SystemTime>);
+
+#[cfg(test)]
+impl ManualClock {
+    pub fn new() -> Arc<Self> {
+        Arc::new(ManualClock(Mutex::new(
+            UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        )))
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.0.lock().unwrap() += by;
+    }
+}
+
+#[cfg(test)]
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.0.lock().unwrap()
+    }
+}
+
+// When a set should go ahead, based on whether the key already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SetCondition {
+    #[default]
+    Always,
+    // Insert only (`If-None-Match: *`).
+    IfAbsent,
+    // Overwrite only (`If-Match: *`).
+    IfPresent,
+}
+
+// What gets stored alongside a value, and when the set should go ahead.
+#[derive(Debug, Clone, Default)]
+pub struct SetOptions {
+    pub flags: u32,
+    // Eviction priority; see `KeyIndex::set_priority`.
+    pub priority: i32,
+    // Opaque client JSON, returned by the meta endpoint.
+    pub meta: Option<Arc<serde_json::Value>>,
+    pub condition: SetCondition,
+    // Return the value the set replaced.
+    pub return_old: bool,
+}
+
+// A transformed value ready to be written, already in its spill file if it has one.
+struct Staged {
+    value: Vec<u8>,
+    spill: Option<PathBuf>,
+}
+
+// Queued sets beyond which a cache's write buffer is applied without waiting for its
+// write_buffer_ms window.
+const WRITE_BUFFER_MAX_KEYS: usize = 1024;
+
+// Sets queued by a cache with a write_buffer_ms. Only the latest set of each key is kept,
+// since applying the earlier ones would only be overwritten.
+#[derive(Default)]
+struct WriteBuffer {
+    // Each key's latest set, numbered so they're applied in the order they were made.
+    sets: HashMap<String, (u64, Staged, SetOptions)>,
+    next: u64,
+}
+
+impl WriteBuffer {
+    // Queue a set, returning how many keys now have one queued.
+    fn push(&mut self, key: String, staged: Staged, options: SetOptions) -> usize {
+        self.next += 1;
+        if let Some((_, replaced, _)) = self.sets.insert(key, (self.next, staged, options)) {
+            if let Some(path) = replaced.spill {
+                remove_spill(&path);
+            }
+        }
+        self.sets.len()
+    }
+
+    fn get(&self, key: &str) -> Option<(&Staged, &SetOptions)> {
+        self.sets
+            .get(key)
+            .map(|(_, staged, options)| (staged, options))
+    }
+
+    // Drop `key`'s queued set, returning whether it had one.
+    fn discard(&mut self, key: &str) -> bool {
+        match self.sets.remove(key) {
+            Some((_, staged, _)) => {
+                if let Some(path) = staged.spill {
+                    remove_spill(&path);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Every queued set, oldest first, leaving the buffer empty.
+    fn take(&mut self) -> Vec<(String, Staged, SetOptions)> {
+        let mut sets: Vec<_> = self.sets.drain().collect();
+        sets.sort_unstable_by_key(|(_, (seq, _, _))| *seq);
+        sets.into_iter()
+            .map(|(key, (_, staged, options))| (key, staged, options))
+            .collect()
+    }
+}
+
+// A named cache together with the server-side state kept alongside it.
+pub struct CacheEntry {
+    pub cache: SharedCache,
+    pub read_only: AtomicBool,
+    // Set while the cache is being migrated into the one that will replace it. Writes
+    // fail meanwhile, as they would otherwise land after their key was copied and be lost.
+    migrating: AtomicBool,
+    pub counters: OpCounters,
+    pub idle_key_ttl: Option<Duration>,
+    // The request the cache was created from, kept for exports.
+    pub config: CreateCacheRequest,
+    keys: Mutex<KeyIndex>,
+    // Unix time in milliseconds the cache was created. A policy switch keeps it.
+    created_at: AtomicU64,
+    // Unix time in milliseconds of the last write, delete or eviction.
+    last_modified: AtomicU64,
+    // Bumped on every write, delete or eviction, and announced through `changed`.
+    version: AtomicU64,
+    changed: Notify,
+    // Where values over `config.spill_threshold_bytes` are written.
+    spill_dir: PathBuf,
+    // Hit/miss totals sampled every second for the 1m rates in stats.
+    rates: Mutex<RateWindow>,
+    // Applied to values before they're stored and undone when they're read, so `cache`,
+    // shadow copies and spill files only ever hold the transformed bytes.
+    transforms: Pipeline,
+    clock: SharedClock,
+    // Sets queued for caches with a write_buffer_ms, applied together by the flusher or
+    // once WRITE_BUFFER_MAX_KEYS keys are queued. Reads and writes of a single key look
+    // here first; operations over the whole cache apply the buffer before they start.
+    // Always locked after `keys`, never before.
+    write_buffer: Option<Mutex<WriteBuffer>>,
+}
+
+impl CacheEntry {
+    // Server-wide settings come from `server`: with `shadow_values`, a copy of every value
+    // is kept alongside the cache for `peek`, and oversized values spill to `spill_dir`.
+    pub fn new(
+        cache: SharedCache,
+        config: &CreateCacheRequest,
+        server: &Config,
+    ) -> Result<Self, CacheError> {
+        let transforms = Pipeline::for_cache(config, server)?;
+        if let Some(url) = &config.stats_webhook {
+            webhook::check_url(url, &server.webhook_allowlist)?;
+        }
+        let now = unix_millis(server.clock.now());
+        let ttl_settings = config.ttl_settings();
+        let keys = KeyIndex::new(
+            EvictionOrder::for_cache_type(config.cache_type()),
+            config.max_capacity(),
+            server.shadow_values,
+        )
+        .with_clock(server.clock.clone())
+        .with_ttl(
+            ttl_settings.as_ref().map(|settings| settings.ttl),
+            ttl_settings.map_or(Duration::ZERO, |settings| settings.jitter),
+        );
+        Ok(CacheEntry {
+            cache,
+            read_only: AtomicBool::new(false),
+            migrating: AtomicBool::new(false),
+            counters: OpCounters::default(),
+            idle_key_ttl: config.idle_key_ttl.map(Duration::from_secs),
+            config: config.clone(),
+            keys: Mutex::new(keys),
+            created_at: AtomicU64::new(now),
+            last_modified: AtomicU64::new(now),
+            version: AtomicU64::new(0),
+            changed: Notify::new(),
+            spill_dir: PathBuf::from(&server.spill_dir),
+            rates: Mutex::new(RateWindow::new(RATE_WINDOW_SECS)),
+            transforms,
+            clock: server.clock.clone(),
+            write_buffer: config
+                .write_buffer_ms
+                .map(|_| Mutex::new(WriteBuffer::default())),
+        })
+    }
+
+    pub fn get(&self, key: &String) -> Result<Option<Arc<Vec<u8>>>, CacheError> {
+        Ok(self.get_with_flags(key, true)?.map(|val| val.value))
+    }
+
+    // Get a value along with the flags and metadata it was stored with. Without `count`, the lookup's
+    // hit or miss is left out of the stats.
+    pub fn get_with_flags(
         &self,
-        name: &str,
-    ) -> Result<Arc<dyn Cache<String, Vec<u8>> + Send + Sync>, CacheError> {
-        let caches = self.caches.lock().map_err(|_| CacheError::Internal)?;
-        caches.get(name).cloned().ok_or(CacheError::CacheNotFound)
+        key: &String,
+        count: bool,
+    ) -> Result<Option<FlaggedValue>, CacheError> {
+        self.counters.gets.fetch_add(1, Ordering::Relaxed);
+        let mut keys = self.lock_index();
+        if let Some((value, options)) = self.queued(key) {
+            if count {
+                self.counters.buffered_hits.fetch_add(1, Ordering::Relaxed);
+            }
+            return Ok(Some(FlaggedValue {
+                value,
+                flags: options.flags,
+                meta: options.meta,
+            }));
+        }
+        let val = match count {
+            true => self.lookup(&mut keys, key),
+            false => self.lookup_uncounted(&mut keys, key),
+        };
+        // Setting the value again restarts its TTL. Holding the keys lock keeps this from
+        // overwriting a concurrent set.
+        if let (true, Some(val)) = (self.config.sliding, &val) {
+            self.restart_ttl(&mut keys, key, val)?;
+        }
+        Ok(val.map(|value| FlaggedValue {
+            value,
+            flags: keys.flags(key).unwrap_or(0),
+            meta: keys.meta(key),
+        }))
+    }
+
+    // Look up a value on the server's behalf (e.g. for a dump) without counting it as a
+    // client get or extending a sliding TTL. The cache itself still records the hit or miss.
+    pub fn read(&self, key: &String) -> Option<Arc<Vec<u8>>> {
+        let mut keys = self.lock_index();
+        if let Some((value, _)) = self.queued(key) {
+            return Some(value);
+        }
+        self.lookup(&mut keys, key)
+    }
+
+    // The value and options of `key`'s queued set, if it has one. Callers hold the keys
+    // lock, so a flush can't apply it in between.
+    fn queued(&self, key: &str) -> Option<(Arc<Vec<u8>>, SetOptions)> {
+        let buffer = self.recover(self.write_buffer.as_ref()?, "write buffer");
+        let (staged, options) = buffer.get(key)?;
+        match self.transforms.decode(Arc::new(staged.value.clone())) {
+            Ok(value) => Some((value, options.clone())),
+            Err(_) => {
+                log::error!("failed to decode queued value for key {}", key);
+                None
+            }
+        }
+    }
+
+    fn lookup(&self, keys: &mut KeyIndex, key: &String) -> Option<Arc<Vec<u8>>> {
+        // Dropping an expired key first makes the read below a miss.
+        if keys.expired(key) {
+            keys.remove(key);
+            self.cache.remove(key);
+        }
+        let Some(val) = self.cache.get(key) else {
+            keys.remove(key);
+            return None;
+        };
+        keys.touch(key);
+        let stored = match keys.spill_path(key) {
+            None => val,
+            Some(path) => match std::fs::read(path) {
+                Ok(val) => Arc::new(val),
+                Err(e) => {
+                    log::error!("failed to read spill file {}: {}", path.display(), e);
+                    keys.remove(key);
+                    self.cache.remove(key);
+                    return None;
+                }
+            },
+        };
+        match self.transforms.decode(stored) {
+            Ok(val) => Some(val),
+            Err(_) => {
+                log::error!("failed to decode stored value for key {}", key);
+                keys.remove(key);
+                self.cache.remove(key);
+                None
+            }
+        }
+    }
+
+    // A lookup that is left out of the hit/miss stats.
+    fn lookup_uncounted(&self, keys: &mut KeyIndex, key: &String) -> Option<Arc<Vec<u8>>> {
+        // Every lookup holds the keys lock, so the cache's counts only move for this one.
+        let before = self.cache.stats();
+        let val = self.lookup(keys, key);
+        let after = self.cache.stats();
+        self.counters
+            .hits_offset
+            .fetch_add(after.hits - before.hits, Ordering::Relaxed);
+        self.counters
+            .misses_offset
+            .fetch_add(after.misses - before.misses, Ordering::Relaxed);
+        val
+    }
+
+    // Set a value again to restart its TTL. A spilled value's in-memory stand-in is empty.
+    fn restart_ttl(&self, keys: &mut KeyIndex, key: &str, val: &[u8]) -> Result<(), CacheError> {
+        let stored = match keys.spill_path(key) {
+            Some(_) => Vec::new(),
+            None => self.transforms.encode(val.to_vec())?,
+        };
+        self.cache.set(key.to_string(), stored);
+        keys.restart_ttl(key);
+        Ok(())
+    }
+
+    // Read the shadowed copy of a value without touching the cache, so LRU/MRU order,
+    // idle tracking and hit/miss stats are all left as they were. A TTL-expired value
+    // isn't returned, though it stays in the cache until a read or sweep removes it.
+    pub fn peek(&self, key: &str) -> Result<Option<Arc<Vec<u8>>>, CacheError> {
+        let keys = self.lock_keys();
+        if !keys.shadows_values() {
+            return Err(CacheError::NotShadowed);
+        }
+        if keys.expired(key) {
+            return Ok(None);
+        }
+        keys.peek(key)
+            .map(|val| self.transforms.decode(val))
+            .transpose()
+    }
+
+    // Metadata stored with `key`, null if it has none, or None if the key is absent or
+    // expired. Like `peek`, this leaves the cache, eviction order and stats alone.
+    pub fn meta(&self, key: &str) -> Option<serde_json::Value> {
+        let keys = self.lock_keys();
+        if !keys.contains(key) || keys.expired(key) {
+            return None;
+        }
+        Some(
+            keys.meta(key)
+                .map_or(serde_json::Value::Null, |meta| meta.as_ref().clone()),
+        )
     }
 
-    pub fn remove_cache(
+    // When `key` expires, or None if the cache has no TTL. Fails with KeyNotFound if the
+    // key is absent or already expired. Like `peek`, this leaves the cache and stats alone.
+    pub fn expires_at(&self, key: &str) -> Result<Option<SystemTime>, CacheError> {
+        let keys = self.lock_keys();
+        if !keys.contains(key) || keys.expired(key) {
+            return Err(CacheError::KeyNotFound);
+        }
+        Ok(keys.expires(key))
+    }
+
+    pub fn set(&self, key: String, value: Vec<u8>) -> Result<(), CacheError> {
+        self.set_if(key, value, SetOptions::default()).map(|_| ())
+    }
+
+    // Set a value and what goes with it if `options.condition` holds for the key's current
+    // presence, failing with PreconditionFailed otherwise. With `return_old`, returns the
+    // value it replaced; reading it isn't counted as a hit or miss.
+    pub fn set_if(
         &self,
-        name: &str,
-    ) -> Result<Arc<dyn Cache<String, Vec<u8>> + Send + Sync>, CacheError> {
-        let mut caches = self.caches.lock().map_err(|_| CacheError::Internal)?;
-        caches.remove(name).ok_or(CacheError::CacheNotFound)
+        key: String,
+        value: Vec<u8>,
+        options: SetOptions,
+    ) -> Result<Option<Arc<Vec<u8>>>, CacheError> {
+        let SetOptions {
+            condition,
+            return_old,
+            ..
+        } = options;
+        if let (Some(buffer), SetCondition::Always, false) =
+            (&self.write_buffer, condition, return_old)
+        {
+            let staged = self.stage(value)?;
+            let mut buffer = self.recover(buffer, "write buffer");
+            // Checked under the lock a migration takes to apply the buffer, so nothing is
+            // queued after it's been copied.
+            if let Err(e) = self.check_writable() {
+                drop(buffer);
+                if let Some(path) = staged.spill {
+                    remove_spill(&path);
+                }
+                return Err(e);
+            }
+            self.counters.sets.fetch_add(1, Ordering::Relaxed);
+            let queued = buffer.push(key, staged, options);
+            drop(buffer);
+            // Reads see the set already, so dumps and long-polls have to as well.
+            self.mark_modified();
+            if queued >= WRITE_BUFFER_MAX_KEYS {
+                self.flush();
+            }
+            return Ok(None);
+        }
+        let mut keys = self.lock_index();
+        self.check_writable()?;
+        self.counters.sets.fetch_add(1, Ordering::Relaxed);
+        let queued = self.queued(&key).map(|(value, _)| value);
+        let old = match (return_old, &queued) {
+            (true, Some(queued)) => Some(queued.clone()),
+            (true, None) => self.lookup_uncounted(&mut keys, &key),
+            (false, _) => None,
+        };
+        // A lookup (rather than the index alone) notices TTL-expired keys.
+        let exists = match condition {
+            SetCondition::Always => queued.is_some() || keys.contains(&key),
+            _ if queued.is_some() => true,
+            _ if return_old => old.is_some(),
+            _ => self.lookup(&mut keys, &key).is_some(),
+        };
+        match (condition, exists) {
+            (SetCondition::IfAbsent, true) | (SetCondition::IfPresent, false) => {
+                return Err(CacheError::PreconditionFailed);
+            }
+            _ => {}
+        }
+        // This set replaces a queued one, which mustn't be applied after it.
+        if queued.is_some() {
+            self.discard_queued(&key);
+        }
+        self.store(&mut keys, key, value, &options)?;
+        Ok(old)
     }
 
-    pub fn insert_cache(
+    // Return the current value, or store `default` and return it if the key is absent.
+    // The check and the set happen under one lock, so concurrent callers agree on the
+    // value. `before_set` runs only on a miss, before anything is stored, and can refuse
+    // the write. Returns whether the default was stored.
+    pub fn get_or_set(
         &self,
-        name: String,
-        cache: Arc<dyn Cache<String, Vec<u8>> + Send + Sync>,
+        key: String,
+        default: Vec<u8>,
+        before_set: impl FnOnce() -> Result<(), CacheError>,
+    ) -> Result<(Arc<Vec<u8>>, bool), CacheError> {
+        self.counters.gets.fetch_add(1, Ordering::Relaxed);
+        let mut keys = self.lock_index();
+        if let Some((val, _)) = self.queued(&key) {
+            self.counters.buffered_hits.fetch_add(1, Ordering::Relaxed);
+            return Ok((val, false));
+        }
+        if let Some(val) = self.lookup(&mut keys, &key) {
+            if self.config.sliding {
+                self.restart_ttl(&mut keys, &key, &val)?;
+            }
+            return Ok((val, false));
+        }
+        before_set()?;
+        self.check_writable()?;
+        self.counters.sets.fetch_add(1, Ordering::Relaxed);
+        let val = Arc::new(default);
+        self.store(&mut keys, key, val.as_ref().clone(), &SetOptions::default())?;
+        Ok((val, true))
+    }
+
+    // Set every entry or none of them. All values are staged before the first is written,
+    // under one lock, so a failure part way (e.g. a hard capacity that can't take them
+    // all) leaves the cache as it was.
+    pub fn set_all(&self, entries: Vec<(String, Vec<u8>)>) -> Result<(), CacheError> {
+        let mut keys = self.lock_keys();
+        self.check_writable()?;
+        if self.config.hard_capacity.is_some() {
+            let new: HashSet<&String> = entries
+                .iter()
+                .map(|(key, _)| key)
+                .filter(|key| !keys.contains(key))
+                .collect();
+            if keys.len() as u64 + new.len() as u64 > self.config.max_capacity() {
+                return Err(CacheError::InsufficientStorage);
+            }
+        }
+        let mut staged = Vec::with_capacity(entries.len());
+        for (key, value) in entries {
+            match self.stage(value) {
+                Ok(value) => staged.push((key, value)),
+                Err(e) => {
+                    for path in staged.into_iter().filter_map(|(_, value)| value.spill) {
+                        remove_spill(&path);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        self.counters
+            .sets
+            .fetch_add(staged.len() as u64, Ordering::Relaxed);
+        let options = SetOptions::default();
+        for (key, value) in staged {
+            self.commit(&mut keys, key, value, &options);
+        }
+        Ok(())
+    }
+
+    // Transform a value and write it through the index, evicting a key first if the
+    // cache is full.
+    fn store(
+        &self,
+        keys: &mut KeyIndex,
+        key: String,
+        value: Vec<u8>,
+        options: &SetOptions,
     ) -> Result<(), CacheError> {
-        let mut caches = self.caches.lock().map_err(|_| CacheError::Internal)?;
+        if self.config.hard_capacity.is_some()
+            && !keys.contains(&key)
+            && keys.len() as u64 >= self.config.max_capacity()
+        {
+            return Err(CacheError::InsufficientStorage);
+        }
+        let value = self.stage(value)?;
+        self.commit(keys, key, value, options);
+        Ok(())
+    }
+
+    // Do the fallible part of a write: transform the value and, if it's over the spill
+    // threshold, write it out.
+    fn stage(&self, value: Vec<u8>) -> Result<Staged, CacheError> {
+        let value = self.transforms.encode(value)?;
+        let spill = match self.config.spill_threshold_bytes {
+            Some(threshold) if value.len() as u64 > threshold => Some(self.write_spill(&value)?),
+            _ => None,
+        };
+        Ok(Staged { value, spill })
+    }
+
+    // Evicting the index's victim first keeps the cache below capacity, so it never
+    // picks one of its own and the index's priorities decide what goes.
+    fn commit(&self, keys: &mut KeyIndex, key: String, staged: Staged, options: &SetOptions) {
+        if let Some(victim) = keys.insert(&key, &staged.value, options.flags) {
+            self.cache.remove(&victim);
+        }
+        keys.set_priority(&key, options.priority);
+        keys.set_meta(&key, options.meta.clone());
+        match staged.spill {
+            Some(path) => {
+                keys.spill(&key, path);
+                self.cache.set(key, Vec::new());
+            }
+            None => self.cache.set(key, staged.value),
+        }
+        self.mark_modified();
+    }
+
+    // Apply the queued sets under one lock, returning how many there were.
+    pub fn flush(&self) -> usize {
+        let mut keys = self.lock_index();
+        self.apply_buffered(&mut keys)
+    }
+
+    // Apply queued sets in the order they were made.
+    fn apply_buffered(&self, keys: &mut KeyIndex) -> usize {
+        let Some(buffer) = &self.write_buffer else {
+            return 0;
+        };
+        let pending = self.recover(buffer, "write buffer").take();
+        let count = pending.len();
+        for (key, staged, options) in pending {
+            self.commit(keys, key, staged, &options);
+        }
+        count
+    }
+
+    // Drop `key`'s queued set, returning whether it had one. Callers hold the keys lock.
+    fn discard_queued(&self, key: &str) -> bool {
+        self.write_buffer
+            .as_ref()
+            .is_some_and(|buffer| self.recover(buffer, "write buffer").discard(key))
+    }
+
+    // Write a value too large to keep in memory to a new file in the spill directory.
+    fn write_spill(&self, value: &[u8]) -> Result<PathBuf, CacheError> {
+        let path = self.spill_dir.join(format!("{}.bin", Uuid::new_v4()));
+        std::fs::create_dir_all(&self.spill_dir)
+            .and_then(|_| std::fs::write(&path, value))
+            .map_err(|e| {
+                log::error!("failed to write spill file {}: {}", path.display(), e);
+                CacheError::Internal
+            })?;
+        Ok(path)
+    }
+
+    pub fn remove(&self, key: &String) -> Result<(), CacheError> {
+        let mut keys = self.lock_index();
+        self.check_writable()?;
+        self.counters.deletes.fetch_add(1, Ordering::Relaxed);
+        self.discard_queued(key);
+        keys.remove(key);
+        self.cache.remove(key);
+        self.mark_modified();
+        Ok(())
+    }
+
+    // Remove each of `keys` under a single lock, returning how many were present.
+    pub fn remove_many(&self, keys: &[String]) -> Result<usize, CacheError> {
+        let mut index = self.lock_index();
+        self.check_writable()?;
+        self.counters
+            .deletes
+            .fetch_add(keys.len() as u64, Ordering::Relaxed);
+        let mut removed = 0;
+        for key in keys {
+            let queued = self.discard_queued(key);
+            if index.remove(key) {
+                self.cache.remove(key);
+                removed += 1;
+            } else if queued {
+                removed += 1;
+            }
+        }
+        if removed > 0 {
+            self.mark_modified();
+        }
+        Ok(removed)
+    }
+
+    // Drop keys that haven't been accessed within `idle`, returning how many were evicted.
+    pub fn evict_idle(&self, idle: Duration) -> usize {
+        let mut keys = self.lock_keys();
+        let idle_keys = keys.idle_keys(idle);
+        for key in &idle_keys {
+            keys.remove(key);
+            self.cache.remove(key);
+        }
+        if !idle_keys.is_empty() {
+            self.mark_modified();
+        }
+        idle_keys.len()
+    }
+
+    // Evict keys beyond `soft`, returning how many were evicted.
+    pub fn trim_to(&self, soft: u64) -> usize {
+        let mut keys = self.lock_keys();
+        let trimmed = keys.trim_to(soft);
+        for key in &trimmed {
+            self.cache.remove(key);
+        }
+        if !trimmed.is_empty() {
+            self.mark_modified();
+        }
+        trimmed.len()
+    }
+
+    // Bytes held by the cache's keys and values, counting queued sets.
+    pub fn bytes(&self) -> u64 {
+        let keys = self.lock_index();
+        self.with_queued(&keys).1
+    }
+
+    // How many keys the queued sets add to the index, and the bytes it will hold once
+    // they're applied, leaving aside any evictions they cause. Reading these doesn't
+    // apply the buffer, so stats and metrics don't cut a write_buffer_ms window short.
+    fn with_queued(&self, keys: &KeyIndex) -> (u64, u64) {
+        let Some(buffer) = &self.write_buffer else {
+            return (0, keys.bytes());
+        };
+        let buffer = self.recover(buffer, "write buffer");
+        let (mut new_keys, mut bytes) = (0, keys.bytes());
+        for (key, (_, staged, _)) in &buffer.sets {
+            match keys.size(key) {
+                Some(size) => bytes -= size,
+                None => new_keys += 1,
+            }
+            bytes += (key.len() + staged.value.len()) as u64;
+        }
+        (new_keys, bytes)
+    }
+
+    // Snapshot of the tracked keys, in key order.
+    pub fn keys(&self) -> Vec<String> {
+        self.lock_keys().keys()
+    }
+
+    // Every key, next to be evicted first.
+    pub fn eviction_order(&self) -> Vec<String> {
+        self.lock_keys().eviction_order()
+    }
+
+    // Length of every stored value, as held after any transforms.
+    pub fn value_sizes(&self) -> Vec<u64> {
+        self.lock_keys().value_sizes()
+    }
+
+    // Keys written after `since`, oldest write first.
+    pub fn modified_after(&self, since: SystemTime) -> Vec<(String, SystemTime)> {
+        self.lock_keys().modified_after(since)
+    }
+
+    // Up to `limit` keys sorting after `after`, in key order.
+    pub fn keys_after(&self, after: Option<&str>, limit: usize) -> Vec<String> {
+        self.lock_keys().keys_after(after, limit)
+    }
+
+    // Number of tracked keys starting with `prefix`.
+    pub fn count_keys(&self, prefix: &str) -> usize {
+        self.lock_keys().count_prefix(prefix)
+    }
+
+    // The cache's stats, with hits and misses counted from the last reset.
+    pub fn stats(&self) -> CacheStats {
+        // Queued sets count towards the size. Holding the keys lock keeps a flush from
+        // applying them in between.
+        let keys = self.lock_index();
+        let (new_keys, _) = self.with_queued(&keys);
+        let mut stats = self.cache.stats();
+        stats.size = (stats.size + new_keys).min(stats.capacity);
+        stats.hits = stats
+            .hits
+            .saturating_sub(self.counters.hits_offset.load(Ordering::Relaxed))
+            + self.counters.buffered_hits.load(Ordering::Relaxed);
+        stats.misses = stats
+            .misses
+            .saturating_sub(self.counters.misses_offset.load(Ordering::Relaxed));
+        stats
+    }
+
+    // Drop index entries for keys the cache has already expired on its own, returning how
+    // many were dropped and the bytes they accounted for. Keys are probed oldest first so
+    // LRU/MRU caches end up in the order they started in, and the probes are left out of
+    // the hit/miss stats.
+    pub fn compact(&self) -> (usize, u64) {
+        let mut keys = self.lock_keys();
+        let before = keys.bytes();
+        let (mut hits, mut misses) = (0, 0);
+        for key in keys.keys_by_age() {
+            match self.cache.get(&key) {
+                Some(_) => hits += 1,
+                None => {
+                    keys.remove(&key);
+                    misses += 1;
+                }
+            }
+        }
+        self.counters.hits_offset.fetch_add(hits, Ordering::Relaxed);
+        self.counters
+            .misses_offset
+            .fetch_add(misses, Ordering::Relaxed);
+        (misses as usize, before - keys.bytes())
+    }
+
+    // Check the key index against itself and against the cache, returning how many keys
+    // it tracks and a description of each discrepancy. Presence probes are left out of
+    // the hit/miss stats, as for `compact`. Keys a ttl cache has expired on its own are
+    // expected to linger in the index until read, so they aren't reported.
+    pub fn verify(&self) -> (usize, Vec<String>) {
+        let keys = self.lock_keys();
+        let mut problems = keys.verify();
+        if self.config.ttl_settings().is_none() {
+            let (mut hits, mut misses) = (0, 0);
+            for key in keys.keys_by_age() {
+                match self.cache.get(&key) {
+                    Some(_) => hits += 1,
+                    None => {
+                        problems.push(format!("key {} is indexed but not in the cache", key));
+                        misses += 1;
+                    }
+                }
+            }
+            self.counters.hits_offset.fetch_add(hits, Ordering::Relaxed);
+            self.counters
+                .misses_offset
+                .fetch_add(misses, Ordering::Relaxed);
+            let size = self.cache.stats().size;
+            if size != keys.len() as u64 {
+                problems.push(format!(
+                    "cache holds {} entries but the index tracks {}",
+                    size,
+                    keys.len()
+                ));
+            }
+        }
+        (keys.len(), problems)
+    }
+
+    // Record the current hit/miss totals as the newest sample in the rate window.
+    pub fn sample_rates(&self) {
+        let stats = self.stats();
+        self.recover(&self.rates, "rate window")
+            .record(stats.hits, stats.misses);
+    }
+
+    // Hits and misses per second over the last minute.
+    pub fn rates(&self) -> (f64, f64) {
+        self.recover(&self.rates, "rate window").rates()
+    }
+
+    // Zero the operation counters and hit/miss counts, leaving the contents alone.
+    pub fn reset_counters(&self) {
+        let stats = self.cache.stats();
+        let counters = &self.counters;
+        counters.gets.store(0, Ordering::Relaxed);
+        counters.sets.store(0, Ordering::Relaxed);
+        counters.deletes.store(0, Ordering::Relaxed);
+        counters.slow.store(0, Ordering::Relaxed);
+        counters.buffered_hits.store(0, Ordering::Relaxed);
+        counters.hits_offset.store(stats.hits, Ordering::Relaxed);
+        counters
+            .misses_offset
+            .store(stats.misses, Ordering::Relaxed);
+    }
+
+    // Copy this cache's entries, oldest first, and server-side state into `target`, a new
+    // cache that is about to replace it. Hit/miss counts belong to the old cache and
+    // aren't carried over. Entries in a ttl target start a fresh TTL unless `keep_expiry`,
+    // which suits a target whose ttl and jitter are unchanged. Writes to this cache
+    // fail from here on, so the caller must swap `target` in, or `cancel_migration` if it
+    // gives up. Fails with ReadOnly if another migration got here first.
+    pub fn migrate_to(&self, target: &CacheEntry, keep_expiry: bool) -> Result<(), CacheError> {
+        if self.migrating.swap(true, Ordering::SeqCst) {
+            return Err(CacheError::ReadOnly);
+        }
+        let copied = self.copy_to(target, keep_expiry);
+        if copied.is_err() {
+            self.cancel_migration();
+        }
+        copied
+    }
+
+    // Take writes again after an abandoned `migrate_to`.
+    pub fn cancel_migration(&self) {
+        self.migrating.store(false, Ordering::SeqCst);
+    }
+
+    fn copy_to(&self, target: &CacheEntry, keep_expiry: bool) -> Result<(), CacheError> {
+        let mut keys = self.lock_keys();
+        let mut target_keys = target.lock_index();
+        for key in keys.keys_by_age() {
+            if let Some(val) = self.lookup(&mut keys, &key) {
+                let options = SetOptions {
+                    flags: keys.flags(&key).unwrap_or(0),
+                    priority: keys.priority(&key).unwrap_or(0),
+                    meta: keys.meta(&key),
+                    ..Default::default()
+                };
+                target.store(
+                    &mut target_keys,
+                    key.clone(),
+                    val.as_ref().clone(),
+                    &options,
+                )?;
+                if keep_expiry {
+                    target_keys.set_expires(&key, keys.expires(&key));
+                }
+            }
+        }
+        for (from, to) in [
+            (&self.counters.gets, &target.counters.gets),
+            (&self.counters.sets, &target.counters.sets),
+            (&self.counters.deletes, &target.counters.deletes),
+        ] {
+            to.store(from.load(Ordering::Relaxed), Ordering::Relaxed);
+        }
+        target.set_read_only(self.read_only.load(Ordering::SeqCst));
+        target
+            .created_at
+            .store(self.created_at.load(Ordering::SeqCst), Ordering::SeqCst);
+        target
+            .last_modified
+            .store(self.last_modified.load(Ordering::SeqCst), Ordering::SeqCst);
+        target.version.store(self.version(), Ordering::SeqCst);
+        Ok(())
+    }
+
+    pub fn set_read_only(&self, enabled: bool) {
+        self.read_only.store(enabled, Ordering::SeqCst);
+    }
+
+    // Fail unless the cache's `allowed_ops` permit `op`. Handlers check this for client
+    // requests; server-side reads and writes (seeding, imports) aren't restricted.
+    pub fn check_op(&self, op: CacheOp) -> Result<(), CacheError> {
+        match &self.config.allowed_ops {
+            Some(ops) if !ops.contains(&op) => Err(CacheError::OperationNotAllowed),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn created_at(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.created_at.load(Ordering::SeqCst))
+    }
+
+    // How long the cache has existed, or zero if the clock has gone backwards since.
+    pub fn age(&self) -> Duration {
+        self.clock
+            .now()
+            .duration_since(self.created_at())
+            .unwrap_or_default()
+    }
+
+    pub fn last_modified(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.last_modified.load(Ordering::SeqCst))
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version.load(Ordering::SeqCst)
+    }
+
+    // Wait until the version moves past `since`, or `timeout` elapses.
+    pub async fn wait_for_change(&self, since: u64, timeout: Duration) {
+        // Created before the check so a change in between still wakes it.
+        let changed = self.changed.notified();
+        if self.version() != since {
+            return;
+        }
+        let _ = tokio::time::timeout(timeout, changed).await;
+    }
+
+    fn mark_modified(&self) {
+        self.last_modified
+            .fetch_max(unix_millis(self.clock.now()), Ordering::SeqCst);
+        self.version.fetch_add(1, Ordering::SeqCst);
+        self.changed.notify_waiters();
+    }
+
+    // The key index with any queued sets applied, for operations over the whole cache.
+    fn lock_keys(&self) -> MutexGuard<'_, KeyIndex> {
+        let mut keys = self.lock_index();
+        self.apply_buffered(&mut keys);
+        keys
+    }
+
+    // The key index as it is, for operations on single keys that check the write buffer
+    // themselves.
+    fn lock_index(&self) -> MutexGuard<'_, KeyIndex> {
+        self.recover(&self.keys, "key index")
+    }
+
+    // `recover` for one of the cache's own locks.
+    fn recover<'a, T>(&self, lock: &'a Mutex<T>, what: &str) -> MutexGuard<'a, T> {
+        recover(lock, format_args!("{} on cache {}", what, self.config.name))
+    }
+
+    // Fail writes to the cache, including replacing it, while it's read-only or being
+    // migrated.
+    pub fn check_writable(&self) -> Result<(), CacheError> {
+        match self.read_only.load(Ordering::SeqCst) || self.migrating.load(Ordering::SeqCst) {
+            true => Err(CacheError::ReadOnly),
+            false => Ok(()),
+        }
+    }
+}
+
+// Counters of one cache at the time a /cache/stats/delta token was issued.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CounterSnapshot {
+    pub hits: u64,
+    pub misses: u64,
+    pub sets: u64,
+}
+
+// Lock a mutex, taking it over if a panicking holder poisoned it so the server keeps
+// serving rather than failing every request from then on. The poison is cleared after the
+// first recovery so it's only reported once.
+fn recover<'a, T>(lock: &'a Mutex<T>, what: fmt::Arguments) -> MutexGuard<'a, T> {
+    lock.lock().unwrap_or_else(|poisoned| {
+        log::warn!(
+            "recovering poisoned {} lock; a previous operation panicked",
+            what
+        );
+        lock.clear_poison();
+        poisoned.into_inner()
+    })
+}
+
+// Seconds of hit/miss samples behind the `_1m` rates.
+const RATE_WINDOW_SECS: usize = 60;
+
+// Snapshots kept for /cache/stats/delta tokens; older tokens expire.
+const MAX_SNAPSHOTS: usize = 64;
+
+// How often stats go to a cache's stats_webhook unless it sets an interval.
+const DEFAULT_STATS_WEBHOOK_INTERVAL_MS: u64 = 10_000;
+
+#[derive(Default)]
+struct Snapshots {
+    next_token: u64,
+    by_token: BTreeMap<u64, HashMap<String, CounterSnapshot>>,
+}
+
+pub struct AppState {
+    pub caches: Mutex<HashMap<String, Arc<CacheEntry>>>,
+    pub config: Config,
+    // Assigns keys to `config.peers` for /route.
+    pub ring: HashRing,
+    // Handles used by /admin/shutdown; only added once the servers are running, so tests
+    // never stop anything.
+    servers: Mutex<Vec<ServerHandle>>,
+    snapshots: Mutex<Snapshots>,
+    started: SystemTime,
+    // While set, client writes are refused; see `check_maintenance`.
+    maintenance: AtomicBool,
+}
+
+impl AppState {
+    pub fn new(config: Config) -> Self {
+        AppState {
+            caches: Mutex::new(HashMap::new()),
+            ring: HashRing::new(&config.peers),
+            started: config.clock.now(),
+            config,
+            servers: Mutex::new(Vec::new()),
+            snapshots: Mutex::new(Snapshots::default()),
+            maintenance: AtomicBool::new(false),
+        }
+    }
+
+    pub fn set_maintenance(&self, enabled: bool) {
+        self.maintenance.store(enabled, Ordering::SeqCst);
+    }
+
+    // Fail client writes (to values or to the set of caches) while in maintenance mode.
+    // Admin endpoints don't check it, so operators can still work on the server.
+    pub fn check_maintenance(&self) -> Result<(), CacheError> {
+        match self.maintenance.load(Ordering::SeqCst) {
+            true => Err(CacheError::Maintenance),
+            false => Ok(()),
+        }
+    }
+
+    // Time since the state was created, i.e. since the server started.
+    pub fn uptime(&self) -> Duration {
+        self.config
+            .clock
+            .now()
+            .duration_since(self.started)
+            .unwrap_or_default()
+    }
+
+    pub fn add_server_handle(&self, handle: ServerHandle) {
+        recover(&self.servers, format_args!("server handles")).push(handle);
+    }
+
+    // Gracefully stop every server in the background, so the caller can still respond.
+    // Does nothing if no server handle has been added.
+    pub fn shutdown(&self) {
+        let servers = recover(&self.servers, format_args!("server handles")).clone();
+        for handle in servers {
+            actix_web::rt::spawn(async move { handle.stop(true).await });
+        }
+    }
+
+    pub fn get_cache(&self, name: &str) -> Result<Arc<CacheEntry>, CacheError> {
+        let caches = self.lock_caches();
+        caches.get(name).cloned().ok_or(CacheError::CacheNotFound)
+    }
+
+    // Snapshot of every cache, taken under the lock so callers can work on it without holding it.
+    pub fn list_caches(&self) -> Vec<(String, Arc<CacheEntry>)> {
+        self.lock_caches()
+            .iter()
+            .map(|(name, cache)| (name.clone(), cache.clone()))
+            .collect()
+    }
+
+    // Bytes held across every cache.
+    pub fn total_bytes(&self) -> u64 {
+        self.list_caches()
+            .iter()
+            .map(|(_, cache)| cache.bytes())
+            .sum()
+    }
+
+    // Fail if reserving `bytes` more would take the server past its memory budget.
+    pub fn check_free_bytes(&self, bytes: u64) -> Result<(), CacheError> {
+        let Some(budget) = self.config.memory_budget_bytes else {
+            return Ok(());
+        };
+        match self.total_bytes().saturating_add(bytes) > budget {
+            true => Err(CacheError::InsufficientStorage),
+            false => Ok(()),
+        }
+    }
+
+    pub fn remove_cache(&self, name: &str) -> Result<Arc<CacheEntry>, CacheError> {
+        let mut caches = self.lock_caches();
+        caches.remove(name).ok_or(CacheError::CacheNotFound)
+    }
+
+    pub fn insert_cache(&self, name: String, entry: CacheEntry) -> Result<(), CacheError> {
+        let mut caches = self.lock_caches();
         if caches.contains_key(&name) {
             return Err(CacheError::CacheAlreadyExists);
         }
-        caches.insert(name, cache);
+        let entry = Arc::new(entry);
+        self.spawn_sweepers(&entry);
+        caches.insert(name, entry);
         Ok(())
     }
 
+    // Swap in `entry` for an existing cache, returning the one it replaced.
+    pub fn replace_cache(
+        &self,
+        name: &str,
+        entry: CacheEntry,
+    ) -> Result<Arc<CacheEntry>, CacheError> {
+        let mut caches = self.lock_caches();
+        let slot = caches.get_mut(name).ok_or(CacheError::CacheNotFound)?;
+        let entry = Arc::new(entry);
+        self.spawn_sweepers(&entry);
+        Ok(std::mem::replace(slot, entry))
+    }
+
+    // Swap in each of `entries` for an existing cache under one lock, returning the caches
+    // they replaced. If any is missing, none are replaced.
+    pub fn replace_caches(
+        &self,
+        entries: Vec<(String, CacheEntry)>,
+    ) -> Result<Vec<Arc<CacheEntry>>, CacheError> {
+        let mut caches = self.lock_caches();
+        if entries.iter().any(|(name, _)| !caches.contains_key(name)) {
+            return Err(CacheError::CacheNotFound);
+        }
+        Ok(entries
+            .into_iter()
+            .filter_map(|(name, entry)| {
+                let entry = Arc::new(entry);
+                self.spawn_sweepers(&entry);
+                caches.insert(name, entry)
+            })
+            .collect())
+    }
+
+    // Start the background sweeps the cache's config asks for. They stop once the cache
+    // is dropped.
+    fn spawn_sweepers(&self, entry: &Arc<CacheEntry>) {
+        if let Some(idle) = entry.idle_key_ttl {
+            // Sweeping at half the idle TTL bounds how long past its deadline an idle
+            // key can linger.
+            spawn_sweeper(Arc::downgrade(entry), idle / 2, move |cache| {
+                cache.evict_idle(idle);
+            });
+        }
+        if let Some(soft) = entry.config.soft_capacity {
+            let period = Duration::from_millis(self.config.trim_interval_ms);
+            spawn_sweeper(Arc::downgrade(entry), period, move |cache| {
+                cache.trim_to(soft);
+            });
+        }
+        if let Some(window) = entry.config.write_buffer_ms {
+            spawn_sweeper(
+                Arc::downgrade(entry),
+                Duration::from_millis(window),
+                |cache| {
+                    cache.flush();
+                },
+            );
+        }
+        if let Some(url) = &entry.config.stats_webhook {
+            self.spawn_stats_webhook(entry, url.clone());
+        }
+    }
+
+    // POST the cache's stats to `url` on its stats_webhook_interval_ms until the cache is
+    // dropped. Failed deliveries are logged and not retried; the next one is a fresh
+    // snapshot anyway.
+    fn spawn_stats_webhook(&self, entry: &Arc<CacheEntry>, url: String) {
+        // Checked when the cache was created.
+        let Ok(url) = webhook::check_url(&url, &self.config.webhook_allowlist) else {
+            return;
+        };
+        let allowlist = self.config.webhook_allowlist.clone();
+        let cache = Arc::downgrade(entry);
+        let period = Duration::from_millis(
+            entry
+                .config
+                .stats_webhook_interval_ms
+                .unwrap_or(DEFAULT_STATS_WEBHOOK_INTERVAL_MS),
+        );
+        let near_full_threshold = self.config.near_full_threshold;
+        actix_web::rt::spawn(async move {
+            let mut interval = actix_web::rt::time::interval(period);
+            loop {
+                interval.tick().await;
+                let Some(cache) = cache.upgrade() else {
+                    break;
+                };
+                let payload = StatsWebhookPayload {
+                    cache: cache.config.name.clone(),
+                    stats: StatsResponse::new(&cache, near_full_threshold),
+                };
+                drop(cache);
+                // The host is resolved and checked on every delivery, and the request goes to
+                // that address without following redirects, so neither DNS nor the receiver
+                // can point it somewhere internal.
+                let addr = match webhook::resolve(&url, &allowlist).await {
+                    Ok(addr) => addr,
+                    Err(e) => {
+                        log::warn!(
+                            "stats webhook {} for cache {} not sent: {}",
+                            url,
+                            payload.cache,
+                            e
+                        );
+                        continue;
+                    }
+                };
+                let client = reqwest::Client::builder()
+                    .resolve(url.host_str().unwrap_or_default(), addr)
+                    .redirect(reqwest::redirect::Policy::none())
+                    .build();
+                let sent = match client {
+                    Ok(client) => client.post(url.clone()).json(&payload).send().await,
+                    Err(e) => Err(e),
+                };
+                match sent {
+                    Ok(resp) if !resp.status().is_success() => log::warn!(
+                        "stats webhook {} for cache {} returned {}",
+                        url,
+                        payload.cache,
+                        resp.status()
+                    ),
+                    Err(e) => log::warn!(
+                        "stats webhook {} for cache {} failed: {}",
+                        url,
+                        payload.cache,
+                        e
+                    ),
+                    Ok(_) => {}
+                }
+            }
+        });
+    }
+
+    // Sample every cache's hit/miss totals once a second for the rolling rates in stats.
+    pub fn spawn_rate_sampler(self: &Arc<Self>) {
+        let state = Arc::downgrade(self);
+        actix_web::rt::spawn(async move {
+            let mut interval = actix_web::rt::time::interval(Duration::from_secs(1));
+            loop {
+                interval.tick().await;
+                let Some(state) = state.upgrade() else {
+                    break;
+                };
+                state.sample_rates();
+            }
+        });
+    }
+
+    // Push metrics to CACHERS_PUSHGATEWAY on an interval, for short-lived deployments that
+    // are gone before anything scrapes /metrics. Failed pushes are logged and retried on
+    // the next tick.
+    pub fn spawn_metrics_pusher(self: &Arc<Self>) {
+        let Some(gateway) = &self.config.pushgateway else {
+            return;
+        };
+        let url = metrics::push_url(gateway);
+        let period = Duration::from_millis(self.config.push_interval_ms);
+        let state = Arc::downgrade(self);
+        actix_web::rt::spawn(async move {
+            let client = reqwest::Client::new();
+            let mut interval = actix_web::rt::time::interval(period.max(Duration::from_millis(10)));
+            loop {
+                interval.tick().await;
+                let Some(state) = state.upgrade() else {
+                    break;
+                };
+                let body = metrics::format_metrics(&state.list_caches());
+                drop(state);
+                if let Err(e) = metrics::push(&client, &url, body).await {
+                    log::warn!("metrics push to {} failed: {}", url, e);
+                }
+            }
+        });
+    }
+
+    pub fn sample_rates(&self) {
+        for (_, cache) in self.list_caches() {
+            cache.sample_rates();
+        }
+    }
+
+    // Record every cache's counters under a new token, returning it with the snapshot.
+    pub fn take_snapshot(&self) -> (u64, HashMap<String, CounterSnapshot>) {
+        let current: HashMap<String, CounterSnapshot> = self
+            .list_caches()
+            .into_iter()
+            .map(|(name, cache)| {
+                let stats = cache.stats();
+                let snapshot = CounterSnapshot {
+                    hits: stats.hits,
+                    misses: stats.misses,
+                    sets: cache.counters.sets.load(Ordering::Relaxed),
+                };
+                (name, snapshot)
+            })
+            .collect();
+        let mut snapshots = recover(&self.snapshots, format_args!("stats snapshots"));
+        let token = snapshots.next_token;
+        snapshots.next_token += 1;
+        snapshots.by_token.insert(token, current.clone());
+        while snapshots.by_token.len() > MAX_SNAPSHOTS {
+            snapshots.by_token.pop_first();
+        }
+        (token, current)
+    }
+
+    // The snapshot issued under `token`, unless it has expired.
+    pub fn snapshot(&self, token: u64) -> Option<HashMap<String, CounterSnapshot>> {
+        let snapshots = recover(&self.snapshots, format_args!("stats snapshots"));
+        snapshots.by_token.get(&token).cloned()
+    }
+
+    // Fill in server defaults for settings a create request left unset.
+    pub fn with_defaults(&self, mut req: CreateCacheRequest) -> CreateCacheRequest {
+        if req.cache_type.is_none() {
+            req.cache_type = self.config.default_cache_type.clone();
+        }
+        req
+    }
+
     pub fn cache_exists(&self, name: &str) -> Result<(), CacheError> {
-        let caches = self.caches.lock().unwrap();
-        match caches.contains_key(name) {
+        match self.lock_caches().contains_key(name) {
             true => Ok(()),
             false => Err(CacheError::CacheNotFound),
         }
     }
+
+    // Holders only look up, insert or remove whole entries, so the registry is consistent
+    // even if one of them panicked.
+    fn lock_caches(&self) -> MutexGuard<'_, HashMap<String, Arc<CacheEntry>>> {
+        recover(&self.caches, format_args!("cache registry"))
+    }
+}
+
+pub fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64)
+}
+
+// Run `sweep` on the cache every `period` until the cache is dropped.
+fn spawn_sweeper<F>(cache: Weak<CacheEntry>, period: Duration, sweep: F)
+where
+    F: Fn(&CacheEntry) + 'static,
+{
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(period.max(Duration::from_millis(10)));
+        loop {
+            interval.tick().await;
+            let Some(cache) = cache.upgrade() else {
+                break;
+            };
+            sweep(&cache);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factory::build_cache;
+
+    #[actix_web::test]
+    async fn test_metrics_pusher() {
+        use actix_web::{web, App, HttpResponse, HttpServer};
+
+        // A stand-in gateway that forwards each pushed body to the test.
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+        let gateway = HttpServer::new(move || {
+            let tx = tx.clone();
+            App::new().route(
+                "/metrics/job/{job}",
+                web::put().to(move |job: web::Path<String>, body: String| {
+                    let tx = tx.clone();
+                    async move {
+                        if job.as_str() == "cachers" {
+                            let _ = tx.send(body);
+                        }
+                        HttpResponse::Ok().finish()
+                    }
+                }),
+            )
+        })
+        .workers(1)
+        .bind("127.0.0.1:0")
+        .unwrap();
+        let addr = gateway.addrs()[0];
+        let gateway = gateway.run();
+        let handle = gateway.handle();
+        actix_web::rt::spawn(gateway);
+
+        let state = Arc::new(AppState::new(Config {
+            pushgateway: Some(format!("http://{}/", addr)),
+            push_interval_ms: 50,
+            ..Config::default()
+        }));
+        let config = CreateCacheRequest {
+            name: "users".to_string(),
+            cache_type: Some("lru".to_string()),
+            capacity: 10,
+            ..Default::default()
+        };
+        let cache = CacheEntry::new(build_cache(&config).unwrap(), &config, &state.config).unwrap();
+        state.insert_cache("users".to_string(), cache).unwrap();
+        state.spawn_metrics_pusher();
+
+        let body = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(body.contains("cachers_cache_capacity{cache=\"users\"} 10\n"));
+
+        // The pusher's pooled connection would hold up a graceful stop.
+        handle.stop(false).await;
+    }
+
+    #[actix_web::test]
+    async fn test_stats_webhook() {
+        use actix_web::{web, App, HttpResponse, HttpServer};
+
+        // A stand-in endpoint that forwards each payload to the test.
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<StatsWebhookPayload>();
+        let endpoint = HttpServer::new(move || {
+            let tx = tx.clone();
+            App::new().route(
+                "/hook",
+                web::post().to(move |body: web::Json<StatsWebhookPayload>| {
+                    let tx = tx.clone();
+                    async move {
+                        let _ = tx.send(body.into_inner());
+                        HttpResponse::NoContent().finish()
+                    }
+                }),
+            )
+        })
+        .workers(1)
+        .bind("127.0.0.1:0")
+        .unwrap();
+        let addr = endpoint.addrs()[0];
+        let endpoint = endpoint.run();
+        let handle = endpoint.handle();
+        actix_web::rt::spawn(endpoint);
+
+        // Loopback targets have to be allowed explicitly.
+        let state = AppState::new(Config {
+            webhook_allowlist: vec!["127.0.0.1".to_string()],
+            ..Config::default()
+        });
+        let config = CreateCacheRequest {
+            name: "users".to_string(),
+            cache_type: Some("lru".to_string()),
+            capacity: 10,
+            stats_webhook: Some(format!("http://{}/hook", addr)),
+            stats_webhook_interval_ms: Some(1000),
+            ..Default::default()
+        };
+        let cache = CacheEntry::new(build_cache(&config).unwrap(), &config, &state.config).unwrap();
+        cache.set("a".to_string(), b"1".to_vec()).unwrap();
+        state.insert_cache("users".to_string(), cache).unwrap();
+
+        for _ in 0..2 {
+            let payload = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(payload.cache, "users");
+            assert_eq!(payload.stats.size, 1);
+            assert_eq!(payload.stats.capacity, 10);
+        }
+
+        // Deliveries stop with the cache.
+        state.remove_cache("users").unwrap();
+        actix_web::rt::time::sleep(Duration::from_millis(100)).await;
+        while rx.try_recv().is_ok() {}
+        actix_web::rt::time::sleep(Duration::from_millis(1200)).await;
+        assert!(rx.try_recv().is_err());
+
+        // The sender's pooled connection would hold up a graceful stop.
+        handle.stop(false).await;
+    }
+
+    fn buffered_cache(window_ms: u64) -> CacheEntry {
+        let config = CreateCacheRequest {
+            name: "test".to_string(),
+            cache_type: Some("lru".to_string()),
+            capacity: 10_000,
+            write_buffer_ms: Some(window_ms),
+            ..Default::default()
+        };
+        CacheEntry::new(build_cache(&config).unwrap(), &config, &Config::default()).unwrap()
+    }
+
+    #[test]
+    fn test_write_buffer() {
+        let cache = buffered_cache(60_000);
+        let applied = |cache: &CacheEntry| cache.cache.stats().size;
+
+        // Reads, get-or-sets and conditional sets from many threads are answered from the
+        // buffer, so every set still goes in as one batch.
+        std::thread::scope(|s| {
+            for t in 0..4 {
+                let cache = &cache;
+                s.spawn(move || {
+                    for i in 0..50 {
+                        let key = format!("{}-{}", t, i);
+                        cache.set(key.clone(), b"v".to_vec()).unwrap();
+                        assert_eq!(cache.get(&key).unwrap().as_deref(), Some(&b"v".to_vec()));
+                        let (val, created) =
+                            cache.get_or_set(key, b"w".to_vec(), || Ok(())).unwrap();
+                        assert_eq!((val.as_slice(), created), (&b"v"[..], false));
+                    }
+                });
+            }
+        });
+        assert_eq!(applied(&cache), 0);
+        assert_eq!(cache.flush(), 200);
+        assert_eq!(applied(&cache), 200);
+        assert_eq!(cache.flush(), 0);
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (400, 0));
+        assert_eq!(cache.counters.sets.load(Ordering::Relaxed), 200);
+
+        // A conditional set sees the queued value and replaces it, so flushing later
+        // doesn't put the queued one back.
+        cache.set("a".to_string(), b"1".to_vec()).unwrap();
+        let old = cache
+            .set_if(
+                "a".to_string(),
+                b"2".to_vec(),
+                SetOptions {
+                    condition: SetCondition::IfPresent,
+                    return_old: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(old.as_deref(), Some(&b"1".to_vec()));
+        let insert = |key: &str| {
+            cache.set_if(
+                key.to_string(),
+                b"x".to_vec(),
+                SetOptions {
+                    condition: SetCondition::IfAbsent,
+                    ..Default::default()
+                },
+            )
+        };
+        cache.set("b".to_string(), b"1".to_vec()).unwrap();
+        assert!(matches!(insert("b"), Err(CacheError::PreconditionFailed)));
+        // A delete drops the queued set.
+        cache.remove(&"b".to_string()).unwrap();
+        assert!(cache.get(&"b".to_string()).unwrap().is_none());
+        assert_eq!(applied(&cache), 201);
+        assert_eq!(cache.flush(), 0);
+        assert_eq!(
+            cache.get(&"a".to_string()).unwrap().as_deref(),
+            Some(&b"2".to_vec())
+        );
+        assert!(cache.get(&"b".to_string()).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_write_buffer_size_threshold() {
+        let cache = buffered_cache(60_000);
+        for i in 0..WRITE_BUFFER_MAX_KEYS - 1 {
+            cache.set(format!("key{}", i), b"v".to_vec()).unwrap();
+        }
+        assert_eq!(cache.cache.stats().size, 0);
+        // Sets of an already-queued key don't add to the buffer.
+        cache.set("key0".to_string(), b"w".to_vec()).unwrap();
+        assert_eq!(cache.cache.stats().size, 0);
+
+        cache
+            .set(format!("key{}", WRITE_BUFFER_MAX_KEYS), b"v".to_vec())
+            .unwrap();
+        assert_eq!(cache.cache.stats().size, WRITE_BUFFER_MAX_KEYS as u64);
+        assert_eq!(cache.flush(), 0);
+        assert_eq!(
+            cache.get(&"key0".to_string()).unwrap().as_deref(),
+            Some(&b"w".to_vec())
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_write_buffer_flushed_in_background() {
+        let state = AppState::new(Config::default());
+        state
+            .insert_cache("test".to_string(), buffered_cache(10))
+            .unwrap();
+        let cache = state.get_cache("test").unwrap();
+        cache.set("a".to_string(), b"1".to_vec()).unwrap();
+        actix_web::rt::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(cache.flush(), 0);
+        assert!(cache.cache.get(&"a".to_string()).is_some());
+    }
+
+    #[actix_web::test]
+    async fn test_write_buffer_not_applied_by_stats() {
+        let state = AppState::new(Config::default());
+        state
+            .insert_cache("test".to_string(), buffered_cache(60_000))
+            .unwrap();
+        let cache = state.get_cache("test").unwrap();
+        cache.set("a".to_string(), b"1".to_vec()).unwrap();
+        cache.flush();
+        let version = cache.version();
+        cache.set("a".to_string(), b"22".to_vec()).unwrap();
+        cache.set("b".to_string(), b"3".to_vec()).unwrap();
+        // Queued sets are announced as soon as they're made.
+        assert!(cache.version() > version);
+
+        // Everything that reads stats on a schedule: the rate sampler, the metrics pusher,
+        // stats webhooks and GET /cache/list.
+        state.sample_rates();
+        metrics::format_metrics(&state.list_caches());
+        let stats = StatsResponse::new(&cache, 0.9);
+        assert_eq!(stats.size, 2);
+        assert_eq!(cache.bytes(), 5);
+        assert_eq!(state.total_bytes(), 5);
+
+        assert_eq!(cache.cache.stats().size, 1);
+        assert_eq!(cache.flush(), 2);
+        assert_eq!(cache.bytes(), 5);
+        assert_eq!(cache.stats().size, 2);
+    }
+
+    #[test]
+    fn test_migrate_refuses_writes() {
+        let old = buffered_cache(60_000);
+        old.set("a".to_string(), b"1".to_vec()).unwrap();
+        old.set_read_only(true);
+        let new = buffered_cache(60_000);
+        old.migrate_to(&new, false).unwrap();
+        // The target keeps the source's own flag.
+        assert!(new.read_only.load(Ordering::SeqCst));
+        old.set_read_only(false);
+        new.set_read_only(false);
+
+        // Until the swap, writes to the old cache fail rather than go missing.
+        for write in [
+            old.set("b".to_string(), b"2".to_vec()),
+            old.set_all(vec![("b".to_string(), b"2".to_vec())]),
+            old.remove(&"a".to_string()),
+        ] {
+            assert!(matches!(write, Err(CacheError::ReadOnly)));
+        }
+        assert!(matches!(
+            old.migrate_to(&new, false),
+            Err(CacheError::ReadOnly)
+        ));
+        assert_eq!(
+            new.get(&"a".to_string()).unwrap().as_deref(),
+            Some(&b"1".to_vec())
+        );
+        assert_eq!(new.stats().size, 1);
+
+        old.cancel_migration();
+        old.set("b".to_string(), b"2".to_vec()).unwrap();
+        assert_eq!(old.stats().size, 2);
+    }
+
+    #[actix_web::test]
+    async fn test_replace_caches() {
+        let state = AppState::new(Config::default());
+        state
+            .insert_cache("a".to_string(), buffered_cache(60_000))
+            .unwrap();
+        state
+            .get_cache("a")
+            .unwrap()
+            .set("key".to_string(), b"1".to_vec())
+            .unwrap();
+
+        // One missing cache means none are replaced.
+        let entries = vec![
+            ("a".to_string(), buffered_cache(60_000)),
+            ("b".to_string(), buffered_cache(60_000)),
+        ];
+        assert!(matches!(
+            state.replace_caches(entries),
+            Err(CacheError::CacheNotFound)
+        ));
+        assert_eq!(state.get_cache("a").unwrap().stats().size, 1);
+
+        let old = state
+            .replace_caches(vec![("a".to_string(), buffered_cache(60_000))])
+            .unwrap();
+        assert_eq!(old[0].stats().size, 1);
+        assert_eq!(state.get_cache("a").unwrap().stats().size, 0);
+    }
+
+    #[test]
+    fn test_recovers_poisoned_key_lock() {
+        let config = CreateCacheRequest {
+            name: "test".to_string(),
+            cache_type: Some("lru".to_string()),
+            capacity: 10,
+            ..Default::default()
+        };
+        let cache = Arc::new(
+            CacheEntry::new(build_cache(&config).unwrap(), &config, &Config::default()).unwrap(),
+        );
+        cache.set("a".to_string(), b"1".to_vec()).unwrap();
+
+        let poisoner = cache.clone();
+        let result = std::thread::spawn(move || {
+            let _keys = poisoner.lock_keys();
+            panic!("operation failed while holding the key index");
+        })
+        .join();
+        assert!(result.is_err());
+        assert!(cache.keys.is_poisoned());
+
+        cache.set("b".to_string(), b"2".to_vec()).unwrap();
+        assert_eq!(
+            cache.get(&"a".to_string()).unwrap().as_deref(),
+            Some(&b"1".to_vec())
+        );
+        assert_eq!(cache.keys(), vec!["a", "b"]);
+        assert!(!cache.keys.is_poisoned());
+    }
+
+    #[test]
+    fn test_recovers_poisoned_registry_lock() {
+        let state = Arc::new(AppState::new(Config::default()));
+        let config = CreateCacheRequest {
+            name: "test".to_string(),
+            cache_type: Some("lru".to_string()),
+            capacity: 10,
+            ..Default::default()
+        };
+        let cache = CacheEntry::new(build_cache(&config).unwrap(), &config, &state.config).unwrap();
+        state.insert_cache("test".to_string(), cache).unwrap();
+
+        let poisoner = state.clone();
+        let result = std::thread::spawn(move || {
+            let _caches = poisoner.lock_caches();
+            panic!("operation failed while holding the cache registry");
+        })
+        .join();
+        assert!(result.is_err());
+        assert!(state.caches.is_poisoned());
+
+        assert!(state.get_cache("test").is_ok());
+        assert!(state.cache_exists("test").is_ok());
+        assert_eq!(state.list_caches().len(), 1);
+        assert!(!state.caches.is_poisoned());
+    }
+
+    #[test]
+    fn test_manual_clock() {
+        let clock = ManualClock::new();
+        let server = Config {
+            clock: clock.clone(),
+            ..Config::default()
+        };
+        let config = CreateCacheRequest {
+            name: "test".to_string(),
+            cache_type: Some("lru".to_string()),
+            capacity: 10,
+            idle_key_ttl: Some(60),
+            ..Default::default()
+        };
+        let state = AppState::new(server.clone());
+        let cache = CacheEntry::new(build_cache(&config).unwrap(), &config, &server).unwrap();
+        let created = cache.created_at();
+        cache.set("a".to_string(), b"1".to_vec()).unwrap();
+        cache.set("b".to_string(), b"2".to_vec()).unwrap();
+
+        clock.advance(Duration::from_secs(45));
+        cache.get(&"a".to_string()).unwrap();
+        cache.set("c".to_string(), b"3".to_vec()).unwrap();
+        assert_eq!(cache.age(), Duration::from_secs(45));
+        assert_eq!(cache.last_modified(), created + Duration::from_secs(45));
+        assert_eq!(cache.evict_idle(Duration::from_secs(60)), 0);
+
+        // "b" has now gone a full minute unused; "a" and "c" were used 45s ago.
+        clock.advance(Duration::from_secs(15));
+        assert_eq!(cache.evict_idle(Duration::from_secs(60)), 1);
+        assert_eq!(cache.keys(), vec!["a", "c"]);
+        assert_eq!(
+            cache.modified_after(created)[0].1,
+            created + Duration::from_secs(45)
+        );
+        assert_eq!(state.uptime(), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_encrypted_values() {
+        let config = CreateCacheRequest {
+            name: "secrets".to_string(),
+            cache_type: Some("lru".to_string()),
+            capacity: 10,
+            encrypted: true,
+            ..Default::default()
+        };
+        let server = Config {
+            encryption_key: Some("MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=".to_string()),
+            shadow_values: true,
+            ..Config::default()
+        };
+        let cache = CacheEntry::new(build_cache(&config).unwrap(), &config, &server).unwrap();
+        let key = "key".to_string();
+        cache.set(key.clone(), b"plaintext".to_vec()).unwrap();
+
+        let stored = cache.cache.get(&key).unwrap();
+        assert_ne!(stored.as_slice(), b"plaintext");
+        assert!(!stored.windows(9).any(|w| w == b"plaintext"));
+        assert_eq!(cache.get(&key).unwrap().unwrap().as_slice(), b"plaintext");
+        assert_eq!(cache.peek(&key).unwrap().unwrap().as_slice(), b"plaintext");
+
+        // Without the key, encrypted caches can't be created.
+        assert!(
+            CacheEntry::new(build_cache(&config).unwrap(), &config, &Config::default()).is_err()
+        );
+    }
 }
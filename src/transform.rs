@@ -0,0 +1,156 @@
+use crate::config::Config;
+use crate::errors::CacheError;
+use crate::request_types::CreateCacheRequest;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::prelude::*;
+use std::sync::Arc;
+
+// A reversible change made to values before they're stored, and undone when they're read.
+pub trait Transform: Send + Sync {
+    fn encode(&self, value: &[u8]) -> Result<Vec<u8>, CacheError>;
+    fn decode(&self, stored: &[u8]) -> Result<Vec<u8>, CacheError>;
+}
+
+// The transforms a cache's values pass through: in order on the way in, in reverse on
+// the way out. Empty for most caches, in which case values are stored untouched.
+#[derive(Default)]
+pub struct Pipeline {
+    transforms: Vec<Box<dyn Transform>>,
+}
+
+impl Pipeline {
+    pub fn for_cache(config: &CreateCacheRequest, server: &Config) -> Result<Self, CacheError> {
+        let mut transforms: Vec<Box<dyn Transform>> = Vec::new();
+        if config.encrypted {
+            let key = server.encryption_key.as_deref().ok_or_else(|| {
+                CacheError::Unprocessable(
+                    "encrypted caches need CACHERS_ENCRYPTION_KEY to be set".to_string(),
+                )
+            })?;
+            transforms.push(Box::new(AesGcm::new(key)?));
+        }
+        Ok(Pipeline { transforms })
+    }
+
+    pub fn encode(&self, value: Vec<u8>) -> Result<Vec<u8>, CacheError> {
+        self.transforms
+            .iter()
+            .try_fold(value, |value, transform| transform.encode(&value))
+    }
+
+    pub fn decode(&self, stored: Arc<Vec<u8>>) -> Result<Arc<Vec<u8>>, CacheError> {
+        if self.transforms.is_empty() {
+            return Ok(stored);
+        }
+        let value = self
+            .transforms
+            .iter()
+            .rev()
+            .try_fold(stored.as_ref().clone(), |value, transform| {
+                transform.decode(&value)
+            })?;
+        Ok(Arc::new(value))
+    }
+}
+
+const NONCE_LEN: usize = 12;
+
+// AES-256-GCM with a random nonce per value, stored ahead of the ciphertext.
+pub struct AesGcm {
+    cipher: Aes256Gcm,
+}
+
+impl AesGcm {
+    // `key` is 32 bytes, base64-encoded.
+    pub fn new(key: &str) -> Result<Self, CacheError> {
+        let key = BASE64_STANDARD
+            .decode(key.trim())
+            .ok()
+            .filter(|key| key.len() == 32)
+            .ok_or_else(|| {
+                CacheError::Unprocessable(
+                    "CACHERS_ENCRYPTION_KEY must be 32 bytes, base64-encoded".to_string(),
+                )
+            })?;
+        Ok(AesGcm {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)),
+        })
+    }
+}
+
+impl Transform for AesGcm {
+    fn encode(&self, value: &[u8]) -> Result<Vec<u8>, CacheError> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, value)
+            .map_err(|_| CacheError::Internal)?;
+        let mut stored = nonce.to_vec();
+        stored.extend(ciphertext);
+        Ok(stored)
+    }
+
+    fn decode(&self, stored: &[u8]) -> Result<Vec<u8>, CacheError> {
+        if stored.len() < NONCE_LEN {
+            return Err(CacheError::Internal);
+        }
+        let (nonce, ciphertext) = stored.split_at(NONCE_LEN);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| CacheError::Internal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encrypted() -> Pipeline {
+        let config = CreateCacheRequest {
+            encrypted: true,
+            ..Default::default()
+        };
+        let server = Config {
+            encryption_key: Some(BASE64_STANDARD.encode([7u8; 32])),
+            ..Config::default()
+        };
+        Pipeline::for_cache(&config, &server).unwrap()
+    }
+
+    #[test]
+    fn test_aes_gcm_round_trip() {
+        let pipeline = encrypted();
+        let stored = pipeline.encode(b"secret".to_vec()).unwrap();
+        assert_ne!(stored, b"secret");
+        assert_eq!(stored.len(), NONCE_LEN + b"secret".len() + 16);
+        // Nonces are random, so equal values encrypt differently.
+        assert_ne!(pipeline.encode(b"secret".to_vec()).unwrap(), stored);
+        assert_eq!(
+            pipeline
+                .decode(Arc::new(stored.clone()))
+                .unwrap()
+                .as_slice(),
+            b"secret"
+        );
+
+        let mut tampered = stored;
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(pipeline.decode(Arc::new(tampered)).is_err());
+    }
+
+    #[test]
+    fn test_encryption_key_required() {
+        let config = CreateCacheRequest {
+            encrypted: true,
+            ..Default::default()
+        };
+        assert!(Pipeline::for_cache(&config, &Config::default()).is_err());
+        let server = Config {
+            encryption_key: Some(BASE64_STANDARD.encode([7u8; 16])),
+            ..Config::default()
+        };
+        assert!(Pipeline::for_cache(&config, &server).is_err());
+        assert!(Pipeline::for_cache(&CreateCacheRequest::default(), &Config::default()).is_ok());
+    }
+}
@@ -0,0 +1,149 @@
+use crate::errors::CacheError;
+use reqwest::Url;
+use std::net::{IpAddr, SocketAddr};
+
+// Checks on where a cache's stats_webhook may point. The server calls the URL for as long
+// as the cache exists, so loopback and link-local targets (local admin ports, cloud
+// metadata services) are refused unless CACHERS_WEBHOOK_ALLOWLIST names the host. Once
+// the allowlist is set, only the hosts on it are allowed at all.
+
+// Shortest stats_webhook_interval_ms accepted.
+pub const MIN_INTERVAL_MS: u64 = 1000;
+
+// Parse a webhook URL and check its host. A host given by name is checked again each time
+// it's resolved; see `resolve`.
+pub fn check_url(url: &str, allowlist: &[String]) -> Result<Url, CacheError> {
+    let refuse = |reason: &str| {
+        Err(CacheError::Unprocessable(format!(
+            "stats_webhook {}",
+            reason
+        )))
+    };
+    let parsed = match Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => parsed,
+        _ => return refuse("must be an http or https URL"),
+    };
+    let internal = match host(&parsed) {
+        Some(host) => match host.parse::<IpAddr>() {
+            Ok(ip) => is_internal(ip),
+            Err(_) => {
+                let name = host.to_ascii_lowercase();
+                name == "localhost" || name.ends_with(".localhost")
+            }
+        },
+        None => return refuse("must name a host"),
+    };
+    match allowed(&parsed, allowlist) {
+        true => Ok(parsed),
+        false if !allowlist.is_empty() => refuse("host is not in CACHERS_WEBHOOK_ALLOWLIST"),
+        false if internal => refuse("must not target a loopback or link-local address"),
+        false => Ok(parsed),
+    }
+}
+
+// The address to deliver to `url`, refusing internal ones for hosts that aren't on the
+// allowlist. Connecting to this address rather than resolving the name again keeps the
+// name from switching to an internal address in between.
+pub async fn resolve(url: &Url, allowlist: &[String]) -> Result<SocketAddr, String> {
+    let port = url.port_or_known_default().ok_or("URL has no port")?;
+    let host = host(url).ok_or("URL has no host")?;
+    let addrs: Vec<SocketAddr> = match host.parse::<IpAddr>() {
+        Ok(ip) => vec![SocketAddr::new(ip, port)],
+        Err(_) => tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|e| e.to_string())?
+            .collect(),
+    };
+    let allowed = allowed(url, allowlist);
+    addrs
+        .into_iter()
+        .find(|addr| allowed || !is_internal(addr.ip()))
+        .ok_or_else(|| "host resolves only to loopback or link-local addresses".to_string())
+}
+
+// Whether the allowlist names the URL's host. IPv6 hosts may be listed with or without
+// brackets.
+fn allowed(url: &Url, allowlist: &[String]) -> bool {
+    let Some(host) = host(url) else {
+        return false;
+    };
+    allowlist.iter().any(|entry| {
+        entry
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .eq_ignore_ascii_case(host)
+    })
+}
+
+// The URL's host, without the brackets around an IPv6 address.
+fn host(url: &Url) -> Option<&str> {
+    url.host_str()
+        .map(|host| host.trim_start_matches('[').trim_end_matches(']'))
+}
+
+fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_loopback() || ip.is_link_local() || ip.is_unspecified(),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_internal(ip.into()),
+            // fe80::/10 is link-local.
+            None => {
+                ip.is_loopback() || ip.is_unspecified() || (ip.segments()[0] & 0xffc0) == 0xfe80
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_url() {
+        for url in [
+            "http://example.com/hook",
+            "https://203.0.113.5:8443/hook",
+            "http://10.0.0.2/hook",
+        ] {
+            assert!(check_url(url, &[]).is_ok(), "{}", url);
+        }
+        for url in [
+            "ftp://example.com/hook",
+            "not a url",
+            "http://127.0.0.1:8080/admin/shutdown",
+            "http://169.254.169.254/latest/meta-data",
+            "http://[::1]/hook",
+            "http://[::ffff:127.0.0.1]/hook",
+            "http://[fe80::1]/hook",
+            "http://0.0.0.0/hook",
+            "http://LocalHost/hook",
+            "http://app.localhost/hook",
+        ] {
+            assert!(
+                matches!(check_url(url, &[]), Err(CacheError::Unprocessable(_))),
+                "{}",
+                url
+            );
+        }
+
+        let allowlist = vec!["127.0.0.1".to_string(), "[::1]".to_string()];
+        assert!(check_url("http://127.0.0.1:9000/hook", &allowlist).is_ok());
+        assert!(check_url("http://[::1]/hook", &allowlist).is_ok());
+        assert!(check_url("http://example.com/hook", &allowlist).is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_resolve() {
+        let url = Url::parse("http://localhost:9000/hook").unwrap();
+        assert!(resolve(&url, &[]).await.is_err());
+        let addr = resolve(&url, &["localhost".to_string()]).await.unwrap();
+        assert!(addr.ip().is_loopback());
+        assert_eq!(addr.port(), 9000);
+
+        let url = Url::parse("http://203.0.113.5/hook").unwrap();
+        assert_eq!(
+            resolve(&url, &[]).await.unwrap(),
+            "203.0.113.5:80".parse().unwrap()
+        );
+    }
+}